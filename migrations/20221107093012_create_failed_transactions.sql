@@ -0,0 +1,15 @@
+-- Dead-letter table for transactions that could not be decoded
+CREATE TABLE failed_transactions(
+    id uuid PRIMARY KEY,
+    chain_id SMALLINT NOT NULL,
+    height BIGINT NOT NULL,
+    block_hash TEXT NOT NULL,
+    payload_hash TEXT NOT NULL,
+    request_key TEXT,
+    field TEXT NOT NULL,
+    error TEXT NOT NULL,
+    raw_transaction TEXT NOT NULL,
+    raw_output TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+CREATE INDEX failed_transactions_chain_id_height_idx ON failed_transactions(chain_id, height);
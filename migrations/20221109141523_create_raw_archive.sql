@@ -0,0 +1,16 @@
+-- Raw json returned by the node, gzip compressed
+CREATE TABLE raw_headers(
+    hash TEXT PRIMARY KEY,
+    chain_id SMALLINT NOT NULL,
+    height BIGINT NOT NULL,
+    payload_hash TEXT NOT NULL,
+    data BYTEA NOT NULL
+);
+CREATE INDEX raw_headers_chain_id_height_idx ON raw_headers(chain_id, height);
+
+CREATE TABLE raw_payloads(
+    payload_hash TEXT NOT NULL,
+    chain_id SMALLINT NOT NULL,
+    data BYTEA NOT NULL,
+    PRIMARY KEY (chain_id, payload_hash)
+);
@@ -0,0 +1,56 @@
+-- Tables derived from decoded block payloads
+CREATE TABLE transactions(
+    id uuid PRIMARY KEY,
+    request_key TEXT NOT NULL,
+    chain_id SMALLINT NOT NULL,
+    height BIGINT NOT NULL,
+    block_hash TEXT NOT NULL,
+    creation_time BIGINT NOT NULL,
+    sender TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    code TEXT,
+    data JSONB,
+    continuation JSONB,
+    gas BIGINT NOT NULL,
+    gas_limit BIGINT NOT NULL,
+    gas_price DOUBLE PRECISION NOT NULL,
+    ttl DOUBLE PRECISION NOT NULL,
+    status TEXT NOT NULL,
+    result JSONB,
+    logs TEXT NOT NULL
+);
+CREATE INDEX transactions_request_key_idx ON transactions(request_key);
+CREATE INDEX transactions_chain_id_height_idx ON transactions(chain_id, height);
+CREATE INDEX transactions_sender_idx ON transactions(sender);
+
+CREATE TABLE events(
+    id uuid PRIMARY KEY,
+    request_key TEXT NOT NULL,
+    chain_id SMALLINT NOT NULL,
+    height BIGINT NOT NULL,
+    block_hash TEXT NOT NULL,
+    idx INTEGER NOT NULL,
+    module TEXT NOT NULL,
+    module_hash TEXT NOT NULL,
+    name TEXT NOT NULL,
+    params JSONB NOT NULL
+);
+CREATE INDEX events_request_key_idx ON events(request_key);
+CREATE INDEX events_chain_id_height_idx ON events(chain_id, height);
+CREATE INDEX events_module_name_idx ON events(module, name);
+
+CREATE TABLE transfers(
+    id uuid PRIMARY KEY,
+    request_key TEXT NOT NULL,
+    chain_id SMALLINT NOT NULL,
+    height BIGINT NOT NULL,
+    block_hash TEXT NOT NULL,
+    idx INTEGER NOT NULL,
+    module TEXT NOT NULL,
+    sender TEXT NOT NULL,
+    receiver TEXT NOT NULL,
+    amount NUMERIC NOT NULL
+);
+CREATE INDEX transfers_chain_id_height_idx ON transfers(chain_id, height);
+CREATE INDEX transfers_sender_idx ON transfers(sender);
+CREATE INDEX transfers_receiver_idx ON transfers(receiver);
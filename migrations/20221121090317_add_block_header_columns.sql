@@ -0,0 +1,8 @@
+-- Header fields needed to query blocks, NULL for blocks indexed before
+ALTER TABLE blocks
+    ADD COLUMN hash TEXT,
+    ADD COLUMN parent TEXT,
+    ADD COLUMN payload_hash TEXT,
+    ADD COLUMN creation_time BIGINT;
+CREATE UNIQUE INDEX blocks_hash_idx ON blocks(hash);
+CREATE INDEX blocks_chain_id_height_idx ON blocks(chain_id, height);
@@ -0,0 +1,23 @@
+-- Balance of an account after every block that changed it
+CREATE TABLE balance_snapshots(
+    account TEXT NOT NULL,
+    chain_id SMALLINT NOT NULL,
+    module TEXT NOT NULL,
+    height BIGINT NOT NULL,
+    block_hash TEXT NOT NULL,
+    delta NUMERIC NOT NULL,
+    balance NUMERIC NOT NULL,
+    PRIMARY KEY (account, chain_id, module, height)
+);
+CREATE INDEX balance_snapshots_chain_id_height_idx ON balance_snapshots(chain_id, height);
+
+-- Latest balance snapshot of every account
+CREATE TABLE balances(
+    account TEXT NOT NULL,
+    chain_id SMALLINT NOT NULL,
+    module TEXT NOT NULL,
+    balance NUMERIC NOT NULL,
+    height BIGINT NOT NULL,
+    PRIMARY KEY (account, chain_id, module)
+);
+CREATE INDEX balances_chain_id_height_idx ON balances(chain_id, height);
@@ -0,0 +1,33 @@
+-- Guards set by create-account, transfer-create and rotate calls
+CREATE TABLE account_guards(
+    id uuid PRIMARY KEY,
+    account TEXT NOT NULL,
+    chain_id SMALLINT NOT NULL,
+    module TEXT NOT NULL,
+    height BIGINT NOT NULL,
+    block_hash TEXT NOT NULL,
+    request_key TEXT NOT NULL,
+    idx INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    guard JSONB
+);
+CREATE INDEX account_guards_chain_id_height_idx ON account_guards(chain_id, height);
+CREATE INDEX account_guards_account_idx ON account_guards(account, chain_id, module, height);
+
+-- Every account seen on a chain, with its latest guard
+CREATE TABLE accounts(
+    account TEXT NOT NULL,
+    chain_id SMALLINT NOT NULL,
+    module TEXT NOT NULL,
+    first_seen_height BIGINT NOT NULL,
+    first_seen_request_key TEXT NOT NULL,
+    guard JSONB,
+    guard_height BIGINT,
+    PRIMARY KEY (account, chain_id, module)
+);
+CREATE INDEX accounts_chain_id_first_seen_height_idx ON accounts(chain_id, first_seen_height);
+CREATE INDEX accounts_chain_id_guard_height_idx ON accounts(chain_id, guard_height);
+
+-- First transfer of an account
+CREATE INDEX transfers_sender_chain_id_module_height_idx ON transfers(sender, chain_id, module, height);
+CREATE INDEX transfers_receiver_chain_id_module_height_idx ON transfers(receiver, chain_id, module, height);
@@ -0,0 +1,43 @@
+-- Gas used and paid by the transactions of every block
+CREATE TABLE block_gas(
+    id uuid PRIMARY KEY,
+    chain_id SMALLINT NOT NULL,
+    height BIGINT NOT NULL,
+    block_hash TEXT NOT NULL,
+    creation_time BIGINT NOT NULL,
+    transactions INTEGER NOT NULL,
+    total_gas BIGINT NOT NULL,
+    total_fees NUMERIC NOT NULL,
+    min_gas_price DOUBLE PRECISION,
+    mean_gas_price DOUBLE PRECISION,
+    median_gas_price DOUBLE PRECISION,
+    p90_gas_price DOUBLE PRECISION,
+    max_gas_price DOUBLE PRECISION,
+    block_gas_limit BIGINT NOT NULL,
+    -- total_gas / block_gas_limit
+    fullness DOUBLE PRECISION NOT NULL
+);
+CREATE UNIQUE INDEX block_gas_block_hash_idx ON block_gas(block_hash);
+CREATE INDEX block_gas_chain_id_height_idx ON block_gas(chain_id, height);
+CREATE INDEX block_gas_chain_id_creation_time_idx ON block_gas(chain_id, creation_time);
+
+-- Gas of the blocks of a chain created in an hour
+CREATE TABLE hourly_gas(
+    chain_id SMALLINT NOT NULL,
+    hour TIMESTAMPTZ NOT NULL,
+    blocks INTEGER NOT NULL,
+    transactions INTEGER NOT NULL,
+    total_gas BIGINT NOT NULL,
+    total_fees NUMERIC NOT NULL,
+    mean_gas_price DOUBLE PRECISION,
+    median_gas_price DOUBLE PRECISION,
+    p90_gas_price DOUBLE PRECISION,
+    -- Sum of the gas limits of the blocks
+    block_gas_limit BIGINT NOT NULL,
+    -- total_gas / block_gas_limit
+    fullness DOUBLE PRECISION NOT NULL,
+    min_height BIGINT NOT NULL,
+    max_height BIGINT NOT NULL,
+    PRIMARY KEY (chain_id, hour)
+);
+CREATE INDEX hourly_gas_chain_id_max_height_idx ON hourly_gas(chain_id, max_height);
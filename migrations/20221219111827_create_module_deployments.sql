@@ -0,0 +1,20 @@
+-- Modules, interfaces and namespaces defined by transactions
+CREATE TABLE module_deployments(
+    id uuid PRIMARY KEY,
+    chain_id SMALLINT NOT NULL,
+    height BIGINT NOT NULL,
+    block_hash TEXT NOT NULL,
+    request_key TEXT NOT NULL,
+    idx INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    namespace TEXT,
+    name TEXT NOT NULL,
+    qualified_name TEXT NOT NULL,
+    hash TEXT,
+    governance TEXT,
+    governance_kind TEXT,
+    code TEXT NOT NULL
+);
+CREATE INDEX module_deployments_chain_id_height_idx ON module_deployments(chain_id, height);
+CREATE INDEX module_deployments_qualified_name_idx ON module_deployments(qualified_name, chain_id, height);
+CREATE INDEX module_deployments_hash_idx ON module_deployments(hash);
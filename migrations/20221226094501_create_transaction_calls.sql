@@ -0,0 +1,16 @@
+-- Module functions called by the code of exec transactions
+CREATE TABLE transaction_calls(
+    id uuid PRIMARY KEY,
+    request_key TEXT NOT NULL,
+    chain_id SMALLINT NOT NULL,
+    height BIGINT NOT NULL,
+    block_hash TEXT NOT NULL,
+    -- Order of the call within the transaction code
+    idx INTEGER NOT NULL,
+    module TEXT NOT NULL,
+    function TEXT NOT NULL,
+    args JSONB NOT NULL
+);
+CREATE INDEX transaction_calls_request_key_idx ON transaction_calls(request_key);
+CREATE INDEX transaction_calls_chain_id_height_idx ON transaction_calls(chain_id, height);
+CREATE INDEX transaction_calls_module_function_idx ON transaction_calls(module, function);
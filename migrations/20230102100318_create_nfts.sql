@@ -0,0 +1,52 @@
+-- Marmalade and poly-fungible-v2 token events
+CREATE TABLE nft_events(
+    id uuid PRIMARY KEY,
+    request_key TEXT NOT NULL,
+    chain_id SMALLINT NOT NULL,
+    height BIGINT NOT NULL,
+    block_hash TEXT NOT NULL,
+    idx INTEGER NOT NULL,
+    module TEXT NOT NULL,
+    token_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    sender TEXT,
+    receiver TEXT,
+    amount NUMERIC,
+    sender_balance NUMERIC,
+    receiver_balance NUMERIC,
+    sale_id TEXT,
+    params JSONB NOT NULL
+);
+CREATE INDEX nft_events_chain_id_height_idx ON nft_events(chain_id, height);
+CREATE INDEX nft_events_token_id_idx ON nft_events(token_id, chain_id, module, height);
+CREATE INDEX nft_events_sender_idx ON nft_events(sender, chain_id, module, token_id, height);
+CREATE INDEX nft_events_receiver_idx ON nft_events(receiver, chain_id, module, token_id, height);
+
+-- Tokens created by TOKEN events
+CREATE TABLE nft_tokens(
+    id uuid PRIMARY KEY,
+    chain_id SMALLINT NOT NULL,
+    module TEXT NOT NULL,
+    token_id TEXT NOT NULL,
+    height BIGINT NOT NULL,
+    block_hash TEXT NOT NULL,
+    request_key TEXT NOT NULL,
+    precision INTEGER,
+    uri TEXT,
+    policies JSONB NOT NULL
+);
+CREATE INDEX nft_tokens_chain_id_height_idx ON nft_tokens(chain_id, height);
+CREATE INDEX nft_tokens_token_id_idx ON nft_tokens(token_id, chain_id, module);
+
+-- Current holders of every token, from the balances of RECONCILE events
+CREATE TABLE nft_ownerships(
+    account TEXT NOT NULL,
+    chain_id SMALLINT NOT NULL,
+    module TEXT NOT NULL,
+    token_id TEXT NOT NULL,
+    balance NUMERIC NOT NULL,
+    height BIGINT NOT NULL,
+    PRIMARY KEY (account, chain_id, module, token_id)
+);
+CREATE INDEX nft_ownerships_token_id_idx ON nft_ownerships(token_id, chain_id, module);
+CREATE INDEX nft_ownerships_chain_id_height_idx ON nft_ownerships(chain_id, height);
@@ -0,0 +1,14 @@
+-- Chain claimed by an indexer instance until `expires_at`
+CREATE TABLE chain_leases(
+    chain_id SMALLINT PRIMARY KEY,
+    instance_id TEXT NOT NULL,
+    acquired_at TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+CREATE INDEX chain_leases_instance_id_idx ON chain_leases(instance_id);
+
+-- Last heartbeat of the indexer instances sharing the chains
+CREATE TABLE indexer_instances(
+    instance_id TEXT PRIMARY KEY,
+    heartbeat_at TIMESTAMPTZ NOT NULL
+);
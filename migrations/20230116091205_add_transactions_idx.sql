@@ -0,0 +1,3 @@
+-- Position of a transaction in its block, 0 for transactions indexed before
+ALTER TABLE transactions ADD COLUMN idx INTEGER NOT NULL DEFAULT 0;
+CREATE INDEX transactions_block_hash_idx ON transactions(block_hash, idx);
@@ -0,0 +1,295 @@
+use serde_json::{json, Value};
+
+use crate::decode::DecodedTransaction;
+use crate::pact::{parse, Expr};
+use crate::types::Continuation;
+
+/// Fungible-v2 modules whose account guards are recorded
+pub const KNOWN_FUNGIBLES: &[&str] = &[
+    "coin",
+    "arkade.token",
+    "free.anedak",
+    "free.babena",
+    "hypercent.prod-hype-coin",
+    "kaddex.kdx",
+    "kdlaunch.kdswap-token",
+    "kdlaunch.token",
+    "runonflux.flux",
+];
+
+/// Why the guard of an account was set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuardChangeKind {
+    /// `create-account` or `transfer-create`
+    Create,
+    /// `rotate`
+    Rotate,
+}
+
+impl GuardChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GuardChangeKind::Create => "create",
+            GuardChangeKind::Rotate => "rotate",
+        }
+    }
+}
+
+/// Guard given to an account by a fungible call of a transaction
+#[derive(Debug, Clone)]
+pub struct GuardChange {
+    pub account: String,
+    /// Qualified token module name, e.g. `coin`
+    pub module: String,
+    pub kind: GuardChangeKind,
+    /// `None` when the guard is not a literal of the code or the payload data
+    pub guard: Option<Value>,
+}
+
+///
+/// Accounts of the `KNOWN_FUNGIBLES` created or rotated by a successful
+/// transaction. For exec transactions only literal arguments and
+/// `read-keyset`/`read-msg` of the payload data are resolved: accounts
+/// computed by the code are not found. Continuations give the receiver of a
+/// `transfer-crosschain` on its chain.
+pub fn guard_changes(tx: &DecodedTransaction) -> Vec<GuardChange> {
+    if tx.output.result.status != "success" {
+        return vec![];
+    }
+    if let Some(cont) = &tx.cmd.payload.cont {
+        return crosschain_receiver(cont, &tx.output.continuation)
+            .into_iter()
+            .collect();
+    }
+    let Some(exec) = &tx.cmd.payload.exec else {
+        return vec![];
+    };
+    let Ok(exprs) = parse(&exec.code) else {
+        return vec![];
+    };
+
+    let mut changes = vec![];
+    for expr in &exprs {
+        expr.walk(&mut |e| {
+            let Some((name, args)) = e.as_call() else {
+                return;
+            };
+            let Some((module, function)) = name.rsplit_once('.') else {
+                return;
+            };
+            if !KNOWN_FUNGIBLES.contains(&module) {
+                return;
+            }
+            // (create-account account guard)
+            // (transfer-create sender receiver guard amount)
+            // (rotate account guard)
+            let (kind, account, guard) = match (function, args) {
+                ("create-account", [account, guard, ..]) => {
+                    (GuardChangeKind::Create, account, guard)
+                }
+                ("transfer-create", [_, receiver, guard, ..]) => {
+                    (GuardChangeKind::Create, receiver, guard)
+                }
+                ("rotate", [account, guard, ..]) => (GuardChangeKind::Rotate, account, guard),
+                _ => return,
+            };
+            if let Some(account) = account.resolve_string(&exec.data) {
+                changes.push(GuardChange {
+                    account,
+                    module: module.to_string(),
+                    kind,
+                    guard: resolve_guard(guard, &exec.data),
+                });
+            }
+        });
+    }
+    changes
+}
+
+///
+/// Receiver of the last step of
+/// `(transfer-crosschain sender receiver receiver-guard target-chain amount)`,
+/// which runs on the target chain. The guard is the one yielded by the first
+/// step, or else the argument of the call.
+fn crosschain_receiver(cont: &Continuation, pact: &Value) -> Option<GuardChange> {
+    if cont.rollback || cont.step != 1 {
+        return None;
+    }
+    let (module, function) = pact["continuation"]["def"].as_str()?.rsplit_once('.')?;
+    if function != "transfer-crosschain" || !KNOWN_FUNGIBLES.contains(&module) {
+        return None;
+    }
+    let args = &pact["continuation"]["args"];
+    let yielded = &pact["yield"]["data"];
+    let account = yielded["receiver"].as_str().or_else(|| args[1].as_str())?;
+    let guard = match (&yielded["receiver-guard"], &args[2]) {
+        (Value::Null, Value::Null) => None,
+        (Value::Null, guard) | (guard, _) => Some(guard.clone()),
+    };
+
+    Some(GuardChange {
+        account: account.to_string(),
+        module: module.to_string(),
+        kind: GuardChangeKind::Create,
+        guard,
+    })
+}
+
+/// Keyset read from the data or a keyset reference
+fn resolve_guard(expr: &Expr, data: &Value) -> Option<Value> {
+    match expr.as_call()? {
+        ("read-keyset" | "read-msg", [key]) => match &data[key.as_str()?] {
+            Value::Null => None,
+            guard => Some(guard.clone()),
+        },
+        ("keyset-ref-guard", [name]) => Some(json!({ "keysetref": name.as_str()? })),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn transaction(payload: Value, continuation: Value) -> DecodedTransaction {
+        let cmd = json!({
+            "networkId": "mainnet01",
+            "payload": payload,
+            "signers": [],
+            "meta": {
+                "creationTime": 0,
+                "ttl": 600.0,
+                "gasLimit": 1000,
+                "chainId": "1",
+                "gasPrice": 1e-8,
+                "sender": "alice"
+            },
+            "nonce": "1"
+        });
+        DecodedTransaction {
+            request_key: "rk".to_string(),
+            signed: serde_json::from_value(json!({
+                "hash": "rk",
+                "cmd": cmd.to_string(),
+                "sigs": []
+            }))
+            .unwrap(),
+            cmd: serde_json::from_value(cmd).unwrap(),
+            output: serde_json::from_value(json!({
+                "continuation": continuation,
+                "events": [],
+                "gas": 1,
+                "logs": "",
+                "metaData": null,
+                "reqKey": "rk",
+                "result": { "status": "success", "data": "Write succeeded" },
+                "txId": 1
+            }))
+            .unwrap(),
+        }
+    }
+
+    fn exec(code: &str, data: Value) -> DecodedTransaction {
+        transaction(
+            json!({ "exec": { "code": code, "data": data } }),
+            Value::Null,
+        )
+    }
+
+    fn crosschain(step: u8, yielded: Value) -> DecodedTransaction {
+        let guard = json!({ "keys": ["bob"], "pred": "keys-all" });
+        transaction(
+            json!({
+                "cont": {
+                    "proof": "proof",
+                    "pactId": "pact",
+                    "rollback": false,
+                    "step": step,
+                    "data": {}
+                }
+            }),
+            json!({
+                "pactId": "pact",
+                "step": step,
+                "stepCount": 2,
+                "continuation": {
+                    "def": "coin.transfer-crosschain",
+                    "args": ["k:alice", "k:bob", guard, "1", 1.0]
+                },
+                "yield": yielded
+            }),
+        )
+    }
+
+    #[test]
+    fn guards_are_read_from_the_payload_data() {
+        let ks = json!({ "keys": ["bob"], "pred": "keys-all" });
+        let tx = exec(
+            r#"(coin.transfer-create "k:alice" "k:bob" (read-keyset "ks") 1.0)
+               (coin.rotate "k:carol" (keyset-ref-guard "ns.admin"))"#,
+            json!({ "ks": ks }),
+        );
+
+        let changes = guard_changes(&tx);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].account, "k:bob");
+        assert_eq!(changes[0].module, "coin");
+        assert_eq!(changes[0].kind, GuardChangeKind::Create);
+        assert_eq!(changes[0].guard, Some(ks));
+        assert_eq!(changes[1].account, "k:carol");
+        assert_eq!(changes[1].kind, GuardChangeKind::Rotate);
+        assert_eq!(changes[1].guard, Some(json!({ "keysetref": "ns.admin" })));
+    }
+
+    #[test]
+    fn calls_of_unknown_modules_are_ignored() {
+        let tx = exec(
+            r#"(free.my-game.rotate "k:bob" (read-keyset "ks"))"#,
+            json!({ "ks": { "keys": ["bob"], "pred": "keys-all" } }),
+        );
+
+        assert!(guard_changes(&tx).is_empty());
+    }
+
+    #[test]
+    fn crosschain_receiver_guard_is_yielded() {
+        let yielded = json!({ "keys": ["bob2"], "pred": "keys-any" });
+        let tx = crosschain(
+            1,
+            json!({
+                "data": {
+                    "receiver": "k:bob",
+                    "receiver-guard": yielded,
+                    "amount": 1.0,
+                    "source-chain": "0"
+                },
+                "provenance": { "targetChainId": "1", "moduleHash": "hash" }
+            }),
+        );
+
+        let changes = guard_changes(&tx);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].account, "k:bob");
+        assert_eq!(changes[0].module, "coin");
+        assert_eq!(changes[0].kind, GuardChangeKind::Create);
+        assert_eq!(changes[0].guard, Some(yielded));
+    }
+
+    #[test]
+    fn crosschain_receiver_guard_falls_back_to_the_arguments() {
+        let changes = guard_changes(&crosschain(1, Value::Null));
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].account, "k:bob");
+        assert_eq!(
+            changes[0].guard,
+            Some(json!({ "keys": ["bob"], "pred": "keys-all" }))
+        );
+    }
+
+    #[test]
+    fn crosschain_first_step_changes_no_guard() {
+        assert!(guard_changes(&crosschain(0, Value::Null)).is_empty());
+    }
+}
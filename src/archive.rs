@@ -0,0 +1,210 @@
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use sqlx::PgPool;
+
+use crate::configuration::{ArchiveSettings, ArchiveStorage};
+
+#[derive(thiserror::Error, Debug)]
+pub enum ArchiveError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+///
+/// Store for the raw json returned by the node. Headers are keyed by block
+/// hash and payloads by payload hash, both are gzip compressed.
+#[derive(Debug, Clone)]
+pub enum RawArchive {
+    Database(PgPool),
+    Disk(PathBuf),
+}
+
+impl RawArchive {
+    pub fn from_settings(settings: &ArchiveSettings, pool: PgPool) -> Option<Self> {
+        if !settings.enabled {
+            return None;
+        }
+        match settings.storage {
+            ArchiveStorage::Database => Some(Self::Database(pool)),
+            ArchiveStorage::Disk => Some(Self::Disk(PathBuf::from(&settings.path))),
+        }
+    }
+
+    pub async fn store_header(
+        &self,
+        chain_id: i16,
+        height: u64,
+        hash: &str,
+        payload_hash: &str,
+        raw: &[u8],
+    ) -> Result<(), ArchiveError> {
+        let data = compress(raw)?;
+        match self {
+            Self::Database(pool) => {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO raw_headers(hash, chain_id, height, payload_hash, data)
+                    VALUES ($1, $2, $3, $4, $5)
+                    ON CONFLICT (hash)
+                    DO
+                        UPDATE SET data=EXCLUDED.data;
+                    "#,
+                    hash,
+                    chain_id,
+                    height as i64,
+                    payload_hash,
+                    data
+                )
+                .execute(pool)
+                .await?;
+            }
+            Self::Disk(path) => {
+                let dir = path.join("headers").join(chain_id.to_string());
+                tokio::fs::create_dir_all(&dir).await?;
+                tokio::fs::write(dir.join(format!("{}-{}.json.gz", height, hash)), data).await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn store_payload(
+        &self,
+        chain_id: i16,
+        payload_hash: &str,
+        raw: &[u8],
+    ) -> Result<(), ArchiveError> {
+        let data = compress(raw)?;
+        match self {
+            Self::Database(pool) => {
+                sqlx::query!(
+                    r#"
+                    INSERT INTO raw_payloads(payload_hash, chain_id, data)
+                    VALUES ($1, $2, $3)
+                    ON CONFLICT (chain_id, payload_hash)
+                    DO
+                        UPDATE SET data=EXCLUDED.data;
+                    "#,
+                    payload_hash,
+                    chain_id,
+                    data
+                )
+                .execute(pool)
+                .await?;
+            }
+            Self::Disk(path) => {
+                let dir = path.join("payloads").join(chain_id.to_string());
+                tokio::fs::create_dir_all(&dir).await?;
+                tokio::fs::write(dir.join(format!("{}.json.gz", payload_hash)), data).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Raw headers of a chain in an inclusive height range, ordered by height
+    pub async fn headers(
+        &self,
+        chain_id: i16,
+        min_height: u64,
+        max_height: u64,
+    ) -> Result<Vec<Vec<u8>>, ArchiveError> {
+        let compressed = match self {
+            Self::Database(pool) => sqlx::query!(
+                r#"
+                SELECT data FROM raw_headers
+                WHERE chain_id = $1 AND height BETWEEN $2 AND $3
+                ORDER BY height
+                "#,
+                chain_id,
+                min_height as i64,
+                max_height as i64
+            )
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .map(|r| r.data)
+            .collect(),
+            Self::Disk(path) => {
+                let dir = path.join("headers").join(chain_id.to_string());
+                let mut files = vec![];
+                let mut entries = match tokio::fs::read_dir(&dir).await {
+                    Ok(entries) => entries,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+                    Err(e) => return Err(e.into()),
+                };
+                while let Some(entry) = entries.next_entry().await? {
+                    // File names are `<height>-<hash>.json.gz`
+                    let name = entry.file_name();
+                    let height = name
+                        .to_str()
+                        .and_then(|n| n.split_once('-'))
+                        .and_then(|(h, _)| h.parse::<u64>().ok());
+                    match height {
+                        Some(h) if (min_height..=max_height).contains(&h) => {
+                            files.push((h, entry.path()))
+                        }
+                        _ => {}
+                    }
+                }
+                files.sort();
+                let mut data = vec![];
+                for (_, file) in files {
+                    data.push(tokio::fs::read(file).await?);
+                }
+                data
+            }
+        };
+        compressed.iter().map(|d| decompress(d)).collect()
+    }
+
+    /// Raw payload of a chain by payload hash
+    pub async fn payload(
+        &self,
+        chain_id: i16,
+        payload_hash: &str,
+    ) -> Result<Option<Vec<u8>>, ArchiveError> {
+        let compressed = match self {
+            Self::Database(pool) => sqlx::query!(
+                r#"
+                SELECT data FROM raw_payloads
+                WHERE chain_id = $1 AND payload_hash = $2
+                "#,
+                chain_id,
+                payload_hash
+            )
+            .fetch_optional(pool)
+            .await?
+            .map(|r| r.data),
+            Self::Disk(path) => {
+                let file = path
+                    .join("payloads")
+                    .join(chain_id.to_string())
+                    .join(format!("{}.json.gz", payload_hash));
+                match tokio::fs::read(file).await {
+                    Ok(data) => Some(data),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        };
+        compressed.map(|d| decompress(&d)).transpose()
+    }
+}
+
+fn compress(raw: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(raw)?;
+    encoder.finish()
+}
+
+fn decompress(data: &[u8]) -> Result<Vec<u8>, ArchiveError> {
+    let mut decoder = GzDecoder::new(data);
+    let mut raw = vec![];
+    decoder.read_to_end(&mut raw)?;
+    Ok(raw)
+}
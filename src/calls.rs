@@ -0,0 +1,256 @@
+use serde_json::{json, Value};
+use tracing::warn;
+
+use crate::decode::DecodedTransaction;
+use crate::pact::{parse, Expr};
+
+/// Call of a module function found in the code of a transaction
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionCall {
+    /// Qualified module name, e.g. `free.my-dex`
+    pub module: String,
+    pub function: String,
+    /// Literal arguments, `null` for the ones computed by the code
+    pub args: Vec<Value>,
+}
+
+///
+/// Calls of module functions (`module.function` or `namespace.module.function`)
+/// in the code of an exec transaction, in source order. Built-in functions
+/// and the bodies of module and interface definitions are skipped.
+pub fn function_calls(tx: &DecodedTransaction) -> Vec<FunctionCall> {
+    let Some(exec) = &tx.cmd.payload.exec else {
+        return vec![];
+    };
+    let exprs = match parse(&exec.code) {
+        Ok(exprs) => exprs,
+        Err(e) => {
+            warn!(request_key = %tx.request_key, error = %e, "Failed to parse transaction code");
+            return vec![];
+        }
+    };
+
+    let mut calls = vec![];
+    for expr in &exprs {
+        if let Some(("module" | "interface", _)) = expr.as_call() {
+            continue;
+        }
+        expr.walk(&mut |e| {
+            let Some((name, args)) = e.as_call() else {
+                return;
+            };
+            if let Some((module, function)) = name.rsplit_once('.') {
+                calls.push(FunctionCall {
+                    module: module.to_string(),
+                    function: function.to_string(),
+                    args: args.iter().map(|a| literal(a, &exec.data)).collect(),
+                });
+            }
+        });
+    }
+    calls
+}
+
+///
+/// Json value of a literal expression in the Pact encoding, with the
+/// `read-*` functions resolved from the payload data. Anything else is `null`.
+fn literal(expr: &Expr, data: &Value) -> Value {
+    match expr {
+        Expr::Str(s) | Expr::Symbol(s) => Value::from(s.as_str()),
+        Expr::Atom(a) => match a.as_str() {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            a => match a.parse::<i64>() {
+                Ok(int) => Value::from(int),
+                // Decimals keep their exact digits
+                Err(_) if a.contains('.') && a.parse::<f64>().is_ok() => json!({ "decimal": a }),
+                Err(_) => Value::Null,
+            },
+        },
+        Expr::Array(items) => items.iter().map(|i| literal(i, data)).collect(),
+        Expr::Object(items) => {
+            // key : value pairs
+            let mut object = serde_json::Map::new();
+            let mut items = items.iter();
+            while let (Some(key), Some(Expr::Atom(sep)), Some(value)) =
+                (items.next(), items.next(), items.next())
+            {
+                match (key.as_str(), sep.as_str()) {
+                    (Some(key), ":") => {
+                        object.insert(key.to_string(), literal(value, data));
+                    }
+                    _ => return Value::Null,
+                }
+            }
+            Value::Object(object)
+        }
+        Expr::List(_) => match expr.as_call() {
+            Some((
+                "read-msg" | "read-keyset" | "read-string" | "read-decimal" | "read-integer",
+                [key],
+            )) => key.as_str().map(|k| data[k].clone()).unwrap_or(Value::Null),
+            _ => Value::Null,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn exec(code: &str, data: Value) -> DecodedTransaction {
+        let cmd = json!({
+            "networkId": "mainnet01",
+            "payload": { "exec": { "code": code, "data": data } },
+            "signers": [],
+            "meta": {
+                "creationTime": 0,
+                "ttl": 600.0,
+                "gasLimit": 1000,
+                "chainId": "0",
+                "gasPrice": 1e-8,
+                "sender": "alice"
+            },
+            "nonce": "1"
+        });
+        DecodedTransaction {
+            request_key: "rk".to_string(),
+            signed: serde_json::from_value(json!({
+                "hash": "rk",
+                "cmd": cmd.to_string(),
+                "sigs": []
+            }))
+            .unwrap(),
+            cmd: serde_json::from_value(cmd).unwrap(),
+            output: serde_json::from_value(json!({
+                "continuation": null,
+                "events": [],
+                "gas": 1,
+                "logs": "",
+                "metaData": null,
+                "reqKey": "rk",
+                "result": { "status": "success", "data": "Write succeeded" },
+                "txId": 1
+            }))
+            .unwrap(),
+        }
+    }
+
+    fn call(module: &str, function: &str, args: Vec<Value>) -> FunctionCall {
+        FunctionCall {
+            module: module.to_string(),
+            function: function.to_string(),
+            args,
+        }
+    }
+
+    #[test]
+    fn literal_arguments_are_decoded() {
+        let tx = exec(
+            r#"(coin.transfer "alice" "bob\"s" 1.50) (free.dex.swap 10 true [1 2.0] {"k": 'v})"#,
+            json!({}),
+        );
+        assert_eq!(
+            function_calls(&tx),
+            vec![
+                call(
+                    "coin",
+                    "transfer",
+                    vec![
+                        json!("alice"),
+                        json!("bob\"s"),
+                        json!({ "decimal": "1.50" })
+                    ]
+                ),
+                call(
+                    "free.dex",
+                    "swap",
+                    vec![
+                        json!(10),
+                        json!(true),
+                        json!([1, { "decimal": "2.0" }]),
+                        json!({ "k": "v" })
+                    ]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn read_functions_are_resolved_from_the_data() {
+        let tx = exec(
+            r#"(coin.create-account (read-msg "account") (read-keyset "ks"))"#,
+            json!({ "account": "k:abc", "ks": { "keys": ["abc"], "pred": "keys-all" } }),
+        );
+        assert_eq!(
+            function_calls(&tx),
+            vec![call(
+                "coin",
+                "create-account",
+                vec![
+                    json!("k:abc"),
+                    json!({ "keys": ["abc"], "pred": "keys-all" })
+                ]
+            )]
+        );
+    }
+
+    #[test]
+    fn nested_calls_are_listed_parents_first_with_computed_arguments_null() {
+        let tx = exec(
+            "(coin.transfer \"a\" \"b\" (free.util.amount 1)) ; (ignored.call 1)",
+            json!({}),
+        );
+        assert_eq!(
+            function_calls(&tx),
+            vec![
+                call(
+                    "coin",
+                    "transfer",
+                    vec![json!("a"), json!("b"), Value::Null]
+                ),
+                call("free.util", "amount", vec![json!(1)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn definitions_and_builtins_are_skipped() {
+        let tx = exec(
+            r#"(namespace "free")
+            (module m GOV (defun f () (coin.details "a")))
+            (interface i (defun g:string ()))
+            (+ 1 (length "abc"))"#,
+            json!({}),
+        );
+        assert_eq!(function_calls(&tx), vec![]);
+    }
+
+    #[test]
+    fn malformed_code_has_no_calls() {
+        let tx = exec(r#"(coin.transfer "a" "b" 1.0"#, json!({}));
+        assert_eq!(function_calls(&tx), vec![]);
+    }
+
+    #[test]
+    fn decimals_keep_their_digits() {
+        let data = json!({});
+        assert_eq!(
+            literal(&atom("0.000000000000000001"), &data),
+            json!({ "decimal": "0.000000000000000001" })
+        );
+        assert_eq!(
+            literal(&atom("-3.10"), &data),
+            json!({ "decimal": "-3.10" })
+        );
+        assert_eq!(literal(&atom("-3"), &data), json!(-3));
+        assert_eq!(literal(&atom("1.2.3"), &data), Value::Null);
+        assert_eq!(literal(&atom("some-var"), &data), Value::Null);
+    }
+
+    fn atom(a: &str) -> Expr {
+        Expr::Atom(a.to_string())
+    }
+}
@@ -0,0 +1,229 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::{Args, Parser, Subcommand};
+use tracing::info;
+
+use crate::configuration::{LogFormat, Settings};
+use crate::export::{export, ExportTable};
+use crate::startup::{
+    backfill, get_connection_pool, migrate, reindex, rollback, status, verify_balances, Application,
+};
+
+/// Index the blocks of a chainweb node into Postgres and serve them
+#[derive(Parser, Debug)]
+#[command(version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub overrides: Overrides,
+}
+
+/// Flags taking precedence over the configuration files
+#[derive(Args, Debug)]
+pub struct Overrides {
+    /// Root url of the chainweb node
+    #[arg(long, global = true)]
+    pub node: Option<String>,
+    /// Blocks fetched per request
+    #[arg(long, global = true)]
+    pub limit: Option<u64>,
+    /// Chains indexed by `run`, e.g. `--chains 0,3`
+    #[arg(long, global = true, value_delimiter = ',')]
+    pub chains: Option<Vec<i16>>,
+    /// Keep the raw json of headers and payloads
+    #[arg(long, global = true)]
+    pub archive: bool,
+    /// Output format of the logs
+    #[arg(long, global = true, value_enum)]
+    pub log_format: Option<LogFormat>,
+    /// OTLP gRPC endpoint spans are exported to
+    #[arg(long, global = true)]
+    pub otlp_endpoint: Option<String>,
+}
+
+impl Overrides {
+    pub fn apply(&self, settings: &mut Settings) {
+        if let Some(node) = &self.node {
+            settings.application.host = node.clone();
+        }
+        if let Some(limit) = self.limit {
+            settings.application.limit = limit;
+        }
+        if let Some(chains) = &self.chains {
+            settings.application.chains = Some(chains.clone());
+        }
+        if self.archive {
+            settings.archive.enabled = true;
+        }
+        if let Some(format) = &self.log_format {
+            settings.telemetry.format = format.clone();
+        }
+        if let Some(endpoint) = &self.otlp_endpoint {
+            settings.telemetry.otlp_endpoint = Some(endpoint.clone());
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Index every chain and serve the api, the default command
+    Run {
+        #[arg(long)]
+        api_port: Option<u16>,
+        /// Only index, without the api server
+        #[arg(long)]
+        no_api: bool,
+        /// Exit once every chain reaches its end height, or the cut at startup
+        #[arg(long)]
+        finite: bool,
+    },
+    /// Apply the pending database migrations
+    Migrate,
+    /// Index a height range of a chain from the node and exit
+    Backfill(HeightRange),
+    /// Show the indexed height of every chain against the node cut
+    Status {
+        #[arg(long)]
+        json: bool,
+    },
+    /// Compare indexed KDA balances with `coin.details` on the node
+    #[command(alias = "verify-balances")]
+    Verify {
+        #[arg(long)]
+        chain: i16,
+        /// Accounts to check, the largest indexed balances by default
+        accounts: Vec<String>,
+    },
+    /// Rebuild the derived tables of a height range from the raw archive
+    Reindex(HeightRange),
+    /// Remove the blocks of a chain above a height, to index them again
+    Rollback {
+        #[arg(long)]
+        chain: i16,
+        /// Highest height kept
+        #[arg(long)]
+        to_height: u64,
+    },
+    /// Write the rows of a table as JSON lines
+    Export {
+        #[command(flatten)]
+        range: HeightRange,
+        #[arg(long, value_enum)]
+        table: ExportTable,
+        /// File written instead of stdout
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+}
+
+/// Inclusive height range of a chain
+#[derive(Args, Debug)]
+pub struct HeightRange {
+    #[arg(long)]
+    pub chain: i16,
+    /// Lowest height of the range
+    #[arg(long)]
+    pub from: u64,
+    /// Highest height of the range, included
+    #[arg(long)]
+    pub to: u64,
+}
+
+impl Cli {
+    /// Run the command with the overrides already applied to `configuration`
+    pub async fn run(self, mut configuration: Settings) -> Result<(), anyhow::Error> {
+        let command = self.command.unwrap_or(Command::Run {
+            api_port: None,
+            no_api: false,
+            finite: false,
+        });
+        match command {
+            Command::Run {
+                api_port,
+                no_api,
+                finite,
+            } => {
+                if let Some(port) = api_port {
+                    configuration.api.port = port;
+                }
+                if no_api {
+                    configuration.api.enabled = false;
+                }
+                if finite {
+                    configuration.application.finite = true;
+                }
+                configuration.validate()?;
+                let application = Application::build(configuration).await?;
+                application.run_indexers().await?;
+            }
+            Command::Migrate => migrate(configuration).await?,
+            Command::Backfill(range) => {
+                backfill(configuration, range.chain, range.from, range.to, &[]).await?
+            }
+            Command::Status { json } => {
+                let chains = status(configuration).await?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&chains)?);
+                } else {
+                    println!(
+                        "{:>5} {:>12} {:>12} {:>8}",
+                        "chain", "indexed", "node", "lag"
+                    );
+                    for chain in chains {
+                        let indexed = chain
+                            .indexed_height
+                            .map(|h| h.to_string())
+                            .unwrap_or_else(|| "-".to_string());
+                        println!(
+                            "{:>5} {:>12} {:>12} {:>8}",
+                            chain.chain_id, indexed, chain.node_height, chain.lag
+                        );
+                    }
+                }
+            }
+            Command::Verify { chain, accounts } => {
+                let mismatches = verify_balances(configuration, chain, accounts).await?;
+                if mismatches > 0 {
+                    anyhow::bail!("{} balances differ from the node", mismatches);
+                }
+            }
+            Command::Reindex(range) => {
+                reindex(configuration, range.chain, range.from, range.to, &[]).await?
+            }
+            Command::Rollback { chain, to_height } => {
+                rollback(configuration, chain, to_height, &[]).await?
+            }
+            Command::Export {
+                range,
+                table,
+                output,
+            } => {
+                let mut writer: Box<dyn Write> = match &output {
+                    Some(path) => {
+                        Box::new(BufWriter::new(File::create(path).with_context(|| {
+                            format!("Failed to create {}", path.display())
+                        })?))
+                    }
+                    None => Box::new(BufWriter::new(io::stdout().lock())),
+                };
+                let db_pool = get_connection_pool(&configuration.database);
+                let rows = export(
+                    &db_pool,
+                    table,
+                    range.chain,
+                    range.from as i64,
+                    range.to as i64,
+                    &mut writer,
+                )
+                .await?;
+                info!(table = table.as_str(), rows, "Exported");
+            }
+        }
+
+        Ok(())
+    }
+}
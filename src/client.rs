@@ -0,0 +1,454 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use backoff::ExponentialBackoff;
+use bytes::Bytes;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{IntoUrl, RequestBuilder, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use tokio::sync::Semaphore;
+
+use crate::configuration::{BackoffSettings, HttpSettings};
+
+///
+/// HTTP client of the chainweb node shared by every indexer. Requests wait
+/// for a free slot among `max_concurrent_requests` and for a token of the
+/// rate limit before being sent.
+#[derive(Debug, Clone)]
+pub struct NodeClient {
+    client: reqwest::Client,
+    slots: Arc<Semaphore>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    backoff: BackoffSettings,
+}
+
+/// Successful response of the node
+#[derive(Debug)]
+pub struct NodeResponse {
+    pub url: Url,
+    pub status: StatusCode,
+    pub body: Bytes,
+}
+
+impl NodeResponse {
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, NodeError> {
+        serde_json::from_slice(&self.body).map_err(|error| NodeError::Decode {
+            url: self.url.to_string(),
+            body: excerpt(&self.body),
+            error,
+        })
+    }
+}
+
+/// Longest part of a response body kept in an error
+const BODY_EXCERPT_LENGTH: usize = 512;
+
+///
+/// Failed request to the node. Timeouts, connection errors, 408, 429 and
+/// 5xx responses are transient, other responses and bodies that cannot be
+/// decoded are permanent and retrying them does not help.
+#[derive(thiserror::Error, Debug)]
+pub enum NodeError {
+    #[error("Request to {url} failed: {error}")]
+    Request { url: String, error: reqwest::Error },
+    #[error("{url} answered {status}: {body}")]
+    Status {
+        url: String,
+        status: StatusCode,
+        body: String,
+        /// Delay asked by `Retry-After`
+        retry_after: Option<Duration>,
+    },
+    #[error("Failed to decode the response of {url}: {error}, body: {body}")]
+    Decode {
+        url: String,
+        body: String,
+        error: serde_json::Error,
+    },
+}
+
+impl NodeError {
+    pub fn is_transient(&self) -> bool {
+        match self {
+            NodeError::Request { error, .. } => !error.is_builder(),
+            NodeError::Status { status, .. } => {
+                status.is_server_error()
+                    || *status == StatusCode::TOO_MANY_REQUESTS
+                    || *status == StatusCode::REQUEST_TIMEOUT
+            }
+            NodeError::Decode { .. } => false,
+        }
+    }
+
+    ///
+    /// Error of a retried operation, waiting for `Retry-After` when set. The
+    /// backoff policy is not consulted for these waits, so they are capped
+    /// by its longest interval here and the operation is given up when the
+    /// wait would end after the time it may take.
+    pub fn into_backoff(self, budget: &RetryBudget) -> backoff::Error<NodeError> {
+        if !self.is_transient() {
+            return backoff::Error::Permanent(self);
+        }
+        let retry_after = match &self {
+            NodeError::Status { retry_after, .. } => {
+                retry_after.map(|r| r.min(budget.max_interval))
+            }
+            _ => None,
+        };
+        if let (Some(wait), Some(max_elapsed)) = (retry_after, budget.max_elapsed) {
+            if budget.started.elapsed() + wait > max_elapsed {
+                return backoff::Error::Permanent(self);
+            }
+        }
+        backoff::Error::Transient {
+            err: self,
+            retry_after,
+        }
+    }
+}
+
+/// Limits of the waits asked by the node for a retried operation
+#[derive(Debug, Clone, Copy)]
+pub struct RetryBudget {
+    started: Instant,
+    max_interval: Duration,
+    max_elapsed: Option<Duration>,
+}
+
+/// Whether an error was caused by a permanent failure of the node
+pub fn is_permanent(e: &anyhow::Error) -> bool {
+    e.chain()
+        .any(|cause| matches!(cause.downcast_ref::<NodeError>(), Some(e) if !e.is_transient()))
+}
+
+impl NodeClient {
+    pub fn new(http: &HttpSettings, backoff: &BackoffSettings) -> Result<Self, reqwest::Error> {
+        let client = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(http.connect_timeout_seconds))
+            .timeout(Duration::from_secs(http.request_timeout_seconds))
+            .build()?;
+        let rate_limiter = http
+            .requests_per_second
+            .map(|rate| Arc::new(RateLimiter::new(rate, http.burst as f64)));
+
+        Ok(Self {
+            client,
+            slots: Arc::new(Semaphore::new(http.max_concurrent_requests as usize)),
+            rate_limiter,
+            backoff: backoff.clone(),
+        })
+    }
+
+    pub fn get(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.client.get(url)
+    }
+
+    pub fn post(&self, url: impl IntoUrl) -> RequestBuilder {
+        self.client.post(url)
+    }
+
+    ///
+    /// Send a request once the limits allow it and read its body, failing
+    /// unless the status is a success. The slot is held until the body is
+    /// read.
+    pub async fn send(&self, request: RequestBuilder) -> Result<NodeResponse, NodeError> {
+        let request = request.build().map_err(|error| NodeError::Request {
+            url: error.url().map(Url::to_string).unwrap_or_default(),
+            error,
+        })?;
+        let url = request.url().clone();
+        let request_error = |error| NodeError::Request {
+            url: url.to_string(),
+            error,
+        };
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.acquire().await;
+        }
+        let _slot = self
+            .slots
+            .acquire()
+            .await
+            .expect("The request semaphore is never closed");
+        let resp = self.client.execute(request).await.map_err(request_error)?;
+        let status = resp.status();
+        let retry_after = retry_after(resp.headers());
+        let body = resp.bytes().await.map_err(request_error)?;
+        if !status.is_success() {
+            return Err(NodeError::Status {
+                url: url.to_string(),
+                status,
+                body: excerpt(&body),
+                retry_after,
+            });
+        }
+
+        Ok(NodeResponse { url, status, body })
+    }
+
+    /// Retry policy of the requests to the node
+    pub fn backoff(&self) -> ExponentialBackoff {
+        let initial_interval = Duration::from_millis(self.backoff.initial_interval_ms);
+        ExponentialBackoff {
+            current_interval: initial_interval,
+            initial_interval,
+            max_interval: Duration::from_secs(self.backoff.max_interval_seconds),
+            multiplier: self.backoff.multiplier,
+            max_elapsed_time: self.backoff.max_elapsed_seconds.map(Duration::from_secs),
+            ..ExponentialBackoff::default()
+        }
+    }
+
+    /// Limits of the `Retry-After` waits of an operation starting now
+    pub fn retry_budget(&self) -> RetryBudget {
+        RetryBudget {
+            started: Instant::now(),
+            max_interval: Duration::from_secs(self.backoff.max_interval_seconds),
+            max_elapsed: self.backoff.max_elapsed_seconds.map(Duration::from_secs),
+        }
+    }
+}
+
+/// `Retry-After` in seconds or as an http date
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    match value.parse::<u64>() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => httpdate::parse_http_date(value)
+            .ok()?
+            .duration_since(SystemTime::now())
+            .ok(),
+    }
+}
+
+/// Start of a response body, for error messages
+fn excerpt(body: &[u8]) -> String {
+    let text = String::from_utf8_lossy(body);
+    match text.char_indices().nth(BODY_EXCERPT_LENGTH) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.into_owned(),
+    }
+}
+
+/// Token bucket refilled with `rate` tokens a second, holding up to `burst`
+#[derive(Debug)]
+struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    /// Available tokens and when they were counted
+    state: Mutex<(f64, Instant)>,
+}
+
+impl RateLimiter {
+    fn new(rate: f64, burst: f64) -> Self {
+        Self {
+            rate,
+            burst,
+            state: Mutex::new((burst, Instant::now())),
+        }
+    }
+
+    /// Take a token, waiting for it when the bucket is empty
+    async fn acquire(&self) {
+        let wait = self.reserve(Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Take a token at `now` and return how long to wait until it is refilled
+    fn reserve(&self, now: Instant) -> Duration {
+        let mut state = self.state.lock().expect("Rate limiter lock poisoned");
+        let (tokens, counted_at) = *state;
+        let refilled = now.saturating_duration_since(counted_at).as_secs_f64() * self.rate;
+        // A negative count reserves the tokens of the requests waiting
+        let tokens = (tokens + refilled).min(self.burst) - 1.0;
+        *state = (tokens, now.max(counted_at));
+        Duration::from_secs_f64((-tokens).max(0.0) / self.rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    fn headers(retry_after: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(retry_after).unwrap());
+        headers
+    }
+
+    fn status(status: u16) -> NodeError {
+        NodeError::Status {
+            url: "http://node/cut".to_string(),
+            status: StatusCode::from_u16(status).unwrap(),
+            body: String::new(),
+            retry_after: None,
+        }
+    }
+
+    #[test]
+    fn retry_after_in_seconds() {
+        assert_eq!(retry_after(&headers("120")), Some(Duration::from_secs(120)));
+        assert_eq!(retry_after(&headers(" 0 ")), Some(Duration::ZERO));
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+        assert_eq!(retry_after(&headers("-1")), None);
+        assert_eq!(retry_after(&headers("soon")), None);
+    }
+
+    #[test]
+    fn retry_after_as_an_http_date() {
+        let date = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(120));
+        let wait = retry_after(&headers(&date)).unwrap();
+        // The date is rounded down to the second
+        assert!(wait > Duration::from_secs(118) && wait <= Duration::from_secs(120));
+
+        let past = httpdate::fmt_http_date(SystemTime::now() - Duration::from_secs(60));
+        assert_eq!(retry_after(&headers(&past)), None);
+    }
+
+    fn throttled(retry_after: u64) -> NodeError {
+        NodeError::Status {
+            url: "http://node/cut".to_string(),
+            status: StatusCode::TOO_MANY_REQUESTS,
+            body: String::new(),
+            retry_after: Some(Duration::from_secs(retry_after)),
+        }
+    }
+
+    fn budget(max_interval: u64, max_elapsed: Option<u64>) -> RetryBudget {
+        RetryBudget {
+            started: Instant::now(),
+            max_interval: Duration::from_secs(max_interval),
+            max_elapsed: max_elapsed.map(Duration::from_secs),
+        }
+    }
+
+    #[test]
+    fn retry_after_is_capped_by_the_longest_interval() {
+        match throttled(3600).into_backoff(&budget(60, None)) {
+            backoff::Error::Transient { retry_after, .. } => {
+                assert_eq!(retry_after, Some(Duration::from_secs(60)))
+            }
+            e => panic!("unexpected {:?}", e),
+        }
+        match throttled(2).into_backoff(&budget(60, Some(900))) {
+            backoff::Error::Transient { retry_after, .. } => {
+                assert_eq!(retry_after, Some(Duration::from_secs(2)))
+            }
+            e => panic!("unexpected {:?}", e),
+        }
+    }
+
+    #[test]
+    fn retry_after_past_the_elapsed_time_gives_up() {
+        assert!(matches!(
+            throttled(30).into_backoff(&budget(60, Some(10))),
+            backoff::Error::Permanent(_)
+        ));
+        let started_earlier = RetryBudget {
+            started: Instant::now() - Duration::from_secs(895),
+            ..budget(60, Some(900))
+        };
+        assert!(matches!(
+            throttled(10).into_backoff(&started_earlier),
+            backoff::Error::Permanent(_)
+        ));
+        // Other transient errors wait as the backoff policy decides
+        assert!(matches!(
+            status(503).into_backoff(&started_earlier),
+            backoff::Error::Transient {
+                retry_after: None,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn server_errors_and_throttling_are_transient() {
+        for code in [408, 429, 500, 502, 503, 504] {
+            assert!(status(code).is_transient(), "{} should be retried", code);
+        }
+        for code in [400, 401, 403, 404, 410] {
+            assert!(
+                !status(code).is_transient(),
+                "{} should not be retried",
+                code
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_bodies_and_requests_are_permanent() {
+        let decode = NodeResponse {
+            url: Url::parse("http://node/cut").unwrap(),
+            status: StatusCode::OK,
+            body: Bytes::from_static(b"<html>"),
+        }
+        .json::<serde_json::Value>()
+        .unwrap_err();
+        assert!(!decode.is_transient());
+
+        let error = reqwest::Client::new().get("not a url").build().unwrap_err();
+        let request = NodeError::Request {
+            url: String::new(),
+            error,
+        };
+        assert!(!request.is_transient());
+        assert!(is_permanent(
+            &anyhow::Error::new(request).context("Failed to fetch the cut")
+        ));
+    }
+
+    #[tokio::test]
+    async fn connection_errors_are_transient() {
+        // Nothing listens on the discard port
+        let error = reqwest::Client::new()
+            .get("http://127.0.0.1:9/cut")
+            .send()
+            .await
+            .unwrap_err();
+        let request = NodeError::Request {
+            url: String::new(),
+            error,
+        };
+        assert!(request.is_transient());
+        assert!(!is_permanent(&anyhow::Error::new(request)));
+    }
+
+    #[test]
+    fn rate_limiter_allows_a_burst_then_spaces_requests() {
+        let limiter = RateLimiter::new(4.0, 2.0);
+        let start = limiter.state.lock().unwrap().1;
+        let waits = (0..4).map(|_| limiter.reserve(start)).collect::<Vec<_>>();
+        assert_eq!(
+            waits,
+            vec![
+                Duration::ZERO,
+                Duration::ZERO,
+                Duration::from_millis(250),
+                Duration::from_millis(500)
+            ]
+        );
+    }
+
+    #[test]
+    fn rate_limiter_refills_up_to_the_burst() {
+        let limiter = RateLimiter::new(4.0, 2.0);
+        let start = limiter.state.lock().unwrap().1;
+        limiter.reserve(start);
+        limiter.reserve(start);
+        // Half a second refills 2 tokens
+        let later = start + Duration::from_millis(500);
+        assert_eq!(limiter.reserve(later), Duration::ZERO);
+        assert_eq!(limiter.reserve(later), Duration::ZERO);
+        assert_eq!(limiter.reserve(later), Duration::from_millis(250));
+
+        // An idle period does not allow more than the burst
+        let idle = later + Duration::from_secs(60);
+        assert_eq!(limiter.reserve(idle), Duration::ZERO);
+        assert_eq!(limiter.reserve(idle), Duration::ZERO);
+        assert_eq!(limiter.reserve(idle), Duration::from_millis(250));
+    }
+}
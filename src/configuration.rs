@@ -1,30 +1,287 @@
-use serde::Deserialize;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer};
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
 
+use crate::network::{ChainGraph, GraphTransition, Network};
+
 #[derive(Deserialize, Clone)]
 pub struct Settings {
     pub application: ApplicationSettings,
     pub database: DatabaseSettings,
+    pub archive: ArchiveSettings,
+    pub api: ApiSettings,
+    pub telemetry: TelemetrySettings,
+    pub leases: LeaseSettings,
 }
 
 #[derive(Deserialize, Clone)]
 pub struct ApplicationSettings {
+    /// Root url of the chainweb node
     pub host: String,
+    pub network: Network,
+    /// Version name of a `custom` network
+    pub chainweb_version: Option<String>,
+    /// Chain graph history of a `custom` network
+    #[serde(default)]
+    pub graph_history: Vec<GraphTransition>,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub limit: u64,
     #[serde(deserialize_with = "deserialize_number_from_string")]
-    pub chain_fork_height: u64,
+    pub min_height: u64,
+    /// Height after which chains stop being indexed
+    #[serde(default, deserialize_with = "deserialize_option_number")]
+    pub max_height: Option<u64>,
+    /// Batches of a chain fetched from the node at the same time
     #[serde(deserialize_with = "deserialize_number_from_string")]
-    pub number_of_chains: i16,
+    pub concurrency: u64,
+    /// Stop every chain at its end height, or at the cut at startup when it
+    /// has none, and exit once all of them are done
+    #[serde(default)]
+    pub finite: bool,
+    /// Gas limit of a block, used for the block fullness
     #[serde(deserialize_with = "deserialize_number_from_string")]
-    pub limit: u64,
+    pub block_gas_limit: u64,
+    /// Chains to index, every chain of the node when not set. A list or a
+    /// comma separated string, e.g. `APP_APPLICATION__CHAINS=3,4`
+    #[serde(default, deserialize_with = "deserialize_chain_list")]
+    pub chains: Option<Vec<i16>>,
+    /// Settings of a chain taking precedence over the ones above, by chain id
+    #[serde(default)]
+    pub per_chain: HashMap<String, ChainOverrides>,
+    /// Client of the node shared by the chains
+    pub http: HttpSettings,
+    /// Retries of the requests to the node
+    pub backoff: BackoffSettings,
+}
+
+/// Limits of the requests to the node, summed over every chain
+#[derive(Deserialize, Clone, Debug)]
+pub struct HttpSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub connect_timeout_seconds: u64,
+    /// Time a request may take, reading the response included
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub request_timeout_seconds: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_concurrent_requests: u64,
+    /// Requests sent a second on average, unlimited when not set
+    #[serde(default, deserialize_with = "deserialize_option_number")]
+    pub requests_per_second: Option<f64>,
+    /// Requests sent at once after an idle period
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub burst: u64,
+}
+
+/// Exponential backoff between the retries of a failed request
+#[derive(Deserialize, Clone, Debug)]
+pub struct BackoffSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub initial_interval_ms: u64,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_interval_seconds: u64,
     #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub multiplier: f64,
+    /// Time after which a request is given up, retried forever when not set
+    #[serde(default, deserialize_with = "deserialize_option_number")]
+    pub max_elapsed_seconds: Option<u64>,
+}
+
+/// Settings of `application` that can be set for a single chain
+#[derive(Deserialize, Clone, Default, Debug)]
+pub struct ChainOverrides {
+    #[serde(default, deserialize_with = "deserialize_option_number")]
+    pub min_height: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_option_number")]
+    pub max_height: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_option_number")]
+    pub limit: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_option_number")]
+    pub concurrency: Option<u64>,
+}
+
+/// Settings of a chain once its overrides are applied
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChainSettings {
     pub min_height: u64,
+    pub max_height: Option<u64>,
+    pub limit: u64,
+    pub concurrency: u64,
+}
+
+/// `deserialize_option_number_from_string` of serde-aux only accepts
+/// borrowed strings, environment variables are owned
+fn deserialize_option_number<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr + Deserialize<'de>,
+    <T as FromStr>::Err: std::fmt::Display,
+{
+    #[derive(Deserialize)]
+    struct Number<T>(
+        #[serde(deserialize_with = "deserialize_number_from_string")]
+        #[serde(bound(
+            deserialize = "T: FromStr + Deserialize<'de>, <T as FromStr>::Err: std::fmt::Display"
+        ))]
+        T,
+    );
+
+    Ok(Option::<Number<T>>::deserialize(deserializer)?.map(|Number(n)| n))
+}
+
+fn deserialize_chain_list<'de, D>(deserializer: D) -> Result<Option<Vec<i16>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum ChainList {
+        List(Vec<i16>),
+        Text(String),
+    }
+
+    match Option::<ChainList>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(ChainList::List(chains)) => Ok(Some(chains)),
+        Some(ChainList::Text(text)) => text
+            .split(',')
+            .map(|chain| chain.trim().parse::<i16>())
+            .collect::<Result<Vec<_>, _>>()
+            .map(Some)
+            .map_err(|_| serde::de::Error::custom(format!("invalid chain list {:?}", text))),
+    }
+}
+
+impl ApplicationSettings {
+    pub fn chainweb_version(&self) -> Result<&str, String> {
+        match self.network {
+            Network::Custom => self
+                .chainweb_version
+                .as_deref()
+                .ok_or_else(|| "`chainweb_version` is required for a custom network.".to_string()),
+            _ => Ok(self.network.as_str()),
+        }
+    }
+
+    /// Base url of the chainweb API of the configured network
+    pub fn node_url(&self) -> Result<String, String> {
+        Ok(format!(
+            "{}/chainweb/0.0/{}",
+            self.host.trim_end_matches('/'),
+            self.chainweb_version()?
+        ))
+    }
+
+    pub fn chain_graph(&self) -> Result<ChainGraph, String> {
+        self.network.chain_graph(&self.graph_history)
+    }
+
+    /// Whether the chain is selected by `chains`
+    pub fn indexes_chain(&self, chain_id: i16) -> bool {
+        match &self.chains {
+            Some(chains) => chains.contains(&chain_id),
+            None => true,
+        }
+    }
+
+    /// Settings of a chain, with its `per_chain` overrides
+    pub fn chain_settings(&self, chain_id: i16) -> ChainSettings {
+        let overrides = self
+            .per_chain
+            .get(&chain_id.to_string())
+            .cloned()
+            .unwrap_or_default();
+        ChainSettings {
+            min_height: overrides.min_height.unwrap_or(self.min_height),
+            max_height: overrides.max_height.or(self.max_height),
+            limit: overrides.limit.unwrap_or(self.limit),
+            concurrency: overrides.concurrency.unwrap_or(self.concurrency),
+        }
+    }
+}
+
+/// Where raw node responses are archived
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveStorage {
+    Database,
+    Disk,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ArchiveSettings {
+    pub enabled: bool,
+    pub storage: ArchiveStorage,
+    /// Directory used by the `disk` storage
+    pub path: String,
+}
+
+/// HTTP server exposing the indexed data
+#[derive(Deserialize, Clone)]
+pub struct ApiSettings {
+    pub enabled: bool,
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    /// Blocks a chain may lag behind the node cut for `/ready` to succeed
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub ready_max_lag: u64,
+}
+
+/// Chains shared by the indexer instances of a database
+#[derive(Deserialize, Clone)]
+pub struct LeaseSettings {
+    /// Only index the chains this instance holds a lease on
+    pub enabled: bool,
+    /// Name of this instance in the lease table, `HOSTNAME` with a random
+    /// suffix by default
+    pub instance_id: Option<String>,
+    /// Seconds a lease lasts without being renewed
     #[serde(deserialize_with = "deserialize_number_from_string")]
-    pub max_height: u64,
+    pub ttl_seconds: u64,
+    /// Seconds between renewals of the leases
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub heartbeat_seconds: u64,
+}
+
+impl LeaseSettings {
+    pub fn instance_id(&self) -> String {
+        self.instance_id.clone().unwrap_or_else(|| {
+            let host = std::env::var("HOSTNAME").unwrap_or_else(|_| "indexer".to_string());
+            format!(
+                "{}-{}",
+                host,
+                &uuid::Uuid::new_v4().simple().to_string()[..8]
+            )
+        })
+    }
+}
+
+/// Output format of the logs
+#[derive(Deserialize, clap::ValueEnum, Clone, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Json,
+    Pretty,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct TelemetrySettings {
+    pub format: LogFormat,
+    /// OTLP gRPC endpoint of an OpenTelemetry collector, spans are not
+    /// exported without it
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
 }
 
 #[derive(serde::Deserialize, Clone)]
 pub struct DatabaseSettings {
+    /// Connection url, set from `DATABASE_URL`, used instead of the other
+    /// fields
+    #[serde(default)]
+    pub url: Option<String>,
     pub username: String,
     pub password: String,
     pub host: String,
@@ -36,6 +293,9 @@ pub struct DatabaseSettings {
 
 impl DatabaseSettings {
     pub fn without_db(&self) -> PgConnectOptions {
+        if let Some(options) = self.url_options() {
+            return options;
+        }
         let ssl_mode = if self.require_ssl {
             PgSslMode::Require
         } else {
@@ -50,11 +310,190 @@ impl DatabaseSettings {
     }
 
     pub fn with_db(&self) -> PgConnectOptions {
-        self.without_db().database(&self.database_name)
+        match self.url_options() {
+            Some(options) => options,
+            None => self.without_db().database(&self.database_name),
+        }
+    }
+
+    fn url_options(&self) -> Option<PgConnectOptions> {
+        let url = self.url.as_deref()?;
+        Some(PgConnectOptions::from_str(url).expect("`database.url` is checked by `validate`"))
+    }
+}
+
+/// `prefix` is the key of the settings, e.g. `application.per_chain.3`
+fn check_chain_settings(prefix: &str, chain: &ChainSettings) -> Result<(), ConfigurationError> {
+    let key = |name: &str| format!("{}.{}", prefix, name);
+    if chain.limit == 0 {
+        return Err(ConfigurationError::invalid(
+            &key("limit"),
+            "must be greater than 0",
+        ));
+    }
+    if chain.concurrency == 0 {
+        return Err(ConfigurationError::invalid(
+            &key("concurrency"),
+            "must be greater than 0",
+        ));
+    }
+    if chain.max_height.is_some_and(|max| max < chain.min_height) {
+        return Err(ConfigurationError::invalid(
+            &key("max_height"),
+            "must not be lower than `min_height`",
+        ));
+    }
+
+    Ok(())
+}
+
+fn check_http_settings(
+    http: &HttpSettings,
+    backoff: &BackoffSettings,
+) -> Result<(), ConfigurationError> {
+    let positive = [
+        (
+            "application.http.connect_timeout_seconds",
+            http.connect_timeout_seconds,
+        ),
+        (
+            "application.http.request_timeout_seconds",
+            http.request_timeout_seconds,
+        ),
+        (
+            "application.http.max_concurrent_requests",
+            http.max_concurrent_requests,
+        ),
+        ("application.http.burst", http.burst),
+        (
+            "application.backoff.initial_interval_ms",
+            backoff.initial_interval_ms,
+        ),
+        (
+            "application.backoff.max_interval_seconds",
+            backoff.max_interval_seconds,
+        ),
+    ];
+    if let Some((key, _)) = positive.iter().find(|(_, value)| *value == 0) {
+        return Err(ConfigurationError::invalid(key, "must be greater than 0"));
+    }
+    if http
+        .requests_per_second
+        .is_some_and(|rate| rate.is_nan() || rate <= 0.0)
+    {
+        return Err(ConfigurationError::invalid(
+            "application.http.requests_per_second",
+            "must be greater than 0",
+        ));
+    }
+    if backoff.multiplier.is_nan() || backoff.multiplier < 1.0 {
+        return Err(ConfigurationError::invalid(
+            "application.backoff.multiplier",
+            "must be at least 1",
+        ));
+    }
+
+    Ok(())
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ConfigurationError {
+    #[error(transparent)]
+    Load(#[from] config::ConfigError),
+    /// Missing setting, wrong type or rejected value
+    #[error("Invalid setting `{key}`: {message}")]
+    Invalid { key: String, message: String },
+}
+
+impl ConfigurationError {
+    fn invalid(key: &str, message: impl ToString) -> Self {
+        Self::Invalid {
+            key: key.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl Settings {
+    /// Reject values that deserialize but cannot work
+    pub fn validate(&self) -> Result<(), ConfigurationError> {
+        let host = reqwest::Url::parse(&self.application.host)
+            .map_err(|e| ConfigurationError::invalid("application.host", e))?;
+        if !matches!(host.scheme(), "http" | "https") {
+            return Err(ConfigurationError::invalid(
+                "application.host",
+                "expected an http or https url",
+            ));
+        }
+        // The API path is appended to the host by `node_url`
+        if host.path().split('/').any(|segment| segment == "chainweb") {
+            return Err(ConfigurationError::invalid(
+                "application.host",
+                "expected the root url of the node, without the `/chainweb/0.0/<version>` path",
+            ));
+        }
+        let application = &self.application;
+        check_chain_settings(
+            "application",
+            &ChainSettings {
+                min_height: application.min_height,
+                max_height: application.max_height,
+                limit: application.limit,
+                concurrency: application.concurrency,
+            },
+        )?;
+        for key in application.per_chain.keys() {
+            let prefix = format!("application.per_chain.{}", key);
+            let chain_id = key
+                .parse::<i16>()
+                .map_err(|_| ConfigurationError::invalid(&prefix, "expected a chain id"))?;
+            check_chain_settings(&prefix, &application.chain_settings(chain_id))?;
+        }
+        self.application
+            .chainweb_version()
+            .map_err(|e| ConfigurationError::invalid("application.chainweb_version", e))?;
+        self.application
+            .chain_graph()
+            .map_err(|e| ConfigurationError::invalid("application.graph_history", e))?;
+        check_http_settings(&self.application.http, &self.application.backoff)?;
+        if let Some(url) = &self.database.url {
+            PgConnectOptions::from_str(url)
+                .map_err(|e| ConfigurationError::invalid("database.url", e))?;
+        }
+        if self.leases.enabled {
+            if self.leases.heartbeat_seconds == 0 {
+                return Err(ConfigurationError::invalid(
+                    "leases.heartbeat_seconds",
+                    "must be greater than 0",
+                ));
+            }
+            if self.leases.ttl_seconds <= self.leases.heartbeat_seconds {
+                return Err(ConfigurationError::invalid(
+                    "leases.ttl_seconds",
+                    "must be greater than `leases.heartbeat_seconds`",
+                ));
+            }
+            if self.application.finite {
+                return Err(ConfigurationError::invalid(
+                    "leases.enabled",
+                    "cannot be combined with `application.finite`",
+                ));
+            }
+        }
+        if let Some(endpoint) = &self.telemetry.otlp_endpoint {
+            reqwest::Url::parse(endpoint)
+                .map_err(|e| ConfigurationError::invalid("telemetry.otlp_endpoint", e))?;
+        }
+
+        Ok(())
     }
 }
 
-pub fn get_configuration() -> Result<Settings, config::ConfigError> {
+///
+/// Layer `base.yaml`, the file of the environment, then `APP_` environment
+/// variables with `__` between nested keys, e.g. `APP_DATABASE__PASSWORD`
+/// for `database.password`. `DATABASE_URL` sets `database.url`.
+pub fn get_configuration() -> Result<Settings, ConfigurationError> {
     let base_path = std::env::current_dir().expect("Failed to determine the current directory");
     let config_dir = base_path.join("configuration");
 
@@ -70,8 +509,19 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
     let settings = config::Config::builder()
         .add_source(config::File::from(config_dir.join("base.yaml")).required(true))
         .add_source(config::File::from(config_dir.join(&environment_filename)).required(true))
+        .add_source(
+            config::Environment::with_prefix("APP")
+                .prefix_separator("_")
+                .separator("__"),
+        )
+        .set_override_option("database.url", std::env::var("DATABASE_URL").ok())?
         .build()?;
-    settings.try_deserialize::<Settings>()
+    // The path of the failing field is lost by `try_deserialize`
+    let settings: Settings = serde_path_to_error::deserialize(settings)
+        .map_err(|e| ConfigurationError::invalid(&e.path().to_string(), e.inner()))?;
+    settings.validate()?;
+
+    Ok(settings)
 }
 
 pub enum Environment {
@@ -102,3 +552,198 @@ impl TryFrom<String> for Environment {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `base.yaml` with overrides given as strings, like environment variables
+    fn load(overrides: &[(&str, &str)]) -> Result<Settings, ConfigurationError> {
+        let mut builder = config::Config::builder().add_source(config::File::from_str(
+            include_str!("../configuration/base.yaml"),
+            config::FileFormat::Yaml,
+        ));
+        for (key, value) in overrides {
+            builder = builder.set_override(*key, *value)?;
+        }
+        let settings: Settings = serde_path_to_error::deserialize(builder.build()?)
+            .map_err(|e| ConfigurationError::invalid(&e.path().to_string(), e.inner()))?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Key named by the error of invalid settings
+    fn invalid_key(overrides: &[(&str, &str)]) -> String {
+        match load(overrides) {
+            Err(ConfigurationError::Invalid { key, .. }) => key,
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("{:?} should be rejected", overrides),
+        }
+    }
+
+    #[test]
+    fn base_settings_are_valid() {
+        let settings = load(&[]).unwrap();
+        assert_eq!(settings.application.chains, None);
+        assert!(settings.application.indexes_chain(19));
+    }
+
+    #[test]
+    fn numbers_are_read_from_strings() {
+        let settings = load(&[
+            ("application.limit", "20"),
+            ("application.max_height", "1000"),
+        ])
+        .unwrap();
+        assert_eq!(settings.application.limit, 20);
+        assert_eq!(settings.application.max_height, Some(1000));
+        assert_eq!(
+            invalid_key(&[("application.limit", "many")]),
+            "application.limit"
+        );
+    }
+
+    #[test]
+    fn chains_are_read_from_a_comma_separated_list() {
+        let settings = load(&[("application.chains", "3, 4")]).unwrap();
+        assert_eq!(settings.application.chains, Some(vec![3, 4]));
+        assert!(settings.application.indexes_chain(4));
+        assert!(!settings.application.indexes_chain(5));
+        assert_eq!(
+            invalid_key(&[("application.chains", "3,x")]),
+            "application.chains"
+        );
+    }
+
+    #[test]
+    fn per_chain_settings_override_the_application_ones() {
+        let settings = load(&[
+            ("application.min_height", "10"),
+            ("application.per_chain.3.min_height", "400"),
+            ("application.per_chain.3.concurrency", "4"),
+        ])
+        .unwrap();
+        assert_eq!(
+            settings.application.chain_settings(3),
+            ChainSettings {
+                min_height: 400,
+                max_height: None,
+                limit: 500,
+                concurrency: 4,
+            }
+        );
+        assert_eq!(settings.application.chain_settings(4).min_height, 10);
+    }
+
+    #[test]
+    fn per_chain_settings_are_read_from_quoted_yaml_keys() {
+        let settings: Settings = config::Config::builder()
+            .add_source(config::File::from_str(
+                include_str!("../configuration/base.yaml"),
+                config::FileFormat::Yaml,
+            ))
+            .add_source(config::File::from_str(
+                "application:\n  per_chain:\n    '3':\n      max_height: 30\n",
+                config::FileFormat::Yaml,
+            ))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+        assert_eq!(settings.application.chain_settings(3).max_height, Some(30));
+    }
+
+    #[test]
+    fn invalid_chain_settings_are_named() {
+        assert_eq!(
+            invalid_key(&[("application.limit", "0")]),
+            "application.limit"
+        );
+        assert_eq!(
+            invalid_key(&[("application.concurrency", "0")]),
+            "application.concurrency"
+        );
+        assert_eq!(
+            invalid_key(&[
+                ("application.min_height", "10"),
+                ("application.max_height", "9")
+            ]),
+            "application.max_height"
+        );
+        assert_eq!(
+            invalid_key(&[("application.per_chain.3.limit", "0")]),
+            "application.per_chain.3.limit"
+        );
+        assert_eq!(
+            invalid_key(&[
+                ("application.per_chain.3.max_height", "5"),
+                ("application.min_height", "6")
+            ]),
+            "application.per_chain.3.max_height"
+        );
+        assert_eq!(
+            invalid_key(&[("application.per_chain.first.limit", "10")]),
+            "application.per_chain.first"
+        );
+    }
+
+    #[test]
+    fn invalid_urls_are_named() {
+        assert_eq!(
+            invalid_key(&[("application.host", "ftp://node")]),
+            "application.host"
+        );
+        assert_eq!(
+            invalid_key(&[("application.host", "not a url")]),
+            "application.host"
+        );
+        assert_eq!(
+            invalid_key(&[(
+                "application.host",
+                "https://api.chainweb.com/chainweb/0.0/mainnet01"
+            )]),
+            "application.host"
+        );
+        assert_eq!(
+            invalid_key(&[("database.url", "mysql//nowhere")]),
+            "database.url"
+        );
+    }
+
+    #[test]
+    fn node_client_limits_must_be_usable() {
+        let settings = load(&[
+            ("application.http.requests_per_second", "2.5"),
+            ("application.http.burst", "5"),
+        ])
+        .unwrap();
+        assert_eq!(settings.application.http.requests_per_second, Some(2.5));
+        assert_eq!(settings.application.http.burst, 5);
+        for rate in ["0", "-1", "NaN"] {
+            assert_eq!(
+                invalid_key(&[("application.http.requests_per_second", rate)]),
+                "application.http.requests_per_second"
+            );
+        }
+        for key in [
+            "application.http.burst",
+            "application.http.max_concurrent_requests",
+            "application.http.request_timeout_seconds",
+            "application.backoff.max_interval_seconds",
+        ] {
+            assert_eq!(invalid_key(&[(key, "0")]), key);
+        }
+        assert_eq!(
+            invalid_key(&[("application.backoff.multiplier", "0.5")]),
+            "application.backoff.multiplier"
+        );
+    }
+
+    #[test]
+    fn custom_networks_need_a_version() {
+        assert_eq!(
+            invalid_key(&[("application.network", "custom")]),
+            "application.chainweb_version"
+        );
+    }
+}
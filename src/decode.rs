@@ -0,0 +1,271 @@
+use std::fmt;
+
+use crate::types::{BlockHeader, Output, Transaction, TransactionWithCmdSigs};
+use crate::utils::decode_from_base64_url;
+
+/// Part of a block payload transaction that failed to decode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxField {
+    /// Signed transaction envelope (`hash`, `sigs`, `cmd`)
+    Transaction,
+    /// Transaction output returned by the node
+    Output,
+    /// `cmd` string embedded in the signed transaction
+    Cmd,
+    /// Coinbase output of the block
+    Coinbase,
+}
+
+impl TxField {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TxField::Transaction => "transaction",
+            TxField::Output => "output",
+            TxField::Cmd => "cmd",
+            TxField::Coinbase => "coinbase",
+        }
+    }
+}
+
+impl fmt::Display for TxField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Where a decode failure happened
+#[derive(Debug, Clone)]
+pub struct DecodeContext {
+    pub chain_id: i16,
+    pub height: u64,
+    pub request_key: Option<String>,
+    pub field: TxField,
+}
+
+impl fmt::Display for DecodeContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "chain {} height {} request key {} field `{}`",
+            self.chain_id,
+            self.height,
+            self.request_key.as_deref().unwrap_or("<unknown>"),
+            self.field
+        )
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum DecodeError {
+    #[error("Missing {} in payload transaction ({context})", context.field)]
+    Missing { context: DecodeContext },
+    #[error("Invalid base64 ({context}): {source}")]
+    Base64 {
+        context: DecodeContext,
+        #[source]
+        source: base64_url::base64::DecodeError,
+    },
+    #[error("Invalid json ({context}): {source}")]
+    Json {
+        context: DecodeContext,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+impl DecodeError {
+    pub fn context(&self) -> &DecodeContext {
+        match self {
+            DecodeError::Missing { context }
+            | DecodeError::Base64 { context, .. }
+            | DecodeError::Json { context, .. } => context,
+        }
+    }
+}
+
+/// A payload transaction decoded together with its output
+#[derive(Debug, Clone)]
+pub struct DecodedTransaction {
+    pub request_key: String,
+    pub signed: TransactionWithCmdSigs,
+    pub cmd: Transaction,
+    pub output: Output,
+}
+
+/// A block header with its decoded payload
+#[derive(Debug)]
+pub struct DecodedBlock<'a> {
+    pub header: &'a BlockHeader,
+    pub coinbase: Option<Output>,
+    pub transactions: Vec<DecodedTransaction>,
+}
+
+impl DecodedBlock<'_> {
+    /// Coinbase output followed by the outputs of the transactions
+    pub fn outputs(&self) -> impl Iterator<Item = &Output> {
+        self.coinbase
+            .iter()
+            .chain(self.transactions.iter().map(|t| &t.output))
+    }
+}
+
+///
+/// Decode a `[transaction, output]` pair as returned by `/payload/outputs/batch`.
+/// Both items are base64url encoded json documents.
+pub fn decode_transaction(
+    chain_id: i16,
+    height: u64,
+    transaction: &[String],
+) -> Result<DecodedTransaction, DecodeError> {
+    let mut context = DecodeContext {
+        chain_id,
+        height,
+        request_key: None,
+        field: TxField::Transaction,
+    };
+
+    let signed: TransactionWithCmdSigs =
+        decode_json(&context, transaction.first().map(String::as_str))?;
+    context.request_key = Some(signed.hash.clone());
+
+    context.field = TxField::Output;
+    let output: Output = decode_json(&context, transaction.get(1).map(String::as_str))?;
+
+    // cmd is a json document serialized as a string
+    context.field = TxField::Cmd;
+    let cmd: Transaction =
+        serde_json::from_str(&signed.cmd).map_err(|source| DecodeError::Json {
+            context: context.clone(),
+            source,
+        })?;
+
+    Ok(DecodedTransaction {
+        request_key: output.req_key.clone(),
+        signed,
+        cmd,
+        output,
+    })
+}
+
+/// Decode the base64url encoded coinbase output of a block payload
+pub fn decode_coinbase(chain_id: i16, height: u64, coinbase: &str) -> Result<Output, DecodeError> {
+    let context = DecodeContext {
+        chain_id,
+        height,
+        request_key: None,
+        field: TxField::Coinbase,
+    };
+    decode_json(&context, Some(coinbase))
+}
+
+fn decode_json<T: serde::de::DeserializeOwned>(
+    context: &DecodeContext,
+    input: Option<&str>,
+) -> Result<T, DecodeError> {
+    let input = input.ok_or_else(|| DecodeError::Missing {
+        context: context.clone(),
+    })?;
+    let bytes = decode_from_base64_url(input).map_err(|source| DecodeError::Base64 {
+        context: context.clone(),
+        source,
+    })?;
+    serde_json::from_slice(&bytes).map_err(|source| DecodeError::Json {
+        context: context.clone(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn encode(value: &Value) -> String {
+        base64_url::encode(&value.to_string())
+    }
+
+    fn cmd() -> String {
+        json!({
+            "networkId": "mainnet01",
+            "payload": { "exec": { "code": "(+ 1 2)", "data": {} } },
+            "signers": [],
+            "meta": {
+                "creationTime": 0,
+                "ttl": 600.0,
+                "gasLimit": 1000,
+                "chainId": "0",
+                "gasPrice": 1e-8,
+                "sender": "alice"
+            },
+            "nonce": "1"
+        })
+        .to_string()
+    }
+
+    fn signed(cmd: &str) -> String {
+        encode(&json!({ "hash": "rk", "sigs": [], "cmd": cmd }))
+    }
+
+    fn output() -> String {
+        encode(&json!({
+            "continuation": null,
+            "events": [],
+            "gas": 1,
+            "logs": "",
+            "metaData": null,
+            "reqKey": "rk",
+            "result": { "status": "success", "data": 3 },
+            "txId": 1
+        }))
+    }
+
+    fn decode_error(transaction: &[String]) -> DecodeError {
+        decode_transaction(0, 10, transaction).unwrap_err()
+    }
+
+    #[test]
+    fn transaction_and_output_are_decoded() {
+        let decoded = decode_transaction(0, 10, &[signed(&cmd()), output()]).unwrap();
+        assert_eq!(decoded.request_key, "rk");
+        assert_eq!(decoded.cmd.meta.sender, "alice");
+        assert_eq!(decoded.output.gas, 1);
+    }
+
+    #[test]
+    fn missing_output_is_reported_with_the_request_key() {
+        let error = decode_error(&[signed(&cmd())]);
+        assert!(matches!(error, DecodeError::Missing { .. }));
+        assert_eq!(error.context().field, TxField::Output);
+        assert_eq!(error.context().request_key.as_deref(), Some("rk"));
+        assert_eq!(error.context().height, 10);
+    }
+
+    #[test]
+    fn invalid_base64_is_reported() {
+        let error = decode_error(&["not base64!".to_string(), output()]);
+        assert!(matches!(error, DecodeError::Base64 { .. }));
+        assert_eq!(error.context().field, TxField::Transaction);
+        assert_eq!(error.context().request_key, None);
+    }
+
+    #[test]
+    fn invalid_json_is_reported_by_field() {
+        let error = decode_error(&[signed(&cmd()), encode(&json!({ "gas": 1 }))]);
+        assert!(matches!(error, DecodeError::Json { .. }));
+        assert_eq!(error.context().field, TxField::Output);
+
+        let error = decode_error(&[signed("{"), output()]);
+        assert!(matches!(error, DecodeError::Json { .. }));
+        assert_eq!(error.context().field, TxField::Cmd);
+        assert_eq!(error.context().request_key.as_deref(), Some("rk"));
+    }
+
+    #[test]
+    fn invalid_coinbase_is_reported() {
+        let error = decode_coinbase(3, 10, &encode(&json!([]))).unwrap_err();
+        assert!(matches!(error, DecodeError::Json { .. }));
+        assert_eq!(error.context().field, TxField::Coinbase);
+        assert_eq!(error.context().chain_id, 3);
+    }
+}
@@ -0,0 +1,298 @@
+use serde_json::Value;
+
+use crate::decode::DecodedTransaction;
+use crate::pact::{parse_spanned, Expr};
+
+/// What a deployment defines
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeploymentKind {
+    Module,
+    Interface,
+    Namespace,
+}
+
+impl DeploymentKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeploymentKind::Module => "module",
+            DeploymentKind::Interface => "interface",
+            DeploymentKind::Namespace => "namespace",
+        }
+    }
+}
+
+/// Module, interface or namespace defined by the code of a transaction
+#[derive(Debug, Clone)]
+pub struct Deployment {
+    pub kind: DeploymentKind,
+    /// Namespace selected by `(namespace ...)` before the definition
+    pub namespace: Option<String>,
+    pub name: String,
+    /// Hash of a module, taken from the events the transaction emits for it
+    /// or else from the transaction result when it is the last definition
+    /// of the code
+    pub hash: Option<String>,
+    /// Keyset name or capability governing a module
+    pub governance: Option<String>,
+    /// `keyset` or `capability`
+    pub governance_kind: Option<&'static str>,
+    /// Source of the definition
+    pub code: String,
+}
+
+impl Deployment {
+    /// Name prefixed with its namespace, e.g. `free.my-module`
+    pub fn qualified_name(&self) -> String {
+        match &self.namespace {
+            Some(namespace) => format!("{}.{}", namespace, self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+///
+/// Top-level `module`, `interface` and `define-namespace` definitions of a
+/// successful exec transaction.
+pub fn deployments(tx: &DecodedTransaction) -> Vec<Deployment> {
+    let Some(exec) = &tx.cmd.payload.exec else {
+        return vec![];
+    };
+    if tx.output.result.status != "success" {
+        return vec![];
+    }
+    let Ok(exprs) = parse_spanned(&exec.code) else {
+        return vec![];
+    };
+
+    let mut namespace = None;
+    let mut deployments = vec![];
+    for (expr, span) in &exprs {
+        let Some((function, args)) = expr.as_call() else {
+            continue;
+        };
+        let code = exec.code[span.clone()].to_string();
+        match (function, args) {
+            ("namespace", [ns]) => namespace = ns.resolve_string(&exec.data),
+            ("module", [name, governance, ..]) => {
+                let (governance_kind, governance) = match governance {
+                    Expr::Atom(cap) => (Some("capability"), Some(cap.clone())),
+                    ks => (Some("keyset"), ks.as_str().map(String::from)),
+                };
+                if let Expr::Atom(name) = name {
+                    deployments.push(Deployment {
+                        kind: DeploymentKind::Module,
+                        namespace: namespace.clone(),
+                        name: name.clone(),
+                        hash: None,
+                        governance,
+                        governance_kind,
+                        code,
+                    });
+                }
+            }
+            ("interface", [Expr::Atom(name), ..]) => deployments.push(Deployment {
+                kind: DeploymentKind::Interface,
+                namespace: namespace.clone(),
+                name: name.clone(),
+                hash: None,
+                governance: None,
+                governance_kind: None,
+                code,
+            }),
+            ("define-namespace", [name, ..]) => {
+                if let Some(name) = name.resolve_string(&exec.data) {
+                    deployments.push(Deployment {
+                        kind: DeploymentKind::Namespace,
+                        namespace: None,
+                        name,
+                        hash: None,
+                        governance: None,
+                        governance_kind: None,
+                        code,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Events emitted by a module after it is loaded carry its new hash, the
+    // last one is emitted after any redefinition of the code
+    for deployment in &mut deployments {
+        if deployment.kind == DeploymentKind::Namespace {
+            continue;
+        }
+        let name = deployment.qualified_name();
+        deployment.hash = tx
+            .output
+            .events
+            .iter()
+            .rev()
+            .find(|e| e.module.qualified_name() == name)
+            .map(|e| e.module_hash.clone());
+    }
+
+    // The result is the one of the last expression:
+    // `Loaded module free.my-module, hash <hash>`
+    let last_function = exprs.last().and_then(|(e, _)| e.as_call()).map(|(f, _)| f);
+    if let (Some("module" | "interface"), Some(last), Some(result)) = (
+        last_function,
+        deployments.last_mut(),
+        tx.output.result.data.as_ref().and_then(Value::as_str),
+    ) {
+        let loaded = result
+            .strip_prefix("Loaded module ")
+            .or_else(|| result.strip_prefix("Loaded interface "));
+        if let Some((name, hash)) = loaded.and_then(|l| l.split_once(", hash ")) {
+            if last.hash.is_none() && name == last.qualified_name() {
+                last.hash = Some(hash.to_string());
+            }
+        }
+    }
+    deployments
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn exec(code: &str, data: Value, result: Value, events: Value) -> DecodedTransaction {
+        let cmd = json!({
+            "networkId": "mainnet01",
+            "payload": { "exec": { "code": code, "data": data } },
+            "signers": [],
+            "meta": {
+                "creationTime": 0,
+                "ttl": 600.0,
+                "gasLimit": 1000,
+                "chainId": "0",
+                "gasPrice": 1e-8,
+                "sender": "alice"
+            },
+            "nonce": "1"
+        });
+        DecodedTransaction {
+            request_key: "rk".to_string(),
+            signed: serde_json::from_value(json!({
+                "hash": "rk",
+                "cmd": cmd.to_string(),
+                "sigs": []
+            }))
+            .unwrap(),
+            cmd: serde_json::from_value(cmd).unwrap(),
+            output: serde_json::from_value(json!({
+                "continuation": null,
+                "events": events,
+                "gas": 1,
+                "logs": "",
+                "metaData": null,
+                "reqKey": "rk",
+                "result": result,
+                "txId": 1
+            }))
+            .unwrap(),
+        }
+    }
+
+    fn success(data: &str) -> Value {
+        json!({ "status": "success", "data": data })
+    }
+
+    #[test]
+    fn module_hash_is_read_from_the_result_of_the_last_definition() {
+        let code = r#"(namespace (read-msg "ns")) (module my-mod GOV (defcap GOV () true))"#;
+        let tx = exec(
+            code,
+            json!({ "ns": "free" }),
+            success("Loaded module free.my-mod, hash H1"),
+            json!([]),
+        );
+
+        let found = deployments(&tx);
+        assert_eq!(found.len(), 1);
+        let module = &found[0];
+        assert_eq!(module.kind, DeploymentKind::Module);
+        assert_eq!(module.qualified_name(), "free.my-mod");
+        assert_eq!(module.hash.as_deref(), Some("H1"));
+        assert_eq!(module.governance.as_deref(), Some("GOV"));
+        assert_eq!(module.governance_kind, Some("capability"));
+        assert_eq!(module.code, "(module my-mod GOV (defcap GOV () true))");
+    }
+
+    #[test]
+    fn module_hash_is_unknown_when_followed_by_other_expressions() {
+        let code = r#"(module my-mod 'my-ks (deftable t)) (create-table t)"#;
+        let tx = exec(code, json!({}), success("TableCreated"), json!([]));
+
+        let found = deployments(&tx);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].hash, None);
+        assert_eq!(found[0].governance.as_deref(), Some("my-ks"));
+        assert_eq!(found[0].governance_kind, Some("keyset"));
+    }
+
+    #[test]
+    fn module_hash_is_read_from_its_events() {
+        let code = r#"(module my-mod GOV (defcap GOV () true)) (my-mod.init)"#;
+        let events = json!([
+            {
+                "module": { "name": "coin", "namespace": null },
+                "moduleHash": "COIN",
+                "name": "TRANSFER",
+                "params": []
+            },
+            {
+                "module": { "name": "my-mod", "namespace": null },
+                "moduleHash": "H2",
+                "name": "INIT",
+                "params": []
+            }
+        ]);
+        let tx = exec(code, json!({}), success("Write succeeded"), events);
+
+        assert_eq!(deployments(&tx)[0].hash.as_deref(), Some("H2"));
+    }
+
+    #[test]
+    fn interfaces_and_namespaces_are_found() {
+        let code = r#"(define-namespace "mine" (read-keyset "ks") (read-keyset "ks"))
+            (namespace 'mine)
+            (interface my-if (defun g ()))"#;
+        let tx = exec(
+            code,
+            json!({}),
+            success("Loaded interface mine.my-if, hash H3"),
+            json!([]),
+        );
+
+        let found = deployments(&tx);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].kind, DeploymentKind::Namespace);
+        assert_eq!(found[0].qualified_name(), "mine");
+        assert_eq!(found[1].kind, DeploymentKind::Interface);
+        assert_eq!(found[1].qualified_name(), "mine.my-if");
+        assert_eq!(found[1].hash.as_deref(), Some("H3"));
+    }
+
+    #[test]
+    fn failed_transactions_deploy_nothing() {
+        let tx = exec(
+            "(module my-mod GOV (defcap GOV () true))",
+            json!({}),
+            json!({
+                "status": "failure",
+                "error": {
+                    "callStack": [],
+                    "info": "",
+                    "message": "Keyset failure",
+                    "type": "TxFailure"
+                }
+            }),
+            json!([]),
+        );
+
+        assert!(deployments(&tx).is_empty());
+    }
+}
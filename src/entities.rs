@@ -1,6 +1,18 @@
-use sqlx::PgPool;
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::HashSet;
 use uuid::Uuid;
 
+use crate::accounts::GuardChange;
+use crate::calls::FunctionCall;
+use crate::decode::{DecodeError, DecodedTransaction};
+use crate::deployments::Deployment;
+use crate::nfts::NftEvent;
+use crate::pagination::{Cursor, Page};
+use crate::types::{BlockHeader, Event, Output};
+use crate::utils::{decimal_from_f64, parse_pact_decimal, percentile};
+
 #[derive(sqlx::FromRow, Debug)]
 pub struct Block {
     pub id: Uuid,
@@ -16,38 +28,2412 @@ impl Block {
             height: height as i64,
         }
     }
-    pub async fn insert(self, pool: &PgPool) -> Result<(), sqlx::Error> {
+
+    pub async fn insert_as_last_processed_block(
+        self,
+        tx: &mut Transaction<'_, Postgres>,
+    ) -> Result<(), sqlx::Error> {
         sqlx::query!(
             r#"
-            INSERT INTO blocks(id, chain_id, height)
+            INSERT INTO processed_blocks_logs(id, chain_id, height)
             VALUES ($1, $2, $3)
+            ON CONFLICT (chain_id)
+            DO
+                UPDATE SET height=GREATEST(processed_blocks_logs.height, EXCLUDED.height);
             "#,
             self.id,
             self.chain_id,
             self.height
         )
-        .execute(pool)
+        .execute(tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Height of the last processed block of a chain
+    pub async fn last_processed_height(
+        pool: &PgPool,
+        chain_id: i16,
+    ) -> Result<Option<i64>, sqlx::Error> {
+        sqlx::query_scalar!(
+            "SELECT height FROM processed_blocks_logs WHERE chain_id = $1",
+            chain_id
+        )
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Move the last processed block of a chain back to `height`
+    pub async fn rewind(
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+        height: i64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE processed_blocks_logs SET height = LEAST(height, $2) WHERE chain_id = $1",
+            chain_id,
+            height
+        )
+        .execute(tx)
         .await?;
 
         Ok(())
     }
+}
+
+/// Row of the `blocks` table. Header columns are empty for blocks indexed
+/// before they were added.
+#[derive(sqlx::FromRow, serde::Serialize, Debug, Clone)]
+pub struct BlockRecord {
+    pub id: Uuid,
+    pub chain_id: i16,
+    pub height: i64,
+    pub hash: Option<String>,
+    pub parent: Option<String>,
+    pub payload_hash: Option<String>,
+    pub creation_time: Option<i64>,
+}
+
+impl BlockRecord {
+    pub fn new(header: &BlockHeader) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            chain_id: header.chain_id as i16,
+            height: header.height as i64,
+            hash: Some(header.hash.clone()),
+            parent: Some(header.parent.clone()),
+            payload_hash: Some(header.payload_hash.clone()),
+            creation_time: Some(header.creation_time as i64),
+        }
+    }
 
-    pub async fn insert_as_last_processed_block(self, pool: &PgPool) -> Result<(), sqlx::Error> {
+    /// Store a block unless it is already stored
+    pub async fn insert(self, tx: &mut Transaction<'_, Postgres>) -> Result<(), sqlx::Error> {
         sqlx::query!(
             r#"
-            INSERT INTO processed_blocks_logs(id, chain_id, height)
-            VALUES ($1, $2, $3)
-            ON CONFLICT (chain_id)
-            DO
-                UPDATE SET height=EXCLUDED.height;
+            INSERT INTO blocks(id, chain_id, height, hash, parent, payload_hash, creation_time)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (hash) DO NOTHING
             "#,
             self.id,
             self.chain_id,
-            self.height
+            self.height,
+            self.hash,
+            self.parent,
+            self.payload_hash,
+            self.creation_time
+        )
+        .execute(tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Hashes of the blocks stored for a chain in an inclusive height range
+    pub async fn hashes(
+        pool: &PgPool,
+        chain_id: i16,
+        min_height: i64,
+        max_height: i64,
+    ) -> Result<HashSet<String>, sqlx::Error> {
+        let hashes = sqlx::query_scalar!(
+            r#"
+            SELECT hash AS "hash!"
+            FROM blocks
+            WHERE chain_id = $1 AND height BETWEEN $2 AND $3 AND hash IS NOT NULL
+            "#,
+            chain_id,
+            min_height,
+            max_height
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(hashes.into_iter().collect())
+    }
+
+    /// Remove the blocks of a chain and an inclusive height range, returns
+    /// how many were removed
+    pub async fn delete(
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+        min_height: i64,
+        max_height: i64,
+    ) -> Result<u64, sqlx::Error> {
+        let deleted = sqlx::query!(
+            "DELETE FROM blocks WHERE chain_id = $1 AND height BETWEEN $2 AND $3",
+            chain_id,
+            min_height,
+            max_height
+        )
+        .execute(tx)
+        .await?;
+
+        Ok(deleted.rows_affected())
+    }
+
+    pub async fn find_by_hash(pool: &PgPool, hash: &str) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            BlockRecord,
+            r#"
+            SELECT id, chain_id, height, hash, parent, payload_hash, creation_time
+            FROM blocks
+            WHERE hash = $1
+            "#,
+            hash
+        )
+        .fetch_optional(pool)
+        .await
+    }
+
+    ///
+    /// Block of a chain at a height. When several blocks are stored at the
+    /// height, the one that is the parent of a stored block at the next
+    /// height is canonical, or else the most recently created one.
+    pub async fn find_by_height(
+        pool: &PgPool,
+        chain_id: i16,
+        height: i64,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            BlockRecord,
+            r#"
+            SELECT b.id, b.chain_id, b.height, b.hash, b.parent, b.payload_hash, b.creation_time
+            FROM blocks b
+            WHERE b.chain_id = $1 AND b.height = $2
+            ORDER BY
+                EXISTS (
+                    SELECT 1 FROM blocks c
+                    WHERE c.chain_id = b.chain_id AND c.height = b.height + 1 AND c.parent = b.hash
+                ) DESC,
+                b.creation_time DESC NULLS LAST,
+                b.hash
+            LIMIT 1
+            "#,
+            chain_id,
+            height
+        )
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Latest blocks first, optionally of a single chain
+    pub async fn list(
+        pool: &PgPool,
+        chain_id: Option<i16>,
+        limit: i64,
+        after: Option<Cursor>,
+    ) -> Result<Page<Self>, sqlx::Error> {
+        let blocks = sqlx::query_as!(
+            BlockRecord,
+            r#"
+            SELECT id, chain_id, height, hash, parent, payload_hash, creation_time
+            FROM blocks
+            WHERE ($1::SMALLINT IS NULL OR chain_id = $1)
+                AND ($2::BIGINT IS NULL OR (height, id) < ($2, $3))
+            ORDER BY height DESC, id DESC
+            LIMIT $4
+            "#,
+            chain_id,
+            after.map(|c| c.height),
+            after.map(|c| c.id),
+            limit + 1
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(Page::new(blocks, limit, |b| Cursor {
+            height: b.height,
+            id: b.id,
+        }))
+    }
+}
+
+/// Payload transaction that could not be decoded, kept with its raw base64
+/// data so it can be inspected and replayed later
+#[derive(sqlx::FromRow, Debug)]
+pub struct FailedTransaction {
+    pub id: Uuid,
+    pub chain_id: i16,
+    pub height: i64,
+    pub block_hash: String,
+    pub payload_hash: String,
+    pub request_key: Option<String>,
+    pub field: String,
+    pub error: String,
+    pub raw_transaction: String,
+    pub raw_output: Option<String>,
+}
+
+impl FailedTransaction {
+    pub fn new(header: &BlockHeader, transaction: &[String], error: &DecodeError) -> Self {
+        let context = error.context();
+        Self {
+            id: Uuid::new_v4(),
+            chain_id: context.chain_id,
+            height: context.height as i64,
+            block_hash: header.hash.clone(),
+            payload_hash: header.payload_hash.clone(),
+            request_key: context.request_key.clone(),
+            field: context.field.to_string(),
+            error: error.to_string(),
+            raw_transaction: transaction.first().cloned().unwrap_or_default(),
+            raw_output: transaction.get(1).cloned(),
+        }
+    }
+
+    pub async fn insert(self, tx: &mut Transaction<'_, Postgres>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO failed_transactions(
+                id, chain_id, height, block_hash, payload_hash, request_key,
+                field, error, raw_transaction, raw_output
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            "#,
+            self.id,
+            self.chain_id,
+            self.height,
+            self.block_hash,
+            self.payload_hash,
+            self.request_key,
+            self.field,
+            self.error,
+            self.raw_transaction,
+            self.raw_output
+        )
+        .execute(tx)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(sqlx::FromRow, serde::Serialize, Debug, Clone)]
+pub struct TransactionRecord {
+    pub id: Uuid,
+    pub request_key: String,
+    pub chain_id: i16,
+    pub height: i64,
+    pub block_hash: String,
+    pub idx: i32,
+    pub creation_time: i64,
+    pub sender: String,
+    pub nonce: String,
+    pub code: Option<String>,
+    pub data: Option<serde_json::Value>,
+    pub continuation: Option<serde_json::Value>,
+    pub gas: i64,
+    pub gas_limit: i64,
+    pub gas_price: f64,
+    pub ttl: f64,
+    pub status: String,
+    pub result: Option<serde_json::Value>,
+    pub logs: String,
+}
+
+impl TransactionRecord {
+    pub fn new(header: &BlockHeader, idx: usize, tx: &DecodedTransaction) -> Self {
+        let exec = tx.cmd.payload.exec.as_ref();
+        let result = &tx.output.result;
+        Self {
+            id: Uuid::new_v4(),
+            request_key: tx.request_key.clone(),
+            chain_id: header.chain_id as i16,
+            height: header.height as i64,
+            block_hash: header.hash.clone(),
+            idx: idx as i32,
+            creation_time: tx.cmd.meta.creation_time as i64,
+            sender: tx.cmd.meta.sender.clone(),
+            nonce: tx.cmd.nonce.clone(),
+            code: exec.map(|e| e.code.clone()),
+            data: exec.map(|e| e.data.clone()),
+            continuation: tx
+                .cmd
+                .payload
+                .cont
+                .as_ref()
+                .map(|c| serde_json::json!({ "pactId": c.pact_id, "step": c.step, "rollback": c.rollback, "data": c.data })),
+            gas: tx.output.gas as i64,
+            gas_limit: tx.cmd.meta.gas_limit as i64,
+            gas_price: tx.cmd.meta.gas_price,
+            ttl: tx.cmd.meta.ttl,
+            status: result.status.clone(),
+            result: result
+                .data
+                .clone()
+                .or_else(|| result.error.as_ref().map(|e| serde_json::json!({ "message": e.message, "type": e.error_type, "info": e.info }))),
+            logs: tx.output.logs.clone(),
+        }
+    }
+
+    pub async fn insert(self, tx: &mut Transaction<'_, Postgres>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO transactions(
+                id, request_key, chain_id, height, block_hash, idx, creation_time, sender,
+                nonce, code, data, continuation, gas, gas_limit, gas_price, ttl,
+                status, result, logs
+            )
+            VALUES (
+                $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17,
+                $18, $19
+            )
+            "#,
+            self.id,
+            self.request_key,
+            self.chain_id,
+            self.height,
+            self.block_hash,
+            self.idx,
+            self.creation_time,
+            self.sender,
+            self.nonce,
+            self.code,
+            self.data,
+            self.continuation,
+            self.gas,
+            self.gas_limit,
+            self.gas_price,
+            self.ttl,
+            self.status,
+            self.result,
+            self.logs
+        )
+        .execute(tx)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_by_request_key(
+        pool: &PgPool,
+        request_key: &str,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            TransactionRecord,
+            r#"
+            SELECT * FROM transactions
+            WHERE request_key = $1
+            ORDER BY height DESC
+            LIMIT 1
+            "#,
+            request_key
+        )
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// The transaction of a request key included in a given block
+    pub async fn find_in_block(
+        pool: &PgPool,
+        request_key: &str,
+        block_hash: &str,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            TransactionRecord,
+            r#"
+            SELECT * FROM transactions
+            WHERE request_key = $1 AND block_hash = $2
+            "#,
+            request_key,
+            block_hash
+        )
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Transactions of several blocks at once, in their order in each block
+    pub async fn list_by_blocks(
+        pool: &PgPool,
+        block_hashes: &[String],
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            TransactionRecord,
+            r#"
+            SELECT * FROM transactions
+            WHERE block_hash = ANY($1)
+            ORDER BY block_hash, idx
+            "#,
+            block_hashes
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Latest transactions first, optionally filtered by sender and chain
+    pub async fn list(
+        pool: &PgPool,
+        sender: Option<&str>,
+        chain_id: Option<i16>,
+        limit: i64,
+        after: Option<Cursor>,
+    ) -> Result<Page<Self>, sqlx::Error> {
+        let transactions = sqlx::query_as!(
+            TransactionRecord,
+            r#"
+            SELECT * FROM transactions
+            WHERE ($1::TEXT IS NULL OR sender = $1)
+                AND ($2::SMALLINT IS NULL OR chain_id = $2)
+                AND ($3::BIGINT IS NULL OR (height, id) < ($3, $4))
+            ORDER BY height DESC, id DESC
+            LIMIT $5
+            "#,
+            sender,
+            chain_id,
+            after.map(|c| c.height),
+            after.map(|c| c.id),
+            limit + 1
         )
-        .execute(pool)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(Page::new(transactions, limit, |t| Cursor {
+            height: t.height,
+            id: t.id,
+        }))
+    }
+}
+
+#[derive(sqlx::FromRow, serde::Serialize, Debug, Clone)]
+pub struct EventRecord {
+    pub id: Uuid,
+    pub request_key: String,
+    pub chain_id: i16,
+    pub height: i64,
+    pub block_hash: String,
+    pub idx: i32,
+    pub module: String,
+    pub module_hash: String,
+    pub name: String,
+    pub params: serde_json::Value,
+}
+
+impl EventRecord {
+    pub fn new(header: &BlockHeader, output: &Output, idx: usize, event: &Event) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            request_key: output.req_key.clone(),
+            chain_id: header.chain_id as i16,
+            height: header.height as i64,
+            block_hash: header.hash.clone(),
+            idx: idx as i32,
+            module: event.module.qualified_name(),
+            module_hash: event.module_hash.clone(),
+            name: event.name.clone(),
+            params: serde_json::Value::Array(event.params.clone()),
+        }
+    }
+
+    pub async fn insert(self, tx: &mut Transaction<'_, Postgres>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO events(
+                id, request_key, chain_id, height, block_hash, idx, module, module_hash,
+                name, params
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            "#,
+            self.id,
+            self.request_key,
+            self.chain_id,
+            self.height,
+            self.block_hash,
+            self.idx,
+            self.module,
+            self.module_hash,
+            self.name,
+            self.params
+        )
+        .execute(tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Events of several transactions at once, by request key and block hash
+    pub async fn list_by_transactions(
+        pool: &PgPool,
+        transactions: &[(String, String)],
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let (request_keys, block_hashes): (Vec<_>, Vec<_>) = transactions.iter().cloned().unzip();
+        sqlx::query_as!(
+            EventRecord,
+            r#"
+            SELECT events.* FROM events
+            JOIN UNNEST($1::TEXT[], $2::TEXT[]) AS t(request_key, block_hash)
+                USING (request_key, block_hash)
+            ORDER BY events.request_key, events.block_hash, events.idx
+            "#,
+            &request_keys,
+            &block_hashes
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Latest events first, optionally filtered by qualified module name,
+    /// event name and chain
+    pub async fn list(
+        pool: &PgPool,
+        module: Option<&str>,
+        name: Option<&str>,
+        chain_id: Option<i16>,
+        limit: i64,
+        after: Option<Cursor>,
+    ) -> Result<Page<Self>, sqlx::Error> {
+        let events = sqlx::query_as!(
+            EventRecord,
+            r#"
+            SELECT * FROM events
+            WHERE ($1::TEXT IS NULL OR module = $1)
+                AND ($2::TEXT IS NULL OR name = $2)
+                AND ($3::SMALLINT IS NULL OR chain_id = $3)
+                AND ($4::BIGINT IS NULL OR (height, id) < ($4, $5))
+            ORDER BY height DESC, id DESC
+            LIMIT $6
+            "#,
+            module,
+            name,
+            chain_id,
+            after.map(|c| c.height),
+            after.map(|c| c.id),
+            limit + 1
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(Page::new(events, limit, |e| Cursor {
+            height: e.height,
+            id: e.id,
+        }))
+    }
+}
+
+/// Module function called by the code of a transaction
+#[derive(sqlx::FromRow, serde::Serialize, Debug, Clone)]
+pub struct TransactionCall {
+    pub id: Uuid,
+    pub request_key: String,
+    pub chain_id: i16,
+    pub height: i64,
+    pub block_hash: String,
+    /// Order of the call within the transaction code
+    pub idx: i32,
+    pub module: String,
+    pub function: String,
+    /// Literal arguments, `null` for the ones computed by the code
+    pub args: serde_json::Value,
+}
+
+impl TransactionCall {
+    pub fn new(
+        header: &BlockHeader,
+        tx: &DecodedTransaction,
+        idx: usize,
+        call: FunctionCall,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            request_key: tx.request_key.clone(),
+            chain_id: header.chain_id as i16,
+            height: header.height as i64,
+            block_hash: header.hash.clone(),
+            idx: idx as i32,
+            module: call.module,
+            function: call.function,
+            args: serde_json::Value::from(call.args),
+        }
+    }
+
+    pub async fn insert(self, tx: &mut Transaction<'_, Postgres>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO transaction_calls(
+                id, request_key, chain_id, height, block_hash, idx, module, function, args
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#,
+            self.id,
+            self.request_key,
+            self.chain_id,
+            self.height,
+            self.block_hash,
+            self.idx,
+            self.module,
+            self.function,
+            self.args
+        )
+        .execute(tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove the rows of a chain and an inclusive height range
+    pub async fn delete(
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+        min_height: i64,
+        max_height: i64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM transaction_calls WHERE chain_id = $1 AND height BETWEEN $2 AND $3",
+            chain_id,
+            min_height,
+            max_height
+        )
+        .execute(tx)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn list_by_transaction(
+        pool: &PgPool,
+        request_key: &str,
+        block_hash: &str,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            TransactionCall,
+            r#"
+            SELECT * FROM transaction_calls
+            WHERE request_key = $1 AND block_hash = $2
+            ORDER BY idx
+            "#,
+            request_key,
+            block_hash
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Latest calls first, optionally filtered by qualified module name,
+    /// function name and chain
+    pub async fn list(
+        pool: &PgPool,
+        module: Option<&str>,
+        function: Option<&str>,
+        chain_id: Option<i16>,
+        limit: i64,
+        after: Option<Cursor>,
+    ) -> Result<Page<Self>, sqlx::Error> {
+        let calls = sqlx::query_as!(
+            TransactionCall,
+            r#"
+            SELECT * FROM transaction_calls
+            WHERE ($1::TEXT IS NULL OR module = $1)
+                AND ($2::TEXT IS NULL OR function = $2)
+                AND ($3::SMALLINT IS NULL OR chain_id = $3)
+                AND ($4::BIGINT IS NULL OR (height, id) < ($4, $5))
+            ORDER BY height DESC, id DESC
+            LIMIT $6
+            "#,
+            module,
+            function,
+            chain_id,
+            after.map(|c| c.height),
+            after.map(|c| c.id),
+            limit + 1
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(Page::new(calls, limit, |c| Cursor {
+            height: c.height,
+            id: c.id,
+        }))
+    }
+}
+
+/// Fungible transfer derived from a `TRANSFER` event
+#[derive(sqlx::FromRow, serde::Serialize, Debug, Clone)]
+pub struct Transfer {
+    pub id: Uuid,
+    pub request_key: String,
+    pub chain_id: i16,
+    pub height: i64,
+    pub block_hash: String,
+    pub idx: i32,
+    pub module: String,
+    pub sender: String,
+    pub receiver: String,
+    pub amount: BigDecimal,
+}
+
+impl Transfer {
+    /// Fungible `TRANSFER` events carry `[sender, receiver, amount]`.
+    /// Returns `None` for any other event.
+    pub fn from_event(
+        header: &BlockHeader,
+        output: &Output,
+        idx: usize,
+        event: &Event,
+    ) -> Option<Self> {
+        if event.name != "TRANSFER" || event.params.len() != 3 {
+            return None;
+        }
+        Some(Self {
+            id: Uuid::new_v4(),
+            request_key: output.req_key.clone(),
+            chain_id: header.chain_id as i16,
+            height: header.height as i64,
+            block_hash: header.hash.clone(),
+            idx: idx as i32,
+            module: event.module.qualified_name(),
+            sender: event.params[0].as_str()?.to_string(),
+            receiver: event.params[1].as_str()?.to_string(),
+            amount: parse_pact_decimal(&event.params[2])?,
+        })
+    }
+
+    pub async fn insert(self, tx: &mut Transaction<'_, Postgres>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO transfers(
+                id, request_key, chain_id, height, block_hash, idx, module, sender,
+                receiver, amount
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            "#,
+            self.id,
+            self.request_key,
+            self.chain_id,
+            self.height,
+            self.block_hash,
+            self.idx,
+            self.module,
+            self.sender,
+            self.receiver,
+            self.amount
+        )
+        .execute(tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove the rows of a chain and an inclusive height range
+    pub async fn delete(
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+        min_height: i64,
+        max_height: i64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM transfers WHERE chain_id = $1 AND height BETWEEN $2 AND $3",
+            chain_id,
+            min_height,
+            max_height
+        )
+        .execute(tx)
         .await?;
 
         Ok(())
     }
+
+    pub async fn list_by_transaction(
+        pool: &PgPool,
+        request_key: &str,
+        block_hash: &str,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            Transfer,
+            r#"
+            SELECT * FROM transfers
+            WHERE request_key = $1 AND block_hash = $2
+            ORDER BY idx
+            "#,
+            request_key,
+            block_hash
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Latest transfers first, optionally filtered by account (sender or
+    /// receiver), qualified token module name and chain
+    pub async fn list(
+        pool: &PgPool,
+        account: Option<&str>,
+        module: Option<&str>,
+        chain_id: Option<i16>,
+        limit: i64,
+        after: Option<Cursor>,
+    ) -> Result<Page<Self>, sqlx::Error> {
+        let transfers = sqlx::query_as!(
+            Transfer,
+            r#"
+            SELECT * FROM transfers
+            WHERE ($1::TEXT IS NULL OR sender = $1 OR receiver = $1)
+                AND ($2::TEXT IS NULL OR module = $2)
+                AND ($3::SMALLINT IS NULL OR chain_id = $3)
+                AND ($4::BIGINT IS NULL OR (height, id) < ($4, $5))
+            ORDER BY height DESC, id DESC
+            LIMIT $6
+            "#,
+            account,
+            module,
+            chain_id,
+            after.map(|c| c.height),
+            after.map(|c| c.id),
+            limit + 1
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(Page::new(transfers, limit, |t| Cursor {
+            height: t.height,
+            id: t.id,
+        }))
+    }
+}
+
+/// Guard set on an account by a transaction
+#[derive(sqlx::FromRow, serde::Serialize, Debug, Clone)]
+pub struct AccountGuard {
+    pub id: Uuid,
+    pub account: String,
+    pub chain_id: i16,
+    pub module: String,
+    pub height: i64,
+    pub block_hash: String,
+    pub request_key: String,
+    /// Order of the change within the block
+    pub idx: i32,
+    pub kind: String,
+    pub guard: Option<serde_json::Value>,
+}
+
+impl AccountGuard {
+    pub fn new(
+        header: &BlockHeader,
+        tx: &DecodedTransaction,
+        idx: usize,
+        change: GuardChange,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            account: change.account,
+            chain_id: header.chain_id as i16,
+            module: change.module,
+            height: header.height as i64,
+            block_hash: header.hash.clone(),
+            request_key: tx.request_key.clone(),
+            idx: idx as i32,
+            kind: change.kind.as_str().to_string(),
+            guard: change.guard,
+        }
+    }
+
+    /// `transfer-create` of an existing account keeps its guard, so creations
+    /// after the first guard of the account are skipped
+    pub async fn insert(self, tx: &mut Transaction<'_, Postgres>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO account_guards(
+                id, account, chain_id, module, height, block_hash, request_key, idx, kind, guard
+            )
+            SELECT $1, $2, $3, $4, $5, $6, $7, $8, $9, $10
+            WHERE $9 <> 'create' OR NOT EXISTS (
+                SELECT 1 FROM account_guards
+                WHERE account = $2 AND chain_id = $3 AND module = $4
+                    AND (height, idx) < ($5, $8)
+            )
+            "#,
+            self.id,
+            self.account,
+            self.chain_id,
+            self.module,
+            self.height,
+            self.block_hash,
+            self.request_key,
+            self.idx,
+            self.kind,
+            self.guard
+        )
+        .execute(tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove the rows of a chain and an inclusive height range
+    pub async fn delete(
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+        min_height: i64,
+        max_height: i64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM account_guards WHERE chain_id = $1 AND height BETWEEN $2 AND $3",
+            chain_id,
+            min_height,
+            max_height
+        )
+        .execute(tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Guard history of an account, latest first
+    pub async fn list(
+        pool: &PgPool,
+        account: &str,
+        chain_id: Option<i16>,
+        module: Option<&str>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            AccountGuard,
+            r#"
+            SELECT * FROM account_guards
+            WHERE account = $1
+                AND ($2::SMALLINT IS NULL OR chain_id = $2)
+                AND ($3::TEXT IS NULL OR module = $3)
+            ORDER BY height DESC, idx DESC
+            "#,
+            account,
+            chain_id,
+            module
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+/// Account of a token on a chain
+#[derive(sqlx::FromRow, serde::Serialize, Debug, Clone)]
+pub struct Account {
+    pub account: String,
+    pub chain_id: i16,
+    pub module: String,
+    /// First transfer or guard change of the account
+    pub first_seen_height: i64,
+    pub first_seen_request_key: String,
+    /// Latest known guard, `None` when it could not be decoded
+    pub guard: Option<serde_json::Value>,
+    pub guard_height: Option<i64>,
+}
+
+impl Account {
+    ///
+    /// Refresh the accounts of a chain seen or given a guard from
+    /// `min_height` on, using the transfers and account guards tables.
+    pub async fn rebuild(
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+        min_height: i64,
+    ) -> Result<(), sqlx::Error> {
+        let removed = sqlx::query!(
+            r#"
+            DELETE FROM accounts
+            WHERE chain_id = $1 AND (first_seen_height >= $2 OR guard_height >= $2)
+            RETURNING account, module
+            "#,
+            chain_id,
+            min_height
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        let (accounts, modules): (Vec<String>, Vec<String>) =
+            removed.into_iter().map(|r| (r.account, r.module)).unzip();
+
+        // Accounts still there were first seen before the range, only a new
+        // guard can change them
+        sqlx::query!(
+            r#"
+            WITH affected AS (
+                SELECT * FROM UNNEST($3::TEXT[], $4::TEXT[]) AS a(account, module)
+                UNION
+                SELECT account, module FROM account_guards
+                WHERE chain_id = $1 AND height >= $2
+                UNION
+                SELECT t.account, t.module FROM (
+                    SELECT receiver AS account, module FROM transfers
+                    WHERE chain_id = $1 AND height >= $2 AND receiver <> ''
+                    UNION
+                    SELECT sender, module FROM transfers
+                    WHERE chain_id = $1 AND height >= $2 AND sender <> ''
+                ) AS t
+                WHERE NOT EXISTS (
+                    SELECT 1 FROM accounts x
+                    WHERE x.chain_id = $1 AND x.account = t.account AND x.module = t.module
+                )
+            )
+            INSERT INTO accounts(
+                account, chain_id, module, first_seen_height, first_seen_request_key, guard,
+                guard_height
+            )
+            SELECT a.account, $1, a.module, f.height, f.request_key, g.guard, g.height
+            FROM affected a
+            CROSS JOIN LATERAL (
+                SELECT height, request_key FROM (
+                    (
+                        SELECT height, request_key FROM transfers
+                        WHERE receiver = a.account AND chain_id = $1 AND module = a.module
+                        ORDER BY height LIMIT 1
+                    )
+                    UNION ALL
+                    (
+                        SELECT height, request_key FROM transfers
+                        WHERE sender = a.account AND chain_id = $1 AND module = a.module
+                        ORDER BY height LIMIT 1
+                    )
+                    UNION ALL
+                    (
+                        SELECT height, request_key FROM account_guards
+                        WHERE account = a.account AND chain_id = $1 AND module = a.module
+                        ORDER BY height, idx LIMIT 1
+                    )
+                ) sightings
+                ORDER BY height LIMIT 1
+            ) f
+            LEFT JOIN LATERAL (
+                SELECT guard, height FROM account_guards
+                WHERE account = a.account AND chain_id = $1 AND module = a.module
+                ORDER BY height DESC, idx DESC LIMIT 1
+            ) g ON true
+            ON CONFLICT (account, chain_id, module)
+            DO
+                UPDATE SET first_seen_height = EXCLUDED.first_seen_height,
+                    first_seen_request_key = EXCLUDED.first_seen_request_key,
+                    guard = EXCLUDED.guard,
+                    guard_height = EXCLUDED.guard_height
+            "#,
+            chain_id,
+            min_height,
+            &accounts,
+            &modules
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// An account on every chain and token it was seen on
+    pub async fn list(
+        pool: &PgPool,
+        account: &str,
+        chain_id: Option<i16>,
+        module: Option<&str>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            Account,
+            r#"
+            SELECT * FROM accounts
+            WHERE account = $1
+                AND ($2::SMALLINT IS NULL OR chain_id = $2)
+                AND ($3::TEXT IS NULL OR module = $3)
+            ORDER BY chain_id, module
+            "#,
+            account,
+            chain_id,
+            module
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+/// Balance of an account for a token on a chain, as of `height`
+#[derive(sqlx::FromRow, serde::Serialize, Debug, Clone)]
+pub struct Balance {
+    pub account: String,
+    pub chain_id: i16,
+    pub module: String,
+    pub balance: BigDecimal,
+    pub height: i64,
+}
+
+impl Balance {
+    ///
+    /// Recompute the balance snapshots of a chain from `min_height` on using
+    /// the transfers table, then refresh the latest balances they affect.
+    /// Transfers from `""` mint and transfers to `""` burn, so only real
+    /// accounts get a balance.
+    pub async fn rebuild(
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+        min_height: i64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM balance_snapshots WHERE chain_id = $1 AND height >= $2",
+            chain_id,
+            min_height
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            r#"
+            WITH deltas AS (
+                SELECT receiver AS account, module, height, block_hash, amount AS delta
+                FROM transfers
+                WHERE chain_id = $1 AND height >= $2 AND receiver <> ''
+                UNION ALL
+                SELECT sender, module, height, block_hash, -amount
+                FROM transfers
+                WHERE chain_id = $1 AND height >= $2 AND sender <> ''
+            ), block_deltas AS (
+                SELECT account, module, height, block_hash, SUM(delta) AS delta
+                FROM deltas
+                GROUP BY account, module, height, block_hash
+            )
+            INSERT INTO balance_snapshots(account, chain_id, module, height, block_hash, delta, balance)
+            SELECT d.account, $1, d.module, d.height, d.block_hash, d.delta,
+                COALESCE(base.balance, 0)
+                    + SUM(d.delta) OVER (PARTITION BY d.account, d.module ORDER BY d.height)
+            FROM block_deltas d
+            LEFT JOIN LATERAL (
+                SELECT balance FROM balance_snapshots s
+                WHERE s.account = d.account AND s.chain_id = $1 AND s.module = d.module
+                    AND s.height < $2
+                ORDER BY s.height DESC
+                LIMIT 1
+            ) base ON true
+            "#,
+            chain_id,
+            min_height
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        // Balances last changed in the rebuilt range may now come from an
+        // older snapshot
+        let removed = sqlx::query!(
+            r#"
+            DELETE FROM balances WHERE chain_id = $1 AND height >= $2
+            RETURNING account, module
+            "#,
+            chain_id,
+            min_height
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        let (accounts, modules): (Vec<String>, Vec<String>) =
+            removed.into_iter().map(|r| (r.account, r.module)).unzip();
+        sqlx::query!(
+            r#"
+            WITH affected AS (
+                SELECT * FROM UNNEST($3::TEXT[], $4::TEXT[]) AS a(account, module)
+                UNION
+                SELECT account, module FROM balance_snapshots
+                WHERE chain_id = $1 AND height >= $2
+            )
+            INSERT INTO balances(account, chain_id, module, balance, height)
+            SELECT DISTINCT ON (s.account, s.module) s.account, $1, s.module, s.balance, s.height
+            FROM balance_snapshots s
+            JOIN affected a ON a.account = s.account AND a.module = s.module
+            WHERE s.chain_id = $1
+            ORDER BY s.account, s.module, s.height DESC
+            ON CONFLICT (account, chain_id, module)
+            DO
+                UPDATE SET balance = EXCLUDED.balance, height = EXCLUDED.height
+            "#,
+            chain_id,
+            min_height,
+            &accounts,
+            &modules
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        Ok(())
+    }
+
+    ///
+    /// Balances of an account, optionally for one chain and token. With a
+    /// height, the balances as of that block instead of the latest ones.
+    pub async fn list(
+        pool: &PgPool,
+        account: &str,
+        chain_id: Option<i16>,
+        module: Option<&str>,
+        height: Option<i64>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        match height {
+            None => {
+                sqlx::query_as!(
+                    Balance,
+                    r#"
+                    SELECT account, chain_id, module, balance, height FROM balances
+                    WHERE account = $1
+                        AND ($2::SMALLINT IS NULL OR chain_id = $2)
+                        AND ($3::TEXT IS NULL OR module = $3)
+                    ORDER BY chain_id, module
+                    "#,
+                    account,
+                    chain_id,
+                    module
+                )
+                .fetch_all(pool)
+                .await
+            }
+            Some(height) => {
+                sqlx::query_as!(
+                    Balance,
+                    r#"
+                    SELECT DISTINCT ON (chain_id, module)
+                        account, chain_id, module, balance, height
+                    FROM balance_snapshots
+                    WHERE account = $1
+                        AND ($2::SMALLINT IS NULL OR chain_id = $2)
+                        AND ($3::TEXT IS NULL OR module = $3)
+                        AND height <= $4
+                    ORDER BY chain_id, module, height DESC
+                    "#,
+                    account,
+                    chain_id,
+                    module,
+                    height
+                )
+                .fetch_all(pool)
+                .await
+            }
+        }
+    }
+
+    /// Latest balances of a token on a chain, highest first
+    pub async fn list_by_module(
+        pool: &PgPool,
+        chain_id: i16,
+        module: &str,
+        limit: i64,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            Balance,
+            r#"
+            SELECT account, chain_id, module, balance, height FROM balances
+            WHERE chain_id = $1 AND module = $2
+            ORDER BY balance DESC
+            LIMIT $3
+            "#,
+            chain_id,
+            module,
+            limit
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+/// Gas used and paid by the transactions of a block
+#[derive(sqlx::FromRow, serde::Serialize, Debug, Clone)]
+pub struct BlockGas {
+    pub id: Uuid,
+    pub chain_id: i16,
+    pub height: i64,
+    pub block_hash: String,
+    /// Microseconds since epoch
+    pub creation_time: i64,
+    pub transactions: i32,
+    pub total_gas: i64,
+    /// Sum of `gas * gas_price`
+    pub total_fees: BigDecimal,
+    pub min_gas_price: Option<f64>,
+    pub mean_gas_price: Option<f64>,
+    pub median_gas_price: Option<f64>,
+    pub p90_gas_price: Option<f64>,
+    pub max_gas_price: Option<f64>,
+    pub block_gas_limit: i64,
+    /// `total_gas / block_gas_limit`
+    pub fullness: f64,
+}
+
+impl BlockGas {
+    pub fn new(header: &BlockHeader, txs: &[DecodedTransaction], block_gas_limit: u64) -> Self {
+        let mut prices = txs
+            .iter()
+            .map(|t| t.cmd.meta.gas_price)
+            .collect::<Vec<f64>>();
+        prices.sort_by(f64::total_cmp);
+        let total_gas = txs.iter().map(|t| t.output.gas).sum::<u64>();
+        let total_fees = txs
+            .iter()
+            .map(|t| BigDecimal::from(t.output.gas) * decimal_from_f64(t.cmd.meta.gas_price))
+            .sum();
+        let mean_gas_price = match prices.len() {
+            0 => None,
+            n => Some(prices.iter().sum::<f64>() / n as f64),
+        };
+        let fullness = match block_gas_limit {
+            0 => 0.0,
+            limit => total_gas as f64 / limit as f64,
+        };
+        Self {
+            id: Uuid::new_v4(),
+            chain_id: header.chain_id as i16,
+            height: header.height as i64,
+            block_hash: header.hash.clone(),
+            creation_time: header.creation_time as i64,
+            transactions: txs.len() as i32,
+            total_gas: total_gas as i64,
+            total_fees,
+            min_gas_price: prices.first().copied(),
+            mean_gas_price,
+            median_gas_price: percentile(&prices, 0.5),
+            p90_gas_price: percentile(&prices, 0.9),
+            max_gas_price: prices.last().copied(),
+            block_gas_limit: block_gas_limit as i64,
+            fullness,
+        }
+    }
+
+    pub async fn insert(self, tx: &mut Transaction<'_, Postgres>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO block_gas(
+                id, chain_id, height, block_hash, creation_time, transactions, total_gas,
+                total_fees, min_gas_price, mean_gas_price, median_gas_price, p90_gas_price,
+                max_gas_price, block_gas_limit, fullness
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
+            "#,
+            self.id,
+            self.chain_id,
+            self.height,
+            self.block_hash,
+            self.creation_time,
+            self.transactions,
+            self.total_gas,
+            self.total_fees,
+            self.min_gas_price,
+            self.mean_gas_price,
+            self.median_gas_price,
+            self.p90_gas_price,
+            self.max_gas_price,
+            self.block_gas_limit,
+            self.fullness
+        )
+        .execute(tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove the rows of a chain and an inclusive height range
+    pub async fn delete(
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+        min_height: i64,
+        max_height: i64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM block_gas WHERE chain_id = $1 AND height BETWEEN $2 AND $3",
+            chain_id,
+            min_height,
+            max_height
+        )
+        .execute(tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Latest blocks first, optionally of a single chain
+    pub async fn list(
+        pool: &PgPool,
+        chain_id: Option<i16>,
+        limit: i64,
+        after: Option<Cursor>,
+    ) -> Result<Page<Self>, sqlx::Error> {
+        let blocks = sqlx::query_as!(
+            BlockGas,
+            r#"
+            SELECT * FROM block_gas
+            WHERE ($1::SMALLINT IS NULL OR chain_id = $1)
+                AND ($2::BIGINT IS NULL OR (height, id) < ($2, $3))
+            ORDER BY height DESC, id DESC
+            LIMIT $4
+            "#,
+            chain_id,
+            after.map(|c| c.height),
+            after.map(|c| c.id),
+            limit + 1
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(Page::new(blocks, limit, |b| Cursor {
+            height: b.height,
+            id: b.id,
+        }))
+    }
+}
+
+/// Gas of the blocks of a chain created in an hour
+#[derive(sqlx::FromRow, serde::Serialize, Debug, Clone)]
+pub struct HourlyGas {
+    pub chain_id: i16,
+    pub hour: DateTime<Utc>,
+    pub blocks: i32,
+    pub transactions: i32,
+    pub total_gas: i64,
+    pub total_fees: BigDecimal,
+    pub mean_gas_price: Option<f64>,
+    pub median_gas_price: Option<f64>,
+    pub p90_gas_price: Option<f64>,
+    /// Sum of the gas limits of the blocks
+    pub block_gas_limit: i64,
+    pub fullness: f64,
+    pub min_height: i64,
+    pub max_height: i64,
+}
+
+impl HourlyGas {
+    ///
+    /// Recompute the hours of a chain with blocks from `min_height` on, and
+    /// the hours that had such blocks before, from the block gas table. Gas
+    /// price statistics are computed over the transactions of the hour.
+    pub async fn rebuild(
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+        min_height: i64,
+    ) -> Result<(), sqlx::Error> {
+        let removed = sqlx::query!(
+            r#"
+            DELETE FROM hourly_gas WHERE chain_id = $1 AND max_height >= $2
+            RETURNING hour
+            "#,
+            chain_id,
+            min_height
+        )
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|r| r.hour)
+        .collect::<Vec<DateTime<Utc>>>();
+
+        // Blocks of a chain created in an hour have consecutive heights
+        sqlx::query!(
+            r#"
+            WITH hours AS (
+                SELECT UNNEST($3::TIMESTAMPTZ[]) AS hour
+                UNION
+                SELECT DISTINCT date_trunc('hour', to_timestamp(creation_time / 1000000.0) AT TIME ZONE 'UTC') AT TIME ZONE 'UTC'
+                FROM block_gas
+                WHERE chain_id = $1 AND height >= $2
+            )
+            INSERT INTO hourly_gas(
+                chain_id, hour, blocks, transactions, total_gas, total_fees, mean_gas_price,
+                median_gas_price, p90_gas_price, block_gas_limit, fullness, min_height, max_height
+            )
+            SELECT $1, h.hour, b.blocks::INTEGER, b.transactions::INTEGER, b.total_gas::BIGINT,
+                b.total_fees, p.mean, p.median, p.p90, b.block_gas_limit::BIGINT,
+                COALESCE(b.total_gas::DOUBLE PRECISION / NULLIF(b.block_gas_limit, 0), 0),
+                b.min_height, b.max_height
+            FROM hours h
+            CROSS JOIN LATERAL (
+                SELECT COUNT(*) AS blocks, SUM(transactions) AS transactions,
+                    SUM(total_gas) AS total_gas, SUM(total_fees) AS total_fees,
+                    SUM(block_gas_limit) AS block_gas_limit, MIN(height) AS min_height,
+                    MAX(height) AS max_height
+                FROM block_gas
+                WHERE chain_id = $1
+                    AND creation_time >= EXTRACT(EPOCH FROM h.hour) * 1000000
+                    AND creation_time < EXTRACT(EPOCH FROM h.hour + INTERVAL '1 hour') * 1000000
+            ) b
+            CROSS JOIN LATERAL (
+                SELECT AVG(gas_price) AS mean,
+                    PERCENTILE_CONT(0.5) WITHIN GROUP (ORDER BY gas_price) AS median,
+                    PERCENTILE_CONT(0.9) WITHIN GROUP (ORDER BY gas_price) AS p90
+                FROM transactions
+                WHERE chain_id = $1 AND height BETWEEN b.min_height AND b.max_height
+            ) p
+            WHERE b.blocks > 0
+            ON CONFLICT (chain_id, hour)
+            DO
+                UPDATE SET blocks = EXCLUDED.blocks,
+                    transactions = EXCLUDED.transactions,
+                    total_gas = EXCLUDED.total_gas,
+                    total_fees = EXCLUDED.total_fees,
+                    mean_gas_price = EXCLUDED.mean_gas_price,
+                    median_gas_price = EXCLUDED.median_gas_price,
+                    p90_gas_price = EXCLUDED.p90_gas_price,
+                    block_gas_limit = EXCLUDED.block_gas_limit,
+                    fullness = EXCLUDED.fullness,
+                    min_height = EXCLUDED.min_height,
+                    max_height = EXCLUDED.max_height
+            "#,
+            chain_id,
+            min_height,
+            &removed
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Latest hours first, optionally of a single chain and a time range
+    pub async fn list(
+        pool: &PgPool,
+        chain_id: Option<i16>,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        limit: i64,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            HourlyGas,
+            r#"
+            SELECT * FROM hourly_gas
+            WHERE ($1::SMALLINT IS NULL OR chain_id = $1)
+                AND ($2::TIMESTAMPTZ IS NULL OR hour >= $2)
+                AND ($3::TIMESTAMPTZ IS NULL OR hour < $3)
+            ORDER BY hour DESC, chain_id
+            LIMIT $4
+            "#,
+            chain_id,
+            from,
+            to,
+            limit
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+/// Gas of a chain over a time range, summed from the hourly aggregates
+#[derive(sqlx::FromRow, serde::Serialize, Debug, Clone)]
+pub struct ChainGas {
+    pub chain_id: i16,
+    pub blocks: i64,
+    pub transactions: i64,
+    pub total_gas: i64,
+    pub total_fees: BigDecimal,
+    /// Mean gas price of the transactions
+    pub mean_gas_price: Option<f64>,
+    pub fullness: f64,
+}
+
+impl ChainGas {
+    pub async fn list(
+        pool: &PgPool,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            ChainGas,
+            r#"
+            SELECT chain_id,
+                SUM(blocks)::BIGINT AS "blocks!",
+                SUM(transactions)::BIGINT AS "transactions!",
+                SUM(total_gas)::BIGINT AS "total_gas!",
+                SUM(total_fees) AS "total_fees!",
+                SUM(mean_gas_price * transactions) / NULLIF(SUM(transactions), 0) AS mean_gas_price,
+                COALESCE(SUM(total_gas)::DOUBLE PRECISION / NULLIF(SUM(block_gas_limit), 0), 0) AS "fullness!"
+            FROM hourly_gas
+            WHERE ($1::TIMESTAMPTZ IS NULL OR hour >= $1)
+                AND ($2::TIMESTAMPTZ IS NULL OR hour < $2)
+            GROUP BY chain_id
+            ORDER BY chain_id
+            "#,
+            from,
+            to
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+/// Module, interface or namespace defined by a transaction
+#[derive(sqlx::FromRow, serde::Serialize, Debug, Clone)]
+pub struct ModuleDeployment {
+    pub id: Uuid,
+    pub chain_id: i16,
+    pub height: i64,
+    pub block_hash: String,
+    pub request_key: String,
+    /// Order of the deployment within the block
+    pub idx: i32,
+    /// `module`, `interface` or `namespace`
+    pub kind: String,
+    pub namespace: Option<String>,
+    pub name: String,
+    pub qualified_name: String,
+    /// Module hash as in `Event::module_hash`, unknown until the module emits
+    /// an event when the definition is not the last expression of the
+    /// transaction
+    pub hash: Option<String>,
+    pub governance: Option<String>,
+    /// `keyset` or `capability`
+    pub governance_kind: Option<String>,
+    pub code: String,
+    /// Position in the history of the definition on its chain, starting at
+    /// 1, only set when read from the database
+    pub version: Option<i64>,
+}
+
+impl ModuleDeployment {
+    pub fn new(
+        header: &BlockHeader,
+        tx: &DecodedTransaction,
+        idx: usize,
+        deployment: Deployment,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            chain_id: header.chain_id as i16,
+            height: header.height as i64,
+            block_hash: header.hash.clone(),
+            request_key: tx.request_key.clone(),
+            idx: idx as i32,
+            kind: deployment.kind.as_str().to_string(),
+            qualified_name: deployment.qualified_name(),
+            namespace: deployment.namespace,
+            name: deployment.name,
+            hash: deployment.hash,
+            governance: deployment.governance,
+            governance_kind: deployment.governance_kind.map(String::from),
+            code: deployment.code,
+            version: None,
+        }
+    }
+
+    pub async fn insert(self, tx: &mut Transaction<'_, Postgres>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO module_deployments(
+                id, chain_id, height, block_hash, request_key, idx, kind, namespace, name,
+                qualified_name, hash, governance, governance_kind, code
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+            "#,
+            self.id,
+            self.chain_id,
+            self.height,
+            self.block_hash,
+            self.request_key,
+            self.idx,
+            self.kind,
+            self.namespace,
+            self.name,
+            self.qualified_name,
+            self.hash,
+            self.governance,
+            self.governance_kind,
+            self.code
+        )
+        .execute(tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove the rows of a chain and an inclusive height range
+    pub async fn delete(
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+        min_height: i64,
+        max_height: i64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM module_deployments WHERE chain_id = $1 AND height BETWEEN $2 AND $3",
+            chain_id,
+            min_height,
+            max_height
+        )
+        .execute(tx)
+        .await?;
+
+        Ok(())
+    }
+
+    ///
+    /// Set the unknown hashes of the modules of a chain from the first event
+    /// each module emits in a later block, before it is deployed again
+    pub async fn fill_hashes(
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            UPDATE module_deployments d
+            SET hash = (
+                SELECT e.module_hash
+                FROM events e
+                WHERE e.chain_id = d.chain_id
+                    AND e.module = d.qualified_name
+                    AND e.height > d.height
+                    AND NOT EXISTS (
+                        SELECT 1 FROM module_deployments n
+                        WHERE n.chain_id = d.chain_id
+                            AND n.qualified_name = d.qualified_name
+                            AND n.height > d.height
+                            AND n.height <= e.height
+                    )
+                ORDER BY e.height
+                LIMIT 1
+            )
+            WHERE d.chain_id = $1 AND d.kind = 'module' AND d.hash IS NULL
+            "#,
+            chain_id
+        )
+        .execute(tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Every version of a module, interface or namespace, latest first
+    pub async fn history(
+        pool: &PgPool,
+        qualified_name: &str,
+        chain_id: Option<i16>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            ModuleDeployment,
+            r#"
+            SELECT *, ROW_NUMBER() OVER (
+                PARTITION BY chain_id ORDER BY height, idx
+            ) AS version
+            FROM module_deployments
+            WHERE qualified_name = $1 AND ($2::SMALLINT IS NULL OR chain_id = $2)
+            ORDER BY height DESC, idx DESC
+            "#,
+            qualified_name,
+            chain_id
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    ///
+    /// Latest version of every module, interface and namespace, most recently
+    /// deployed first
+    pub async fn list(
+        pool: &PgPool,
+        chain_id: Option<i16>,
+        namespace: Option<&str>,
+        kind: Option<&str>,
+        limit: i64,
+        after: Option<Cursor>,
+    ) -> Result<Page<Self>, sqlx::Error> {
+        let deployments = sqlx::query_as!(
+            ModuleDeployment,
+            r#"
+            SELECT id AS "id!", chain_id AS "chain_id!", height AS "height!",
+                block_hash AS "block_hash!", request_key AS "request_key!", idx AS "idx!",
+                kind AS "kind!", namespace, name AS "name!", qualified_name AS "qualified_name!",
+                hash, governance, governance_kind, code AS "code!", version
+            FROM (
+                SELECT DISTINCT ON (chain_id, qualified_name) *
+                FROM (
+                    SELECT *, ROW_NUMBER() OVER (
+                        PARTITION BY chain_id, qualified_name ORDER BY height, idx
+                    ) AS version
+                    FROM module_deployments
+                    WHERE ($1::SMALLINT IS NULL OR chain_id = $1)
+                        AND ($2::TEXT IS NULL OR namespace = $2)
+                        AND ($3::TEXT IS NULL OR kind = $3)
+                ) versions
+                ORDER BY chain_id, qualified_name, height DESC, idx DESC
+            ) latest
+            WHERE ($4::BIGINT IS NULL OR (height, id) < ($4, $5))
+            ORDER BY height DESC, id DESC
+            LIMIT $6
+            "#,
+            chain_id,
+            namespace,
+            kind,
+            after.map(|c| c.height),
+            after.map(|c| c.id),
+            limit + 1
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(Page::new(deployments, limit, |d| Cursor {
+            height: d.height,
+            id: d.id,
+        }))
+    }
+}
+
+/// Marmalade or poly-fungible-v2 token event
+#[derive(sqlx::FromRow, serde::Serialize, Debug, Clone)]
+pub struct NftEventRecord {
+    pub id: Uuid,
+    pub request_key: String,
+    pub chain_id: i16,
+    pub height: i64,
+    pub block_hash: String,
+    /// Order of the event within the block
+    pub idx: i32,
+    pub module: String,
+    pub token_id: String,
+    /// `token`, `mint`, `burn`, `transfer`, `reconcile`, `supply`, `sale`,
+    /// `buy` or `withdraw`
+    pub kind: String,
+    pub sender: Option<String>,
+    pub receiver: Option<String>,
+    pub amount: Option<BigDecimal>,
+    pub sender_balance: Option<BigDecimal>,
+    pub receiver_balance: Option<BigDecimal>,
+    pub sale_id: Option<String>,
+    pub params: serde_json::Value,
+}
+
+impl NftEventRecord {
+    pub fn new(
+        header: &BlockHeader,
+        output: &Output,
+        idx: usize,
+        event: &Event,
+        decoded: NftEvent,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            request_key: output.req_key.clone(),
+            chain_id: header.chain_id as i16,
+            height: header.height as i64,
+            block_hash: header.hash.clone(),
+            idx: idx as i32,
+            module: event.module.qualified_name(),
+            token_id: decoded.token_id,
+            kind: decoded.kind.as_str().to_string(),
+            sender: decoded.sender,
+            receiver: decoded.receiver,
+            amount: decoded.amount,
+            sender_balance: decoded.sender_balance,
+            receiver_balance: decoded.receiver_balance,
+            sale_id: decoded.sale_id,
+            params: serde_json::Value::from(event.params.clone()),
+        }
+    }
+
+    pub async fn insert(self, tx: &mut Transaction<'_, Postgres>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO nft_events(
+                id, request_key, chain_id, height, block_hash, idx, module, token_id, kind,
+                sender, receiver, amount, sender_balance, receiver_balance, sale_id, params
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
+            "#,
+            self.id,
+            self.request_key,
+            self.chain_id,
+            self.height,
+            self.block_hash,
+            self.idx,
+            self.module,
+            self.token_id,
+            self.kind,
+            self.sender,
+            self.receiver,
+            self.amount,
+            self.sender_balance,
+            self.receiver_balance,
+            self.sale_id,
+            self.params
+        )
+        .execute(tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove the rows of a chain and an inclusive height range
+    pub async fn delete(
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+        min_height: i64,
+        max_height: i64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM nft_events WHERE chain_id = $1 AND height BETWEEN $2 AND $3",
+            chain_id,
+            min_height,
+            max_height
+        )
+        .execute(tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Latest events of a token first, optionally filtered by chain, ledger
+    /// module and kind
+    pub async fn list(
+        pool: &PgPool,
+        token_id: &str,
+        chain_id: Option<i16>,
+        module: Option<&str>,
+        kind: Option<&str>,
+        limit: i64,
+        after: Option<Cursor>,
+    ) -> Result<Page<Self>, sqlx::Error> {
+        let events = sqlx::query_as!(
+            NftEventRecord,
+            r#"
+            SELECT * FROM nft_events
+            WHERE token_id = $1
+                AND ($2::SMALLINT IS NULL OR chain_id = $2)
+                AND ($3::TEXT IS NULL OR module = $3)
+                AND ($4::TEXT IS NULL OR kind = $4)
+                AND ($5::BIGINT IS NULL OR (height, id) < ($5, $6))
+            ORDER BY height DESC, id DESC
+            LIMIT $7
+            "#,
+            token_id,
+            chain_id,
+            module,
+            kind,
+            after.map(|c| c.height),
+            after.map(|c| c.id),
+            limit + 1
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(Page::new(events, limit, |e| Cursor {
+            height: e.height,
+            id: e.id,
+        }))
+    }
+}
+
+/// Token created by a `TOKEN` event
+#[derive(sqlx::FromRow, serde::Serialize, Debug, Clone)]
+pub struct NftToken {
+    pub id: Uuid,
+    pub chain_id: i16,
+    /// Qualified name of the ledger module, e.g. `marmalade-v2.ledger`
+    pub module: String,
+    pub token_id: String,
+    pub height: i64,
+    pub block_hash: String,
+    pub request_key: String,
+    pub precision: Option<i32>,
+    pub uri: Option<String>,
+    pub policies: serde_json::Value,
+    /// Supply of the latest `SUPPLY` event, only set when read from the
+    /// database
+    pub supply: Option<BigDecimal>,
+}
+
+impl NftToken {
+    /// Returns `None` for any event but `TOKEN`
+    pub fn from_event(record: &NftEventRecord, decoded: &NftEvent) -> Option<Self> {
+        let creation = decoded.creation.as_ref()?;
+        Some(Self {
+            id: Uuid::new_v4(),
+            chain_id: record.chain_id,
+            module: record.module.clone(),
+            token_id: record.token_id.clone(),
+            height: record.height,
+            block_hash: record.block_hash.clone(),
+            request_key: record.request_key.clone(),
+            precision: creation.precision,
+            uri: creation.uri.clone(),
+            policies: creation.policies.clone(),
+            supply: None,
+        })
+    }
+
+    pub async fn insert(self, tx: &mut Transaction<'_, Postgres>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO nft_tokens(
+                id, chain_id, module, token_id, height, block_hash, request_key, precision,
+                uri, policies
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            "#,
+            self.id,
+            self.chain_id,
+            self.module,
+            self.token_id,
+            self.height,
+            self.block_hash,
+            self.request_key,
+            self.precision,
+            self.uri,
+            self.policies
+        )
+        .execute(tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove the rows of a chain and an inclusive height range
+    pub async fn delete(
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+        min_height: i64,
+        max_height: i64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM nft_tokens WHERE chain_id = $1 AND height BETWEEN $2 AND $3",
+            chain_id,
+            min_height,
+            max_height
+        )
+        .execute(tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// A token on every chain and ledger it was created on
+    pub async fn find(
+        pool: &PgPool,
+        token_id: &str,
+        chain_id: Option<i16>,
+        module: Option<&str>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            NftToken,
+            r#"
+            SELECT t.*, s.amount AS supply
+            FROM nft_tokens t
+            LEFT JOIN LATERAL (
+                SELECT amount FROM nft_events
+                WHERE token_id = t.token_id AND chain_id = t.chain_id AND module = t.module
+                    AND kind = 'supply'
+                ORDER BY height DESC, idx DESC LIMIT 1
+            ) s ON true
+            WHERE t.token_id = $1
+                AND ($2::SMALLINT IS NULL OR t.chain_id = $2)
+                AND ($3::TEXT IS NULL OR t.module = $3)
+            ORDER BY t.chain_id, t.module
+            "#,
+            token_id,
+            chain_id,
+            module
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Latest created tokens first
+    pub async fn list(
+        pool: &PgPool,
+        chain_id: Option<i16>,
+        module: Option<&str>,
+        limit: i64,
+        after: Option<Cursor>,
+    ) -> Result<Page<Self>, sqlx::Error> {
+        let tokens = sqlx::query_as!(
+            NftToken,
+            r#"
+            SELECT t.*, s.amount AS supply
+            FROM nft_tokens t
+            LEFT JOIN LATERAL (
+                SELECT amount FROM nft_events
+                WHERE token_id = t.token_id AND chain_id = t.chain_id AND module = t.module
+                    AND kind = 'supply'
+                ORDER BY height DESC, idx DESC LIMIT 1
+            ) s ON true
+            WHERE ($1::SMALLINT IS NULL OR t.chain_id = $1)
+                AND ($2::TEXT IS NULL OR t.module = $2)
+                AND ($3::BIGINT IS NULL OR (t.height, t.id) < ($3, $4))
+            ORDER BY t.height DESC, t.id DESC
+            LIMIT $5
+            "#,
+            chain_id,
+            module,
+            after.map(|c| c.height),
+            after.map(|c| c.id),
+            limit + 1
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(Page::new(tokens, limit, |t| Cursor {
+            height: t.height,
+            id: t.id,
+        }))
+    }
+}
+
+/// Balance of a token held by an account
+#[derive(sqlx::FromRow, serde::Serialize, Debug, Clone)]
+pub struct NftOwnership {
+    pub account: String,
+    pub chain_id: i16,
+    pub module: String,
+    pub token_id: String,
+    pub balance: BigDecimal,
+    /// Height of the latest change of the balance
+    pub height: i64,
+}
+
+impl NftOwnership {
+    ///
+    /// Refresh the holders of the tokens of a chain whose balances changed
+    /// from `min_height` on, using the balances reported by `RECONCILE`
+    /// events. Accounts left with nothing are removed.
+    pub async fn rebuild(
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+        min_height: i64,
+    ) -> Result<(), sqlx::Error> {
+        let removed = sqlx::query!(
+            r#"
+            DELETE FROM nft_ownerships
+            WHERE chain_id = $1 AND height >= $2
+            RETURNING account, module, token_id
+            "#,
+            chain_id,
+            min_height
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        let mut accounts = Vec::with_capacity(removed.len());
+        let mut modules = Vec::with_capacity(removed.len());
+        let mut token_ids = Vec::with_capacity(removed.len());
+        for r in removed {
+            accounts.push(r.account);
+            modules.push(r.module);
+            token_ids.push(r.token_id);
+        }
+
+        sqlx::query!(
+            r#"
+            WITH affected AS (
+                SELECT * FROM UNNEST($3::TEXT[], $4::TEXT[], $5::TEXT[])
+                    AS a(account, module, token_id)
+                UNION
+                SELECT sender, module, token_id FROM nft_events
+                WHERE chain_id = $1 AND height >= $2 AND kind = 'reconcile' AND sender <> ''
+                UNION
+                SELECT receiver, module, token_id FROM nft_events
+                WHERE chain_id = $1 AND height >= $2 AND kind = 'reconcile' AND receiver <> ''
+            )
+            INSERT INTO nft_ownerships(account, chain_id, module, token_id, balance, height)
+            SELECT a.account, $1, a.module, a.token_id, l.balance, l.height
+            FROM affected a
+            CROSS JOIN LATERAL (
+                SELECT balance, height FROM (
+                    (
+                        SELECT sender_balance AS balance, height, idx FROM nft_events
+                        WHERE sender = a.account AND chain_id = $1 AND module = a.module
+                            AND token_id = a.token_id AND kind = 'reconcile'
+                        ORDER BY height DESC, idx DESC LIMIT 1
+                    )
+                    UNION ALL
+                    (
+                        SELECT receiver_balance, height, idx FROM nft_events
+                        WHERE receiver = a.account AND chain_id = $1 AND module = a.module
+                            AND token_id = a.token_id AND kind = 'reconcile'
+                        ORDER BY height DESC, idx DESC LIMIT 1
+                    )
+                ) changes
+                ORDER BY height DESC, idx DESC LIMIT 1
+            ) l
+            WHERE l.balance > 0
+            ON CONFLICT (account, chain_id, module, token_id)
+            DO
+                UPDATE SET balance = EXCLUDED.balance, height = EXCLUDED.height
+            "#,
+            chain_id,
+            min_height,
+            &accounts,
+            &modules,
+            &token_ids
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Holders of a token, largest balances first
+    pub async fn list_by_token(
+        pool: &PgPool,
+        token_id: &str,
+        chain_id: Option<i16>,
+        module: Option<&str>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            NftOwnership,
+            r#"
+            SELECT * FROM nft_ownerships
+            WHERE token_id = $1
+                AND ($2::SMALLINT IS NULL OR chain_id = $2)
+                AND ($3::TEXT IS NULL OR module = $3)
+            ORDER BY balance DESC, account
+            "#,
+            token_id,
+            chain_id,
+            module
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Tokens held by an account, latest acquired first
+    pub async fn list_by_account(
+        pool: &PgPool,
+        account: &str,
+        chain_id: Option<i16>,
+        module: Option<&str>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            NftOwnership,
+            r#"
+            SELECT * FROM nft_ownerships
+            WHERE account = $1
+                AND ($2::SMALLINT IS NULL OR chain_id = $2)
+                AND ($3::TEXT IS NULL OR module = $3)
+            ORDER BY height DESC, chain_id, module, token_id
+            "#,
+            account,
+            chain_id,
+            module
+        )
+        .fetch_all(pool)
+        .await
+    }
+}
+
+///
+/// Remove the transactions, events and failed transactions of a chain and
+/// an inclusive height range so it can be rebuilt. Rows of the block
+/// processors are removed by the processors.
+pub async fn delete_derived_rows(
+    tx: &mut Transaction<'_, Postgres>,
+    chain_id: i16,
+    min_height: i64,
+    max_height: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM transactions WHERE chain_id = $1 AND height BETWEEN $2 AND $3",
+        chain_id,
+        min_height,
+        max_height
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM events WHERE chain_id = $1 AND height BETWEEN $2 AND $3",
+        chain_id,
+        min_height,
+        max_height
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM failed_transactions WHERE chain_id = $1 AND height BETWEEN $2 AND $3",
+        chain_id,
+        min_height,
+        max_height
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn header() -> BlockHeader {
+        serde_json::from_value(json!({
+            "chainId": 2,
+            "chainwebVersion": "mainnet01",
+            "creationTime": 1_667_000_000_000_000u64,
+            "epochStart": 0,
+            "featureFlags": 0,
+            "hash": "h",
+            "height": 100,
+            "nonce": "0",
+            "parent": "p",
+            "payloadHash": "ph",
+            "target": "t",
+            "weight": "w",
+            "adjacents": {}
+        }))
+        .unwrap()
+    }
+
+    fn transaction(gas: u64, gas_price: f64) -> DecodedTransaction {
+        let cmd = json!({
+            "networkId": "mainnet01",
+            "payload": { "exec": { "code": "(+ 1 2)", "data": {} } },
+            "signers": [],
+            "meta": {
+                "creationTime": 0,
+                "ttl": 600.0,
+                "gasLimit": 1000,
+                "chainId": "2",
+                "gasPrice": gas_price,
+                "sender": "alice"
+            },
+            "nonce": "1"
+        });
+        DecodedTransaction {
+            request_key: "rk".to_string(),
+            signed: serde_json::from_value(json!({
+                "hash": "rk",
+                "cmd": cmd.to_string(),
+                "sigs": []
+            }))
+            .unwrap(),
+            cmd: serde_json::from_value(cmd).unwrap(),
+            output: serde_json::from_value(json!({
+                "continuation": null,
+                "events": [],
+                "gas": gas,
+                "logs": "",
+                "metaData": null,
+                "reqKey": "rk",
+                "result": { "status": "success", "data": 3 },
+                "txId": 1
+            }))
+            .unwrap(),
+        }
+    }
+
+    #[test]
+    fn empty_block_gas() {
+        let gas = BlockGas::new(&header(), &[], 150_000);
+        assert_eq!(gas.chain_id, 2);
+        assert_eq!(gas.height, 100);
+        assert_eq!(gas.transactions, 0);
+        assert_eq!(gas.total_gas, 0);
+        assert_eq!(gas.total_fees, BigDecimal::default());
+        assert_eq!(gas.min_gas_price, None);
+        assert_eq!(gas.mean_gas_price, None);
+        assert_eq!(gas.median_gas_price, None);
+        assert_eq!(gas.p90_gas_price, None);
+        assert_eq!(gas.max_gas_price, None);
+        assert_eq!(gas.fullness, 0.0);
+    }
+
+    #[test]
+    fn block_gas_statistics() {
+        let txs = [
+            transaction(1000, 1e-6),
+            transaction(500, 1e-8),
+            transaction(1500, 1e-7),
+        ];
+        let gas = BlockGas::new(&header(), &txs, 150_000);
+        assert_eq!(gas.transactions, 3);
+        assert_eq!(gas.total_gas, 3000);
+        assert_eq!(gas.total_fees, BigDecimal::from_str("0.00115500").unwrap());
+        assert_eq!(gas.min_gas_price, Some(1e-8));
+        assert_eq!(gas.median_gas_price, Some(1e-7));
+        assert_eq!(gas.p90_gas_price, Some(1e-6));
+        assert_eq!(gas.max_gas_price, Some(1e-6));
+        assert_eq!(gas.fullness, 0.02);
+    }
+
+    #[test]
+    fn block_gas_without_limit_has_no_fullness() {
+        let gas = BlockGas::new(&header(), &[transaction(1000, 1e-8)], 0);
+        assert_eq!(gas.block_gas_limit, 0);
+        assert_eq!(gas.fullness, 0.0);
+        assert_eq!(gas.median_gas_price, Some(1e-8));
+    }
 }
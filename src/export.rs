@@ -0,0 +1,62 @@
+use std::io::Write;
+
+use anyhow::Context;
+use sqlx::PgPool;
+use tokio_stream::StreamExt;
+
+/// Tables written by the `export` command, all keyed by chain and height
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+pub enum ExportTable {
+    Blocks,
+    Transactions,
+    Events,
+    Transfers,
+}
+
+impl ExportTable {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExportTable::Blocks => "blocks",
+            ExportTable::Transactions => "transactions",
+            ExportTable::Events => "events",
+            ExportTable::Transfers => "transfers",
+        }
+    }
+}
+
+///
+/// Write the rows of a table for a chain and an inclusive height range as
+/// JSON lines, lowest height first. Returns the number of written rows.
+pub async fn export(
+    pool: &PgPool,
+    table: ExportTable,
+    chain_id: i16,
+    min_height: i64,
+    max_height: i64,
+    writer: &mut impl Write,
+) -> Result<u64, anyhow::Error> {
+    // The table name comes from `ExportTable`, never from the input
+    let query = format!(
+        r#"
+        SELECT row_to_json(t)::TEXT FROM {} t
+        WHERE chain_id = $1 AND height BETWEEN $2 AND $3
+        ORDER BY height
+        "#,
+        table.as_str()
+    );
+    let mut rows = sqlx::query_scalar::<_, String>(&query)
+        .bind(chain_id)
+        .bind(min_height)
+        .bind(max_height)
+        .fetch(pool);
+
+    let mut written = 0;
+    while let Some(row) = rows.next().await {
+        let row = row.context("Failed to query database")?;
+        writeln!(writer, "{}", row).context("Failed to write exported row")?;
+        written += 1;
+    }
+    writer.flush().context("Failed to write exported row")?;
+
+    Ok(written)
+}
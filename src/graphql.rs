@@ -0,0 +1,727 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_graphql::dataloader::{DataLoader, Loader};
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, Json, Object, OutputType, Result, Schema,
+    SimpleObject,
+};
+use sqlx::PgPool;
+
+use crate::entities::{self, BlockRecord, EventRecord, TransactionRecord};
+use crate::pagination::{page_bounds, Page, DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE};
+
+pub type IndexerSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// Nested queries are limited so a single request cannot walk the whole database
+const MAX_QUERY_DEPTH: usize = 8;
+/// Highest cost of a query, a list costs the cost of its items times its length
+const MAX_QUERY_COMPLEXITY: usize = 50_000;
+/// Length assumed for the lists that are not paginated
+const LIST_LENGTH: usize = 10;
+
+pub fn build_schema(pool: PgPool) -> IndexerSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .data(DataLoader::new(
+            BlockTransactions(pool.clone()),
+            tokio::spawn,
+        ))
+        .data(DataLoader::new(
+            TransactionEvents(pool.clone()),
+            tokio::spawn,
+        ))
+        .data(pool)
+        .limit_depth(MAX_QUERY_DEPTH)
+        .limit_complexity(MAX_QUERY_COMPLEXITY)
+        .finish()
+}
+
+/// Cost of a page of `first` items
+fn page_cost(first: Option<i64>, child_complexity: usize) -> usize {
+    first.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE) as usize * child_complexity
+}
+
+/// Loads the transactions of the blocks resolved by a query in one request,
+/// by block hash
+pub struct BlockTransactions(PgPool);
+
+impl Loader<String> for BlockTransactions {
+    type Value = Vec<TransactionRecord>;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(&self, hashes: &[String]) -> Result<HashMap<String, Self::Value>, Self::Error> {
+        let mut transactions = HashMap::<_, Vec<_>>::new();
+        for transaction in TransactionRecord::list_by_blocks(&self.0, hashes).await? {
+            transactions
+                .entry(transaction.block_hash.clone())
+                .or_default()
+                .push(transaction);
+        }
+        Ok(transactions)
+    }
+}
+
+/// Loads the events of the transactions resolved by a query in one request,
+/// by request key and block hash
+pub struct TransactionEvents(PgPool);
+
+impl Loader<(String, String)> for TransactionEvents {
+    type Value = Vec<EventRecord>;
+    type Error = Arc<sqlx::Error>;
+
+    async fn load(
+        &self,
+        keys: &[(String, String)],
+    ) -> Result<HashMap<(String, String), Self::Value>, Self::Error> {
+        let mut events = HashMap::<_, Vec<_>>::new();
+        for event in EventRecord::list_by_transactions(&self.0, keys).await? {
+            events
+                .entry((event.request_key.clone(), event.block_hash.clone()))
+                .or_default()
+                .push(event);
+        }
+        Ok(events)
+    }
+}
+
+/// Page of items, `next` is the `after` argument of the next page
+#[derive(SimpleObject)]
+#[graphql(concrete(name = "BlockPage", params(Block)))]
+#[graphql(concrete(name = "TransactionPage", params(Transaction)))]
+#[graphql(concrete(name = "EventPage", params(Event)))]
+#[graphql(concrete(name = "TransferPage", params(Transfer)))]
+pub struct Connection<T: OutputType> {
+    pub items: Vec<T>,
+    pub next: Option<String>,
+}
+
+impl<T: OutputType> Connection<T> {
+    fn from_page<R>(page: Page<R>, f: impl FnMut(R) -> T) -> Self {
+        let page = page.map(f);
+        Self {
+            items: page.items,
+            next: page.next,
+        }
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    async fn block(&self, ctx: &Context<'_>, hash: String) -> Result<Option<Block>> {
+        let pool = ctx.data::<PgPool>()?;
+        Ok(BlockRecord::find_by_hash(pool, &hash).await?.map(Block))
+    }
+
+    /// Block of a chain at a height. When a fork left several blocks at the
+    /// height, the parent of the block at the next height is returned, or
+    /// else the most recently created one.
+    async fn block_by_height(
+        &self,
+        ctx: &Context<'_>,
+        chain_id: i16,
+        height: i64,
+    ) -> Result<Option<Block>> {
+        let pool = ctx.data::<PgPool>()?;
+        Ok(BlockRecord::find_by_height(pool, chain_id, height)
+            .await?
+            .map(Block))
+    }
+
+    /// Latest blocks first
+    #[graphql(complexity = "page_cost(first, child_complexity)")]
+    async fn blocks(
+        &self,
+        ctx: &Context<'_>,
+        chain_id: Option<i16>,
+        first: Option<i64>,
+        after: Option<String>,
+    ) -> Result<Connection<Block>> {
+        let pool = ctx.data::<PgPool>()?;
+        let (limit, cursor) = page_bounds(first, after.as_deref())?;
+        let page = BlockRecord::list(pool, chain_id, limit, cursor).await?;
+        Ok(Connection::from_page(page, Block))
+    }
+
+    async fn transaction(
+        &self,
+        ctx: &Context<'_>,
+        request_key: String,
+    ) -> Result<Option<Transaction>> {
+        let pool = ctx.data::<PgPool>()?;
+        Ok(TransactionRecord::find_by_request_key(pool, &request_key)
+            .await?
+            .map(Transaction))
+    }
+
+    /// Latest transactions first
+    #[graphql(complexity = "page_cost(first, child_complexity)")]
+    async fn transactions(
+        &self,
+        ctx: &Context<'_>,
+        sender: Option<String>,
+        chain_id: Option<i16>,
+        first: Option<i64>,
+        after: Option<String>,
+    ) -> Result<Connection<Transaction>> {
+        let pool = ctx.data::<PgPool>()?;
+        let (limit, cursor) = page_bounds(first, after.as_deref())?;
+        let page =
+            TransactionRecord::list(pool, sender.as_deref(), chain_id, limit, cursor).await?;
+        Ok(Connection::from_page(page, Transaction))
+    }
+
+    /// Latest events first. `module` is the qualified module name, e.g. `coin`.
+    #[graphql(complexity = "page_cost(first, child_complexity)")]
+    async fn events(
+        &self,
+        ctx: &Context<'_>,
+        module: Option<String>,
+        name: Option<String>,
+        chain_id: Option<i16>,
+        first: Option<i64>,
+        after: Option<String>,
+    ) -> Result<Connection<Event>> {
+        let pool = ctx.data::<PgPool>()?;
+        let (limit, cursor) = page_bounds(first, after.as_deref())?;
+        let page = EventRecord::list(
+            pool,
+            module.as_deref(),
+            name.as_deref(),
+            chain_id,
+            limit,
+            cursor,
+        )
+        .await?;
+        Ok(Connection::from_page(page, Event))
+    }
+
+    /// Latest transfers first. `account` matches both sender and receiver.
+    #[graphql(complexity = "page_cost(first, child_complexity)")]
+    async fn transfers(
+        &self,
+        ctx: &Context<'_>,
+        account: Option<String>,
+        module: Option<String>,
+        chain_id: Option<i16>,
+        first: Option<i64>,
+        after: Option<String>,
+    ) -> Result<Connection<Transfer>> {
+        let pool = ctx.data::<PgPool>()?;
+        let (limit, cursor) = page_bounds(first, after.as_deref())?;
+        let page = entities::Transfer::list(
+            pool,
+            account.as_deref(),
+            module.as_deref(),
+            chain_id,
+            limit,
+            cursor,
+        )
+        .await?;
+        Ok(Connection::from_page(page, Transfer))
+    }
+
+    async fn account(&self, name: String) -> Account {
+        Account { name }
+    }
+}
+
+pub struct Block(BlockRecord);
+
+#[Object]
+impl Block {
+    async fn chain_id(&self) -> i16 {
+        self.0.chain_id
+    }
+
+    async fn height(&self) -> i64 {
+        self.0.height
+    }
+
+    async fn hash(&self) -> Option<&str> {
+        self.0.hash.as_deref()
+    }
+
+    async fn parent(&self) -> Option<&str> {
+        self.0.parent.as_deref()
+    }
+
+    async fn payload_hash(&self) -> Option<&str> {
+        self.0.payload_hash.as_deref()
+    }
+
+    /// Microseconds since epoch
+    async fn creation_time(&self) -> Option<i64> {
+        self.0.creation_time
+    }
+
+    #[graphql(complexity = "LIST_LENGTH * child_complexity")]
+    async fn transactions(&self, ctx: &Context<'_>) -> Result<Vec<Transaction>> {
+        let Some(hash) = &self.0.hash else {
+            return Ok(vec![]);
+        };
+        let loader = ctx.data::<DataLoader<BlockTransactions>>()?;
+        let transactions = loader.load_one(hash.clone()).await?.unwrap_or_default();
+        Ok(transactions.into_iter().map(Transaction).collect())
+    }
+}
+
+pub struct Transaction(TransactionRecord);
+
+#[Object]
+impl Transaction {
+    async fn request_key(&self) -> &str {
+        &self.0.request_key
+    }
+
+    async fn chain_id(&self) -> i16 {
+        self.0.chain_id
+    }
+
+    async fn height(&self) -> i64 {
+        self.0.height
+    }
+
+    async fn block_hash(&self) -> &str {
+        &self.0.block_hash
+    }
+
+    /// Seconds since epoch
+    async fn creation_time(&self) -> i64 {
+        self.0.creation_time
+    }
+
+    async fn sender(&self) -> &str {
+        &self.0.sender
+    }
+
+    async fn nonce(&self) -> &str {
+        &self.0.nonce
+    }
+
+    async fn code(&self) -> Option<&str> {
+        self.0.code.as_deref()
+    }
+
+    async fn data(&self) -> Option<Json<&serde_json::Value>> {
+        self.0.data.as_ref().map(Json)
+    }
+
+    async fn continuation(&self) -> Option<Json<&serde_json::Value>> {
+        self.0.continuation.as_ref().map(Json)
+    }
+
+    async fn gas(&self) -> i64 {
+        self.0.gas
+    }
+
+    async fn gas_limit(&self) -> i64 {
+        self.0.gas_limit
+    }
+
+    async fn gas_price(&self) -> f64 {
+        self.0.gas_price
+    }
+
+    async fn ttl(&self) -> f64 {
+        self.0.ttl
+    }
+
+    async fn status(&self) -> &str {
+        &self.0.status
+    }
+
+    async fn result(&self) -> Option<Json<&serde_json::Value>> {
+        self.0.result.as_ref().map(Json)
+    }
+
+    async fn logs(&self) -> &str {
+        &self.0.logs
+    }
+
+    async fn block(&self, ctx: &Context<'_>) -> Result<Option<Block>> {
+        let pool = ctx.data::<PgPool>()?;
+        Ok(BlockRecord::find_by_hash(pool, &self.0.block_hash)
+            .await?
+            .map(Block))
+    }
+
+    #[graphql(complexity = "LIST_LENGTH * child_complexity")]
+    async fn events(&self, ctx: &Context<'_>) -> Result<Vec<Event>> {
+        let loader = ctx.data::<DataLoader<TransactionEvents>>()?;
+        let events = loader
+            .load_one((self.0.request_key.clone(), self.0.block_hash.clone()))
+            .await?
+            .unwrap_or_default();
+        Ok(events.into_iter().map(Event).collect())
+    }
+
+    #[graphql(complexity = "LIST_LENGTH * child_complexity")]
+    async fn transfers(&self, ctx: &Context<'_>) -> Result<Vec<Transfer>> {
+        let pool = ctx.data::<PgPool>()?;
+        let transfers =
+            entities::Transfer::list_by_transaction(pool, &self.0.request_key, &self.0.block_hash)
+                .await?;
+        Ok(transfers.into_iter().map(Transfer).collect())
+    }
+
+    /// Module functions called by the code, in source order
+    #[graphql(complexity = "LIST_LENGTH * child_complexity")]
+    async fn calls(&self, ctx: &Context<'_>) -> Result<Vec<Call>> {
+        let pool = ctx.data::<PgPool>()?;
+        let calls = entities::TransactionCall::list_by_transaction(
+            pool,
+            &self.0.request_key,
+            &self.0.block_hash,
+        )
+        .await?;
+        Ok(calls.into_iter().map(Call).collect())
+    }
+}
+
+pub struct Call(entities::TransactionCall);
+
+#[Object]
+impl Call {
+    /// Qualified module name, e.g. `free.my-dex`
+    async fn module(&self) -> &str {
+        &self.0.module
+    }
+
+    async fn function(&self) -> &str {
+        &self.0.function
+    }
+
+    /// Literal arguments, `null` for the ones computed by the code
+    async fn args(&self) -> Json<&serde_json::Value> {
+        Json(&self.0.args)
+    }
+}
+
+pub struct Event(EventRecord);
+
+#[Object]
+impl Event {
+    async fn request_key(&self) -> &str {
+        &self.0.request_key
+    }
+
+    async fn chain_id(&self) -> i16 {
+        self.0.chain_id
+    }
+
+    async fn height(&self) -> i64 {
+        self.0.height
+    }
+
+    async fn block_hash(&self) -> &str {
+        &self.0.block_hash
+    }
+
+    /// Position of the event in the transaction output
+    async fn idx(&self) -> i32 {
+        self.0.idx
+    }
+
+    async fn module(&self) -> &str {
+        &self.0.module
+    }
+
+    async fn module_hash(&self) -> &str {
+        &self.0.module_hash
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn params(&self) -> Json<&serde_json::Value> {
+        Json(&self.0.params)
+    }
+
+    /// `None` for coinbase events
+    async fn transaction(&self, ctx: &Context<'_>) -> Result<Option<Transaction>> {
+        let pool = ctx.data::<PgPool>()?;
+        Ok(
+            TransactionRecord::find_in_block(pool, &self.0.request_key, &self.0.block_hash)
+                .await?
+                .map(Transaction),
+        )
+    }
+}
+
+pub struct Transfer(entities::Transfer);
+
+#[Object]
+impl Transfer {
+    async fn request_key(&self) -> &str {
+        &self.0.request_key
+    }
+
+    async fn chain_id(&self) -> i16 {
+        self.0.chain_id
+    }
+
+    async fn height(&self) -> i64 {
+        self.0.height
+    }
+
+    async fn block_hash(&self) -> &str {
+        &self.0.block_hash
+    }
+
+    async fn idx(&self) -> i32 {
+        self.0.idx
+    }
+
+    async fn module(&self) -> &str {
+        &self.0.module
+    }
+
+    async fn sender(&self) -> Account {
+        Account {
+            name: self.0.sender.clone(),
+        }
+    }
+
+    async fn receiver(&self) -> Account {
+        Account {
+            name: self.0.receiver.clone(),
+        }
+    }
+
+    /// Decimal amount as a string to keep its precision
+    async fn amount(&self) -> String {
+        self.0.amount.to_string()
+    }
+
+    /// `None` for coinbase transfers
+    async fn transaction(&self, ctx: &Context<'_>) -> Result<Option<Transaction>> {
+        let pool = ctx.data::<PgPool>()?;
+        Ok(
+            TransactionRecord::find_in_block(pool, &self.0.request_key, &self.0.block_hash)
+                .await?
+                .map(Transaction),
+        )
+    }
+}
+
+pub struct Account {
+    name: String,
+}
+
+#[Object]
+impl Account {
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Transfers from or to the account, latest first
+    #[graphql(complexity = "page_cost(first, child_complexity)")]
+    async fn transfers(
+        &self,
+        ctx: &Context<'_>,
+        module: Option<String>,
+        chain_id: Option<i16>,
+        first: Option<i64>,
+        after: Option<String>,
+    ) -> Result<Connection<Transfer>> {
+        let pool = ctx.data::<PgPool>()?;
+        let (limit, cursor) = page_bounds(first, after.as_deref())?;
+        let page = entities::Transfer::list(
+            pool,
+            Some(&self.name),
+            module.as_deref(),
+            chain_id,
+            limit,
+            cursor,
+        )
+        .await?;
+        Ok(Connection::from_page(page, Transfer))
+    }
+
+    /// Transactions sent by the account, latest first
+    #[graphql(complexity = "page_cost(first, child_complexity)")]
+    async fn transactions(
+        &self,
+        ctx: &Context<'_>,
+        chain_id: Option<i16>,
+        first: Option<i64>,
+        after: Option<String>,
+    ) -> Result<Connection<Transaction>> {
+        let pool = ctx.data::<PgPool>()?;
+        let (limit, cursor) = page_bounds(first, after.as_deref())?;
+        let page = TransactionRecord::list(pool, Some(&self.name), chain_id, limit, cursor).await?;
+        Ok(Connection::from_page(page, Transaction))
+    }
+
+    /// Balances per chain and token, as of `height` when given
+    #[graphql(complexity = "LIST_LENGTH * child_complexity")]
+    async fn balances(
+        &self,
+        ctx: &Context<'_>,
+        chain_id: Option<i16>,
+        module: Option<String>,
+        height: Option<i64>,
+    ) -> Result<Vec<Balance>> {
+        let pool = ctx.data::<PgPool>()?;
+        let balances =
+            entities::Balance::list(pool, &self.name, chain_id, module.as_deref(), height).await?;
+        Ok(balances.into_iter().map(Balance).collect())
+    }
+
+    /// The account on every chain and token it was seen on
+    #[graphql(complexity = "LIST_LENGTH * child_complexity")]
+    async fn details(
+        &self,
+        ctx: &Context<'_>,
+        chain_id: Option<i16>,
+        module: Option<String>,
+    ) -> Result<Vec<AccountDetails>> {
+        let pool = ctx.data::<PgPool>()?;
+        let accounts =
+            entities::Account::list(pool, &self.name, chain_id, module.as_deref()).await?;
+        Ok(accounts.into_iter().map(AccountDetails).collect())
+    }
+
+    /// Guards set on the account, latest first
+    #[graphql(complexity = "LIST_LENGTH * child_complexity")]
+    async fn guards(
+        &self,
+        ctx: &Context<'_>,
+        chain_id: Option<i16>,
+        module: Option<String>,
+    ) -> Result<Vec<AccountGuard>> {
+        let pool = ctx.data::<PgPool>()?;
+        let guards =
+            entities::AccountGuard::list(pool, &self.name, chain_id, module.as_deref()).await?;
+        Ok(guards.into_iter().map(AccountGuard).collect())
+    }
+
+    /// Marmalade and poly-fungible tokens held by the account
+    #[graphql(complexity = "LIST_LENGTH * child_complexity")]
+    async fn nfts(
+        &self,
+        ctx: &Context<'_>,
+        chain_id: Option<i16>,
+        module: Option<String>,
+    ) -> Result<Vec<NftOwnership>> {
+        let pool = ctx.data::<PgPool>()?;
+        let tokens =
+            entities::NftOwnership::list_by_account(pool, &self.name, chain_id, module.as_deref())
+                .await?;
+        Ok(tokens.into_iter().map(NftOwnership).collect())
+    }
+}
+
+pub struct AccountDetails(entities::Account);
+
+#[Object]
+impl AccountDetails {
+    async fn chain_id(&self) -> i16 {
+        self.0.chain_id
+    }
+
+    async fn module(&self) -> &str {
+        &self.0.module
+    }
+
+    async fn first_seen_height(&self) -> i64 {
+        self.0.first_seen_height
+    }
+
+    async fn first_seen_request_key(&self) -> &str {
+        &self.0.first_seen_request_key
+    }
+
+    /// Latest known guard, null when it could not be decoded
+    async fn guard(&self) -> Option<Json<&serde_json::Value>> {
+        self.0.guard.as_ref().map(Json)
+    }
+
+    async fn guard_height(&self) -> Option<i64> {
+        self.0.guard_height
+    }
+}
+
+pub struct AccountGuard(entities::AccountGuard);
+
+#[Object]
+impl AccountGuard {
+    async fn chain_id(&self) -> i16 {
+        self.0.chain_id
+    }
+
+    async fn module(&self) -> &str {
+        &self.0.module
+    }
+
+    async fn height(&self) -> i64 {
+        self.0.height
+    }
+
+    async fn request_key(&self) -> &str {
+        &self.0.request_key
+    }
+
+    /// `create` or `rotate`
+    async fn kind(&self) -> &str {
+        &self.0.kind
+    }
+
+    async fn guard(&self) -> Option<Json<&serde_json::Value>> {
+        self.0.guard.as_ref().map(Json)
+    }
+}
+
+pub struct Balance(entities::Balance);
+
+#[Object]
+impl Balance {
+    async fn chain_id(&self) -> i16 {
+        self.0.chain_id
+    }
+
+    async fn module(&self) -> &str {
+        &self.0.module
+    }
+
+    /// Decimal balance as a string to keep its precision
+    async fn balance(&self) -> String {
+        self.0.balance.to_string()
+    }
+
+    /// Height of the last block that changed the balance
+    async fn height(&self) -> i64 {
+        self.0.height
+    }
+}
+
+pub struct NftOwnership(entities::NftOwnership);
+
+#[Object]
+impl NftOwnership {
+    async fn chain_id(&self) -> i16 {
+        self.0.chain_id
+    }
+
+    /// Qualified ledger module name, e.g. `marmalade-v2.ledger`
+    async fn module(&self) -> &str {
+        &self.0.module
+    }
+
+    async fn token_id(&self) -> &str {
+        &self.0.token_id
+    }
+
+    /// Decimal balance as a string to keep its precision
+    async fn balance(&self) -> String {
+        self.0.balance.to_string()
+    }
+
+    /// Height of the last block that changed the balance
+    async fn height(&self) -> i64 {
+        self.0.height
+    }
+}
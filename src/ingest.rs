@@ -1,17 +1,32 @@
 use anyhow::Context;
-use backoff::{future::retry, ExponentialBackoff};
+use backoff::future::retry_notify;
+use futures_util::future::try_join_all;
 use serde_json::json;
-use sqlx::PgPool;
+use serde_json::value::RawValue;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::HashMap;
 use std::str;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::{debug, error, info, warn, Span};
 
-use crate::entities::Block;
+use crate::archive::RawArchive;
+use crate::client::{is_permanent, NodeClient, NodeError};
+use crate::decode::{decode_coinbase, decode_transaction, DecodedBlock};
+use crate::entities::{
+    delete_derived_rows, Block, BlockRecord, EventRecord, FailedTransaction, TransactionRecord,
+};
+use crate::leases::{ChainLeases, LeaseLost};
+use crate::metrics::{metrics, CUT_REQUEST, HEADERS_REQUEST, PAYLOADS_REQUEST};
+use crate::processors::{builtin_processors, BlockProcessor};
+use crate::subscriptions::Notification;
 use crate::types::{
-    BlockHeaderItems, BlockPayload, CurrentCut, HashHeight, NewHead, Output, Transaction,
-    TransactionWithCmdSigs,
+    BlockHeader, BlockHeaderItems, BlockPayload, CurrentCut, HashHeight, NewHead, RawItems,
 };
 use crate::utils::{
-    decode_from_base64_url, format_endpoint_with_query_params, req_header_content_type,
-    req_header_content_type_with_accept,
+    format_endpoint_with_query_params, req_header_content_type, req_header_content_type_with_accept,
 };
 
 ///
@@ -28,14 +43,65 @@ use crate::utils::{
 pub struct Ingest {
     pub chain_id: i16,
     pub base_url: String,
-    pub http_client: reqwest::Client,
+    /// Client of the node shared with the other chains
+    pub client: NodeClient,
     pub cut_url: String,
     pub qparams: QueryParams,
     pub root_url: String,
     pub chain_head: HashHeight,
     pub pool: PgPool,
+    pub archive: Option<RawArchive>,
+    /// Receives the rows of every committed batch
+    pub notifier: Option<broadcast::Sender<Notification>>,
+    /// Gas limit of a block, used for the block fullness
+    pub block_gas_limit: u64,
+    /// Processors registered on top of the built-in ones
+    pub processors: Vec<Arc<dyn BlockProcessor>>,
+    /// Height after which `start` stops
+    pub max_height: Option<u64>,
+    /// Batches fetched from the node at the same time by `start`
+    pub concurrency: u64,
+    /// Stop `start` at the chain head of the cut when no end height is set
+    pub finite: bool,
+    /// Lease every batch is written under when chains are shared
+    pub lease: Option<ChainLeases>,
+}
+
+/// Rows stored by an indexer
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct IndexedTotals {
+    pub blocks: u64,
+    pub transactions: u64,
+    pub events: u64,
 }
 
+impl std::ops::AddAssign for IndexedTotals {
+    fn add_assign(&mut self, other: Self) {
+        self.blocks += other.blocks;
+        self.transactions += other.transactions;
+        self.events += other.events;
+    }
+}
+
+/// Headers of a batch, latest first, and their payloads by hash
+struct FetchedBatch {
+    headers: Vec<BlockHeader>,
+    payloads: HashMap<String, BlockPayload>,
+}
+
+/// Block gas limit of mainnet and testnet
+pub const DEFAULT_BLOCK_GAS_LIMIT: u64 = 150_000;
+
+/// How long to wait before checking again whether a chain exists
+const CHAIN_WAIT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// How long to wait for new blocks once the chain head is indexed
+const HEAD_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// How many blocks below a batch the fork point of a reorganization is
+/// searched for
+const MAX_REORG_DEPTH: u64 = 100;
+
 /// Query paramaters for endpoint
 #[derive(Default, Debug, Clone)]
 pub struct QueryParams {
@@ -63,8 +129,14 @@ pub enum ApiFetchResult {
 }
 
 impl Ingest {
-    pub fn new(chain_id: i16, base_url: String, params: QueryParams, pool: PgPool) -> Self {
-        let http_client = reqwest::Client::new();
+    pub fn new(
+        chain_id: i16,
+        base_url: String,
+        params: QueryParams,
+        pool: PgPool,
+        archive: Option<RawArchive>,
+        client: NodeClient,
+    ) -> Self {
         let cut_url = format!("{}/cut", base_url);
         let root_url = base_url.clone();
         let base_url = format!(
@@ -76,7 +148,7 @@ impl Ingest {
         Self {
             chain_id,
             base_url,
-            http_client,
+            client,
             cut_url,
             qparams: params,
             root_url,
@@ -85,66 +157,252 @@ impl Ingest {
                 height: 0,
             },
             pool,
+            archive,
+            notifier: None,
+            block_gas_limit: DEFAULT_BLOCK_GAS_LIMIT,
+            processors: vec![],
+            max_height: None,
+            concurrency: 1,
+            finite: false,
+            lease: None,
         }
     }
 
-    pub async fn start(&mut self) -> Result<(), anyhow::Error> {
-        println!(
-            "starting indexer # {} at # {}",
-            self.chain_id, self.qparams.min_height
-        );
+    pub fn with_notifier(mut self, notifier: broadcast::Sender<Notification>) -> Self {
+        self.notifier = Some(notifier);
+        self
+    }
 
-        let cut = self.current_cut().await?;
+    pub fn with_block_gas_limit(mut self, block_gas_limit: u64) -> Self {
+        self.block_gas_limit = block_gas_limit;
+        self
+    }
+
+    pub fn with_max_height(mut self, max_height: Option<u64>) -> Self {
+        self.max_height = max_height;
+        self
+    }
+
+    pub fn with_concurrency(mut self, concurrency: u64) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    pub fn with_finite(mut self, finite: bool) -> Self {
+        self.finite = finite;
+        self
+    }
 
-        let hh = cut.hashes.get(&self.chain_id).unwrap();
-        self.chain_head = hh.clone();
-        self.qparams.max_height = self.qparams.limit + self.qparams.min_height;
+    /// Run a processor after the built-in ones for every stored batch
+    pub fn with_processor(mut self, processor: Arc<dyn BlockProcessor>) -> Self {
+        self.processors.push(processor);
+        self
+    }
+
+    /// Built-in processors followed by the registered ones
+    fn all_processors(&self) -> Vec<Arc<dyn BlockProcessor>> {
+        let mut processors = builtin_processors(self.block_gas_limit);
+        processors.extend(self.processors.iter().cloned());
+        processors
+    }
 
+    ///
+    /// Index the chain from `qparams.min_height`, following the chain head.
+    /// Returns the stored rows once the end height is reached, which never
+    /// happens without an end height unless `finite` is set.
+    #[tracing::instrument(name = "indexer", skip(self), fields(chain_id = self.chain_id))]
+    pub async fn start(&mut self) -> Result<IndexedTotals, anyhow::Error> {
+        info!(
+            min_height = self.qparams.min_height,
+            max_height = self.max_height,
+            finite = self.finite,
+            "Starting indexer"
+        );
+
+        // Chains added by a future graph transition are not in the cut yet
+        while !self.refresh_chain_head().await? {
+            if self.finite {
+                info!("Chain is not in the current cut, nothing to index");
+                return Ok(IndexedTotals::default());
+            }
+            info!("Chain is not in the current cut yet, waiting");
+            tokio::time::sleep(CHAIN_WAIT_INTERVAL).await;
+        }
+        if self.finite && self.max_height.is_none() {
+            self.max_height = Some(self.chain_head.height);
+        }
+
+        let mut totals = IndexedTotals::default();
         loop {
-            match self.blocks().await {
-                Ok(_) => {
-                    self.qparams.min_height += self.qparams.limit;
+            if self
+                .max_height
+                .is_some_and(|max| self.qparams.min_height > max)
+            {
+                info!(
+                    max_height = self.max_height,
+                    blocks = totals.blocks,
+                    transactions = totals.transactions,
+                    events = totals.events,
+                    "Reached the end height"
+                );
+                return Ok(totals);
+            }
+            if self.qparams.min_height > self.chain_head.height {
+                let head = self.chain_head.height;
+                match self.refresh_chain_head().await {
+                    Ok(_) if self.chain_head.height > head => {}
+                    Ok(_) => tokio::time::sleep(HEAD_POLL_INTERVAL).await,
+                    Err(e) => {
+                        error!(error = %e, "Failed to refresh the chain head");
+                        tokio::time::sleep(HEAD_POLL_INTERVAL).await;
+                    }
+                }
+                continue;
+            }
+            match self.index_next_batches(&mut totals).await {
+                Ok(()) => {}
+                // Retrying a bad request or an invalid response does not help,
+                // nor writing a chain leased to another instance
+                Err(ApiFetchResult::Failure(e))
+                    if is_permanent(&e) || e.chain().any(|c| c.is::<LeaseLost>()) =>
+                {
+                    return Err(e.context(format!(
+                        "Failed to index the blocks from height {}",
+                        self.qparams.min_height
+                    )));
+                }
+                Err(e) => {
+                    error!(error = %e, "Failed to index batch");
+                    tokio::time::sleep(HEAD_POLL_INTERVAL).await;
                 }
-                Err(e) => println!("{}", e),
             }
         }
     }
 
-    pub async fn current_cut(&self) -> Result<CurrentCut, ApiFetchResult> {
-        let resp = retry(ExponentialBackoff::default(), || async {
-            let cut = self
-                .http_client
-                .get(&self.cut_url)
-                .send()
-                .await
-                .context("Failed to send a request")?;
-            let status = cut.status().as_u16();
-            if status != 200 {
-                let err = format!("Error! Got status {}", status);
-                Err(backoff::Error::transient(anyhow::anyhow!(err)))
-            } else {
-                // dbg!("Got status {} for chain {}", status, self.chain_id);
-                let cut_as_json: CurrentCut = cut
-                    .json()
+    ///
+    /// Move `chain_head` to the head of the chain in the current cut.
+    /// Returns false when the chain is not in the cut.
+    async fn refresh_chain_head(&mut self) -> Result<bool, ApiFetchResult> {
+        let cut = self.current_cut().await?;
+        match cut.hashes.get(&self.chain_id) {
+            Some(hh) => {
+                self.chain_head = hh.clone();
+                metrics().set_node_height(self.chain_id, hh.height);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    ///
+    /// Fetch up to `concurrency` batches from `qparams.min_height` at the same
+    /// time, below the chain head and the end height, then store them in
+    /// order. `qparams.min_height` moves past every stored batch.
+    async fn index_next_batches(
+        &mut self,
+        totals: &mut IndexedTotals,
+    ) -> Result<(), ApiFetchResult> {
+        let mut max_height = self.chain_head.height;
+        if let Some(end) = self.max_height {
+            max_height = max_height.min(end);
+        }
+        let limit = self.qparams.limit.max(1);
+        let mut ranges = vec![];
+        let mut from = self.qparams.min_height;
+        while from <= max_height && (ranges.len() as u64) < self.concurrency.max(1) {
+            let to = max_height.min(from + limit - 1);
+            ranges.push(QueryParams {
+                min_height: from,
+                max_height: to,
+                limit,
+            });
+            from = to + 1;
+        }
+
+        let batches = try_join_all(ranges.iter().map(|params| self.fetch_batch(params))).await?;
+        for (params, batch) in ranges.iter().zip(&batches) {
+            // headers are ordered by descending height
+            if let Some(fork_height) = match batch.headers.last() {
+                Some(lowest) => self.fork_height(lowest).await?,
+                None => None,
+            } {
+                warn!(
+                    min_height = params.min_height,
+                    fork_height, "Chain reorganized, rolling back to the fork point"
+                );
+                self.rollback(fork_height)
                     .await
-                    .context("Failed to convert response to json.")?;
-                Ok(cut_as_json)
+                    .context("Failed to roll back a reorganized chain")
+                    .map_err(ApiFetchResult::Failure)?;
+                metrics()
+                    .reorgs
+                    .with_label_values(&[&self.chain_id.to_string()])
+                    .inc();
+                self.qparams.min_height = fork_height + 1;
+                return Ok(());
             }
-        })
+            *totals += self.store_batch(batch).await?;
+            self.qparams.min_height = params.max_height + 1;
+        }
+
+        Ok(())
+    }
+
+    /// Count and log the retries of a request to the node
+    fn on_retry<'a>(
+        &self,
+        request: &'static str,
+        retries: &'a AtomicU32,
+    ) -> impl FnMut(NodeError, Duration) + 'a {
+        let chain = self.chain_id.to_string();
+        move |e, delay| {
+            retries.fetch_add(1, Ordering::Relaxed);
+            metrics()
+                .retries
+                .with_label_values(&[&chain, request])
+                .inc();
+            warn!(request, error = %e, ?delay, "Retrying request to the node");
+        }
+    }
+
+    #[tracing::instrument(skip(self), fields(chain_id = self.chain_id, requests))]
+    pub async fn current_cut(&self) -> Result<CurrentCut, ApiFetchResult> {
+        let retries = AtomicU32::new(0);
+        let budget = self.client.retry_budget();
+        let resp = retry_notify(
+            self.client.backoff(),
+            || async {
+                let cut = self
+                    .client
+                    .send(self.client.get(&self.cut_url))
+                    .await
+                    .map_err(|e| e.into_backoff(&budget))?;
+                cut.json::<CurrentCut>()
+                    .map_err(|e| e.into_backoff(&budget))
+            },
+            self.on_retry(CUT_REQUEST, &retries),
+        )
         .await
         .context("Failed to fetch cut from chainweb node.")
         .map_err(ApiFetchResult::Failure)?;
+        Span::current().record("requests", retries.into_inner() + 1);
 
         Ok(resp)
     }
 
     pub async fn listen_to_new_heads(&mut self) -> Result<(), ApiFetchResult> {
         let url = format!("{}/header/updates", self.root_url);
-        let mut res = reqwest::get(url)
+        let mut res = self
+            .client
+            .get(url)
+            .send()
             .await
             .context("Failed to make request to fetch header updates")
             .map_err(ApiFetchResult::Failure)?;
-        dbg!("{}", res.status().as_u16());
+        debug!(
+            status = res.status().as_u16(),
+            "Listening to header updates"
+        );
 
         while let Some(chunk) = res
             .chunk()
@@ -152,7 +410,7 @@ impl Ingest {
             .context("Failed to read response chunk")
             .map_err(ApiFetchResult::Failure)?
         {
-            println!("Received a new head");
+            debug!("Received a new head");
 
             // Remove non-json from received data
             let s = str::from_utf8(&chunk[23..]).unwrap();
@@ -162,13 +420,33 @@ impl Ingest {
         Ok(())
     }
 
-    pub async fn blocks_headers(&self) -> Result<BlockHeaderItems, ApiFetchResult> {
-        // let current_cut = self.current_cut().await?;
-        // Should never panic because we have all available chain ids
-        // let hh = current_cut.hashes.get(&self.chain_id).unwrap();
+    #[tracing::instrument(
+        skip(self, params),
+        fields(
+            chain_id = self.chain_id,
+            min_height = params.min_height,
+            max_height = params.max_height,
+            requests,
+            headers,
+        )
+    )]
+    pub async fn blocks_headers(
+        &self,
+        params: &QueryParams,
+    ) -> Result<BlockHeaderItems, ApiFetchResult> {
+        self.branch_headers(&self.chain_head.hash, params).await
+    }
 
+    ///
+    /// Headers of the branch ending at the block `upper` within the height
+    /// range of `params`, ordered by descending height
+    async fn branch_headers(
+        &self,
+        upper: &str,
+        params: &QueryParams,
+    ) -> Result<BlockHeaderItems, ApiFetchResult> {
         let body = json!({
-            "upper": [self.chain_head.hash],
+            "upper": [upper],
             "lower": []
         });
 
@@ -176,50 +454,89 @@ impl Ingest {
             "{}/chain/{}{}",
             self.root_url,
             self.chain_id,
-            format_endpoint_with_query_params(&self.qparams)
+            format_endpoint_with_query_params(params)
         );
-        // println!("{}", url);
-        let resp = retry(ExponentialBackoff::default(), || async {
-            let resp = self
-                .http_client
-                .post(url.clone())
-                .headers(req_header_content_type_with_accept())
-                .json(&body)
-                .send()
-                .await
-                .context("Failed to send a request")?;
-
-            let status = resp.status().as_u16();
-            if status != 200 {
-                let detail = format!(
-                    "Error while fetching block headers for chain: {}! Got status {}",
-                    self.chain_id, status
-                );
-                let err = backoff::Error::transient(anyhow::anyhow!(detail));
-                Err(err)
-            } else {
-                // dbg!("Got status {} for chain {}", status, self.chain_id);
-                let block_headers_json: BlockHeaderItems = resp
-                    .json()
+        let retries = AtomicU32::new(0);
+        let budget = self.client.retry_budget();
+        let resp = retry_notify(
+            self.client.backoff(),
+            || async {
+                let request = self
+                    .client
+                    .post(url.clone())
+                    .headers(req_header_content_type_with_accept())
+                    .json(&body);
+                let resp = self
+                    .client
+                    .send(request)
                     .await
-                    .context("Failed to convert response to json.")?;
-                Ok(block_headers_json)
-            }
-        })
+                    .map_err(|e| e.into_backoff(&budget))?;
+                let block_headers_json: BlockHeaderItems =
+                    resp.json().map_err(|e| e.into_backoff(&budget))?;
+                let raw_headers: RawItems = resp.json().map_err(|e| e.into_backoff(&budget))?;
+                Ok((block_headers_json, raw_headers))
+            },
+            self.on_retry(HEADERS_REQUEST, &retries),
+        )
         .await
         .context("Failed to fetch block headers from chainweb node.")
         .map_err(ApiFetchResult::Failure)?;
 
-        Ok(resp)
-    }
+        let (block_headers, raw_headers) = resp;
+        Span::current()
+            .record("requests", retries.into_inner() + 1)
+            .record("headers", block_headers.items.len());
+        if let Some(archive) = &self.archive {
+            for (header, raw) in block_headers.items.iter().zip(raw_headers.items) {
+                archive
+                    .store_header(
+                        self.chain_id,
+                        header.height,
+                        &header.hash,
+                        &header.payload_hash,
+                        raw.get().as_bytes(),
+                    )
+                    .await
+                    .context("Failed to archive raw block header")
+                    .map_err(ApiFetchResult::Failure)?;
+            }
+        }
 
-    pub async fn blocks(&mut self) -> Result<(), ApiFetchResult> {
-        let blocks_headers = self.blocks_headers().await?;
-        // println!("Block headers len: {}", blocks_headers.items.len());
+        Ok(block_headers)
+    }
 
-        // headers are ordered by descending
-        self.qparams.max_height = self.qparams.limit + blocks_headers.items[0].height;
+    /// Fetch and store the blocks of `qparams`, returns how many were stored
+    pub async fn blocks(&self) -> Result<IndexedTotals, ApiFetchResult> {
+        let batch = self.fetch_batch(&self.qparams).await?;
+        self.store_batch(&batch).await
+    }
 
+    ///
+    /// Fetch the headers of a height range below `chain_head` and their
+    /// payloads, archived when the raw archive is enabled.
+    #[tracing::instrument(
+        skip(self, params),
+        fields(
+            chain_id = self.chain_id,
+            min_height = params.min_height,
+            max_height = params.max_height,
+            payload_requests,
+        )
+    )]
+    async fn fetch_batch(&self, params: &QueryParams) -> Result<FetchedBatch, ApiFetchResult> {
+        let chain = self.chain_id.to_string();
+        let timer = metrics()
+            .header_fetch_seconds
+            .with_label_values(&[&chain])
+            .start_timer();
+        let blocks_headers = self.blocks_headers(params).await?;
+        timer.observe_duration();
+        if blocks_headers.items.is_empty() {
+            return Ok(FetchedBatch {
+                headers: vec![],
+                payloads: HashMap::new(),
+            });
+        }
         let payloads_hashes = &blocks_headers
             .items
             .iter()
@@ -227,91 +544,665 @@ impl Ingest {
             .collect::<Vec<String>>();
         let body = json!(payloads_hashes);
 
-        let blocks_payloads = retry(ExponentialBackoff::default(), || async {
-            let url = format!(
-                "{}/chain/{}/payload/outputs/batch",
-                self.root_url, self.chain_id
-            );
-            let resp = self
-                .http_client
-                .post(url)
-                .headers(req_header_content_type())
-                .json(&body)
-                .send()
-                .await
-                .context("Failed to send a request")?;
-            let status = resp.status().as_u16();
-            if status != 200 {
-                let detail = format!(
-                    "Error: Failed fetching blocks payloads {}! Got http status {}",
-                    self.chain_id, status
+        let timer = metrics()
+            .payload_fetch_seconds
+            .with_label_values(&[&chain])
+            .start_timer();
+        let retries = AtomicU32::new(0);
+        let budget = self.client.retry_budget();
+        let blocks_payloads = retry_notify(
+            self.client.backoff(),
+            || async {
+                let url = format!(
+                    "{}/chain/{}/payload/outputs/batch",
+                    self.root_url, self.chain_id
                 );
-                println!("{}", &detail);
-                let err = backoff::Error::transient(anyhow::anyhow!(detail));
-                Err(err)
-            } else {
-                let block_headers_json = resp
-                    .json::<Vec<BlockPayload>>()
+                let request = self
+                    .client
+                    .post(url)
+                    .headers(req_header_content_type())
+                    .json(&body);
+                let resp = self
+                    .client
+                    .send(request)
+                    .await
+                    .map_err(|e| e.into_backoff(&budget))?;
+                let block_payloads_json: Vec<BlockPayload> =
+                    resp.json().map_err(|e| e.into_backoff(&budget))?;
+                let raw_payloads: Vec<Box<RawValue>> =
+                    resp.json().map_err(|e| e.into_backoff(&budget))?;
+                Ok((block_payloads_json, raw_payloads))
+            },
+            self.on_retry(PAYLOADS_REQUEST, &retries),
+        )
+        .await
+        .context("Failed to fetch block payloads from chainweb node.")
+        .map_err(ApiFetchResult::Failure)?;
+
+        timer.observe_duration();
+        Span::current().record("payload_requests", retries.into_inner() + 1);
+        let (blocks_payloads, raw_payloads) = blocks_payloads;
+        if let Some(archive) = &self.archive {
+            for (payload, raw) in blocks_payloads.iter().zip(raw_payloads) {
+                archive
+                    .store_payload(self.chain_id, &payload.payload_hash, raw.get().as_bytes())
                     .await
-                    .context("Failed to convert response to json.")?;
-                Ok(block_headers_json)
+                    .context("Failed to archive raw block payload")
+                    .map_err(ApiFetchResult::Failure)?;
             }
+        }
+
+        let payloads = blocks_payloads
+            .into_iter()
+            .map(|p| (p.payload_hash.clone(), p))
+            .collect::<HashMap<String, BlockPayload>>();
+
+        Ok(FetchedBatch {
+            headers: blocks_headers.items,
+            payloads,
         })
+    }
+
+    ///
+    /// Store the blocks of a fetched batch with their transactions, events
+    /// and derived tables, and advance the last processed block. Returns how
+    /// many blocks were stored.
+    ///
+    /// Height of the last block shared by the stored chain and the branch of
+    /// `lowest`, when the block stored below `lowest` is not its parent.
+    /// The fork point is searched at most `MAX_REORG_DEPTH` blocks lower.
+    async fn fork_height(&self, lowest: &BlockHeader) -> Result<Option<u64>, ApiFetchResult> {
+        let Some(below) = lowest.height.checked_sub(1) else {
+            return Ok(None);
+        };
+        let stored = BlockRecord::hashes(&self.pool, self.chain_id, below as i64, below as i64)
+            .await
+            .context("Failed to read stored block hashes")
+            .map_err(ApiFetchResult::Failure)?;
+        if stored.is_empty() || stored.contains(&lowest.parent) {
+            return Ok(None);
+        }
+
+        let params = QueryParams {
+            min_height: below.saturating_sub(MAX_REORG_DEPTH),
+            max_height: below,
+            limit: MAX_REORG_DEPTH + 1,
+        };
+        let branch = self.branch_headers(&lowest.parent, &params).await?;
+        let stored = BlockRecord::hashes(
+            &self.pool,
+            self.chain_id,
+            params.min_height as i64,
+            below as i64,
+        )
         .await
-        .context("Failed to fetch block headers from chainweb node.")
+        .context("Failed to read stored block hashes")
         .map_err(ApiFetchResult::Failure)?;
+        branch
+            .items
+            .iter()
+            .filter(|header| stored.contains(&header.hash))
+            .map(|header| header.height)
+            .max()
+            .with_context(|| {
+                format!(
+                    "Chain reorganized deeper than {} blocks below height {}",
+                    MAX_REORG_DEPTH, lowest.height
+                )
+            })
+            .map(Some)
+            .map_err(ApiFetchResult::Failure)
+    }
+
+    #[tracing::instrument(
+        skip_all,
+        fields(chain_id = self.chain_id, blocks = batch.headers.len(), transactions)
+    )]
+    async fn store_batch(&self, batch: &FetchedBatch) -> Result<IndexedTotals, ApiFetchResult> {
+        // headers are ordered by descending
+        let Some(last_block) = batch.headers.first() else {
+            return Ok(IndexedTotals::default());
+        };
+        let chain = self.chain_id.to_string();
+        let (blocks, failed) = self.decode_blocks(&batch.headers, &batch.payloads);
+        let totals = IndexedTotals {
+            blocks: batch.headers.len() as u64,
+            transactions: blocks.iter().map(|b| b.transactions.len() as u64).sum(),
+            events: blocks
+                .iter()
+                .flat_map(|b| b.outputs())
+                .map(|o| o.events.len() as u64)
+                .sum(),
+        };
+        Span::current().record("transactions", totals.transactions);
+
+        let timer = metrics()
+            .db_write_seconds
+            .with_label_values(&[&chain])
+            .start_timer();
+        let notifications = self
+            .store_blocks(&batch.headers, &blocks, failed)
+            .await
+            .context("Failed to store blocks in database")
+            .map_err(ApiFetchResult::Failure)?;
+        timer.observe_duration();
+        metrics().set_indexed(self.chain_id, last_block.height, last_block.creation_time);
 
-        let mut txs = vec![];
-        for block_payload in blocks_payloads {
-            if let Some(v) = block_payload.transactions {
-                txs.push(self.process_block_transactions(v))
+        if let Some(notifier) = &self.notifier {
+            // Sending only fails when nobody is subscribed
+            for notification in notifications {
+                let _ = notifier.send(notification);
             }
         }
 
-        for item in &blocks_headers.items {
-            let block = Block::new(item.chain_id, item.height);
-            block
-                .insert(&self.pool)
+        Ok(totals)
+    }
+
+    ///
+    /// Index an inclusive height range from the node, up to the chain head
+    /// in the current cut, and return the number of stored blocks. Blocks
+    /// already indexed are stored again, the last processed block of the
+    /// chain only moves forward.
+    #[tracing::instrument(skip(self), fields(chain_id = self.chain_id))]
+    pub async fn backfill(
+        &mut self,
+        min_height: u64,
+        max_height: u64,
+    ) -> Result<u64, anyhow::Error> {
+        let cut = self.current_cut().await?;
+        self.chain_head = cut
+            .hashes
+            .get(&self.chain_id)
+            .with_context(|| format!("Chain {} is not in the cut of the node", self.chain_id))?
+            .clone();
+        let max_height = max_height.min(self.chain_head.height);
+
+        let mut stored = 0;
+        let mut from = min_height;
+        while from <= max_height {
+            let to = max_height.min(from + self.qparams.limit.max(1) - 1);
+            self.qparams.min_height = from;
+            self.qparams.max_height = to;
+            stored += self.blocks().await?.blocks;
+            from = to + 1;
+        }
+
+        Ok(stored)
+    }
+
+    ///
+    /// Remove the blocks of the chain above `height` with every row derived
+    /// from them, and move the last processed block back to `height` so the
+    /// indexer fetches them again. Returns the number of removed blocks.
+    #[tracing::instrument(skip(self), fields(chain_id = self.chain_id))]
+    pub async fn rollback(&self, height: u64) -> Result<u64, anyhow::Error> {
+        let processors = self.all_processors();
+        let min_height = height as i64 + 1;
+
+        let mut tx = self.pool.begin().await?;
+        if let Some(lease) = &self.lease {
+            lease.fence(&mut tx, self.chain_id).await?;
+        }
+        let removed = BlockRecord::delete(&mut tx, self.chain_id, min_height, i64::MAX).await?;
+        delete_derived_rows(&mut tx, self.chain_id, min_height, i64::MAX).await?;
+        for processor in &processors {
+            processor
+                .delete(&mut tx, self.chain_id, min_height, i64::MAX)
+                .await?;
+        }
+        for processor in &processors {
+            processor.finish(&mut tx, self.chain_id, min_height).await?;
+        }
+        Block::rewind(&mut tx, self.chain_id, height as i64).await?;
+        tx.commit().await?;
+
+        Ok(removed)
+    }
+
+    ///
+    /// Rebuild the tables derived from block payloads for an inclusive height
+    /// range using only the raw archive, without any request to the node.
+    /// Only the canonical blocks are replayed, found by following the parent
+    /// links down from the top of the range, and a batch is only replaced
+    /// when the archive holds every block of it with its payload. Batches
+    /// are replayed from the highest one. Returns the number of replayed
+    /// blocks.
+    #[tracing::instrument(skip(self), fields(chain_id = self.chain_id))]
+    pub async fn reindex(&self, min_height: u64, max_height: u64) -> Result<u64, anyhow::Error> {
+        let archive = self
+            .archive
+            .as_ref()
+            .context("Raw archive must be enabled to reindex")?;
+
+        let processors = self.all_processors();
+        let mut replayed = 0;
+        let mut lowest = None;
+        let result = async {
+            let mut expected = self.canonical_hash_at(archive, max_height).await?;
+            let mut to = max_height;
+            loop {
+                let from = min_height.max(to.saturating_sub(self.qparams.limit.max(1) - 1));
+                let (headers, payloads, parent) =
+                    self.canonical_batch(archive, from, to, expected).await?;
+                let (blocks, failed) = self.decode_blocks(&headers, &payloads);
+
+                let mut tx = self.pool.begin().await?;
+                delete_derived_rows(&mut tx, self.chain_id, from as i64, to as i64).await?;
+                for processor in &processors {
+                    processor
+                        .delete(&mut tx, self.chain_id, from as i64, to as i64)
+                        .await?;
+                }
+                insert_derived_rows(&mut tx, &blocks, failed, &processors).await?;
+                tx.commit().await?;
+
+                info!(from, to, blocks = headers.len(), "Reindexed batch");
+                replayed += headers.len() as u64;
+                lowest = Some(from);
+                if from == min_height {
+                    return Ok::<_, anyhow::Error>(());
+                }
+                expected = parent;
+                to = from - 1;
+            }
+        }
+        .await;
+
+        // Snapshots after the range depend on it, so the aggregates are
+        // rebuilt once at the end rather than for every batch, also when a
+        // batch could not be replayed after higher ones were
+        if let Some(lowest) = lowest {
+            let mut tx = self.pool.begin().await?;
+            for processor in &processors {
+                processor
+                    .finish(&mut tx, self.chain_id, lowest as i64)
+                    .await?;
+            }
+            tx.commit().await?;
+        }
+        result?;
+
+        Ok(replayed)
+    }
+
+    ///
+    /// Hash of the canonical archived block at `height`: the parent of the
+    /// archived blocks one height above, or the only archived block at the
+    /// height when there are none.
+    async fn canonical_hash_at(
+        &self,
+        archive: &RawArchive,
+        height: u64,
+    ) -> Result<String, anyhow::Error> {
+        let decode = |raw: Vec<Vec<u8>>| {
+            raw.iter()
+                .map(|raw| serde_json::from_slice::<BlockHeader>(raw))
+                .collect::<Result<Vec<_>, _>>()
+                .context("Failed to decode archived block header")
+        };
+        let children = decode(
+            archive
+                .headers(self.chain_id, height + 1, height + 1)
+                .await
+                .context("Failed to read archived block headers")?,
+        )?;
+        let mut parents = children.into_iter().map(|h| h.parent).collect::<Vec<_>>();
+        parents.dedup();
+        match parents.as_slice() {
+            [parent] => return Ok(parent.clone()),
+            [] => {}
+            _ => anyhow::bail!(
+                "The archive holds forks at height {}, reindex up to a lower height",
+                height + 1
+            ),
+        }
+        let headers = decode(
+            archive
+                .headers(self.chain_id, height, height)
                 .await
-                .context("Failed to insert block to database")
-                .map_err(ApiFetchResult::Failure)?;
+                .context("Failed to read archived block headers")?,
+        )?;
+        match headers.as_slice() {
+            [header] => Ok(header.hash.clone()),
+            [] => anyhow::bail!("No archived block header at height {}", height),
+            _ => anyhow::bail!(
+                "The archive holds forks at height {}, reindex up to a lower height",
+                height
+            ),
         }
+    }
 
-        // Update processed log table
-        let last_block = blocks_headers.items.first().unwrap();
-        Block::new(last_block.chain_id, last_block.height)
-            .insert_as_last_processed_block(&self.pool)
+    ///
+    /// Canonical archived blocks from `from` to `to`, the block at `to`
+    /// having the hash `expected`, with their payloads and the hash of the
+    /// parent of the block at `from`. Fails unless every block and payload is
+    /// archived.
+    async fn canonical_batch(
+        &self,
+        archive: &RawArchive,
+        from: u64,
+        to: u64,
+        expected: String,
+    ) -> Result<(Vec<BlockHeader>, HashMap<String, BlockPayload>, String), anyhow::Error> {
+        let archived = archive
+            .headers(self.chain_id, from, to)
             .await
-            .context("Failed to insert last processed block in database")
-            .map_err(ApiFetchResult::Failure)?;
+            .context("Failed to read archived block headers")?
+            .iter()
+            .map(|raw| serde_json::from_slice::<BlockHeader>(raw))
+            .collect::<Result<Vec<_>, _>>()
+            .context("Failed to decode archived block header")?;
+        let (headers, parent) = canonical_headers(archived, from, to, expected)?;
 
-        Ok(())
+        let mut raw_payloads = HashMap::new();
+        for header in &headers {
+            if raw_payloads.contains_key(&header.payload_hash) {
+                continue;
+            }
+            let raw = archive
+                .payload(self.chain_id, &header.payload_hash)
+                .await
+                .context("Failed to read archived block payload")?;
+            raw_payloads.insert(header.payload_hash.clone(), raw);
+        }
+        let payloads = decode_archived_payloads(&headers, &raw_payloads)?;
+
+        Ok((headers, payloads, parent))
     }
 
-    fn process_block_transactions(&self, transactions: Vec<Vec<String>>) -> Vec<Transaction> {
-        let mut txs = vec![];
-        for transaction in transactions {
-            let decoded_tx = decode_from_base64_url(&transaction[0]);
-            let decoded_tx_output = decode_from_base64_url(&transaction[1]);
+    /// Decode the payloads of a batch of blocks. Transactions that fail to
+    /// decode are returned separately so they end up in the dead-letter table.
+    fn decode_blocks<'a>(
+        &self,
+        headers: &'a [BlockHeader],
+        payloads: &HashMap<String, BlockPayload>,
+    ) -> (Vec<DecodedBlock<'a>>, Vec<FailedTransaction>) {
+        let mut blocks = vec![];
+        let mut failed = vec![];
+        for header in headers {
+            let mut block = DecodedBlock {
+                header,
+                coinbase: None,
+                transactions: vec![],
+            };
+            if let Some(payload) = payloads.get(&header.payload_hash) {
+                match decode_coinbase(self.chain_id, header.height, &payload.coinbase) {
+                    Ok(coinbase) => block.coinbase = Some(coinbase),
+                    Err(e) => {
+                        warn!(error = %e, "Failed to decode coinbase");
+                        failed.push(FailedTransaction::new(
+                            header,
+                            std::slice::from_ref(&payload.coinbase),
+                            &e,
+                        ));
+                    }
+                }
+                for transaction in payload.transactions.iter().flatten() {
+                    match decode_transaction(self.chain_id, header.height, transaction) {
+                        Ok(tx) => block.transactions.push(tx),
+                        Err(e) => {
+                            warn!(error = %e, "Failed to decode transaction");
+                            failed.push(FailedTransaction::new(header, transaction, &e));
+                        }
+                    }
+                }
+            }
+            blocks.push(block);
+        }
+        metrics()
+            .decode_failures
+            .with_label_values(&[&self.chain_id.to_string()])
+            .inc_by(failed.len() as u64);
+        (blocks, failed)
+    }
 
-            // for debugging purpose to see what goes wrong with the next type conversation
-            let _o: serde_json::Value = serde_json::from_slice(&decoded_tx_output).unwrap();
-            // debug!("{}", _o);
-            let _out: Output = serde_json::from_slice(&decoded_tx_output).unwrap();
+    ///
+    /// Write a batch of blocks, the tables derived from them and the last
+    /// processed block atomically, under the lease of the chain when it is
+    /// shared. Rows of the batch height range are replaced so a batch can be
+    /// stored again, and the processors finish from the lowest height of the
+    /// batch. Returns the notifications of the rows.
+    #[tracing::instrument(
+        skip_all,
+        fields(chain_id = self.chain_id, min_height, max_height, failed = failed.len())
+    )]
+    async fn store_blocks(
+        &self,
+        headers: &[BlockHeader],
+        blocks: &[DecodedBlock<'_>],
+        failed: Vec<FailedTransaction>,
+    ) -> Result<Vec<Notification>, anyhow::Error> {
+        let mut tx = self.pool.begin().await?;
+        if let Some(lease) = &self.lease {
+            lease.fence(&mut tx, self.chain_id).await?;
+        }
+        let heights = headers.iter().map(|h| h.height as i64);
+        let (min_height, max_height) = match (heights.clone().min(), heights.max()) {
+            (Some(min), Some(max)) => (min, max),
+            _ => return Ok(vec![]),
+        };
+        Span::current()
+            .record("min_height", min_height)
+            .record("max_height", max_height);
 
-            // for debugging purpose to see what goes wrong with the next type conversation
-            let _t: serde_json::Value = serde_json::from_slice(&decoded_tx).unwrap();
-            // debug!("{}", _t);
-            let tx: TransactionWithCmdSigs = serde_json::from_slice(&decoded_tx).unwrap();
+        // Headers come latest first, notifications are sent in chain order
+        let mut headers = headers.iter().collect::<Vec<_>>();
+        headers.sort_by_key(|h| h.height);
+        let mut notifications = vec![];
+        for header in headers {
+            let block = BlockRecord::new(header);
+            notifications.push(Notification::Block(block.clone()));
+            block.insert(&mut tx).await?;
+        }
 
-            // debug!("TX: {:?}", tx);
-            // debug!("OUTPUT: {:?}", out);
-            // let tx: TransactionWithCmdSigs = serde_json::from_value(_tx).unwrap();
-            // cmd is string so to make serde works we need to use ::from_str then ::from_value
-            let _cmd: Transaction =
-                serde_json::from_value(serde_json::from_str(&tx.cmd).unwrap()).unwrap();
-            txs.push(_cmd);
+        let processors = self.all_processors();
+        delete_derived_rows(&mut tx, self.chain_id, min_height, max_height).await?;
+        for processor in &processors {
+            processor
+                .delete(&mut tx, self.chain_id, min_height, max_height)
+                .await?;
         }
-        txs
+        notifications.extend(insert_derived_rows(&mut tx, blocks, failed, &processors).await?);
+        for processor in &processors {
+            processor.finish(&mut tx, self.chain_id, min_height).await?;
+        }
+        Block::new(self.chain_id as u16, max_height as u64)
+            .insert_as_last_processed_block(&mut tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(notifications)
+    }
+}
+
+///
+/// Follow the parent links of archived headers down from the block `expected`
+/// at `to` to the block at `from`. Returns the headers in chain order and the
+/// hash of the parent of the block at `from`.
+fn canonical_headers(
+    archived: Vec<BlockHeader>,
+    from: u64,
+    to: u64,
+    expected: String,
+) -> Result<(Vec<BlockHeader>, String), anyhow::Error> {
+    let mut by_hash = archived
+        .into_iter()
+        .map(|h| (h.hash.clone(), h))
+        .collect::<HashMap<_, _>>();
+
+    let mut headers = vec![];
+    let mut expected = expected;
+    for height in (from..=to).rev() {
+        let header = by_hash
+            .remove(&expected)
+            .filter(|h| h.height == height)
+            .with_context(|| {
+                format!("No archived block header {} at height {}", expected, height)
+            })?;
+        expected = header.parent.clone();
+        headers.push(header);
+    }
+    headers.reverse();
+
+    Ok((headers, expected))
+}
+
+/// Decode the archived payload of every header, by payload hash
+fn decode_archived_payloads(
+    headers: &[BlockHeader],
+    raw_payloads: &HashMap<String, Option<Vec<u8>>>,
+) -> Result<HashMap<String, BlockPayload>, anyhow::Error> {
+    let mut payloads = HashMap::new();
+    for header in headers {
+        if payloads.contains_key(&header.payload_hash) {
+            continue;
+        }
+        let raw = raw_payloads
+            .get(&header.payload_hash)
+            .and_then(Option::as_ref)
+            .with_context(|| {
+                format!(
+                    "No archived payload {} for block {} at height {}",
+                    header.payload_hash, header.hash, header.height
+                )
+            })?;
+        let payload: BlockPayload =
+            serde_json::from_slice(raw).context("Failed to decode archived block payload")?;
+        payloads.insert(header.payload_hash.clone(), payload);
+    }
+
+    Ok(payloads)
+}
+
+async fn insert_derived_rows(
+    tx: &mut Transaction<'_, Postgres>,
+    blocks: &[DecodedBlock<'_>],
+    failed: Vec<FailedTransaction>,
+    processors: &[Arc<dyn BlockProcessor>],
+) -> Result<Vec<Notification>, sqlx::Error> {
+    // Headers come latest first, rows are written in chain order
+    let mut blocks = blocks.iter().collect::<Vec<_>>();
+    blocks.sort_by_key(|b| b.header.height);
+
+    let mut notifications = vec![];
+    for block in blocks {
+        for (idx, transaction) in block.transactions.iter().enumerate() {
+            let record = TransactionRecord::new(block.header, idx, transaction);
+            notifications.push(Notification::Transaction(record.clone()));
+            record.insert(tx).await?;
+        }
+        for output in block.outputs() {
+            for (idx, event) in output.events.iter().enumerate() {
+                let record = EventRecord::new(block.header, output, idx, event);
+                notifications.push(Notification::Event(record.clone()));
+                record.insert(tx).await?;
+            }
+        }
+        for processor in processors {
+            processor.process_block(tx, block).await?;
+        }
+    }
+    for failed_transaction in failed {
+        failed_transaction.insert(tx).await?;
+    }
+
+    Ok(notifications)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn header(height: u64, hash: &str, parent: &str) -> BlockHeader {
+        serde_json::from_value(json!({
+            "chainId": 0,
+            "chainwebVersion": "mainnet01",
+            "creationTime": 0,
+            "epochStart": 0,
+            "featureFlags": 0,
+            "hash": hash,
+            "height": height,
+            "nonce": "0",
+            "parent": parent,
+            "payloadHash": format!("p-{}", hash),
+            "target": "t",
+            "weight": "w",
+            "adjacents": {}
+        }))
+        .unwrap()
+    }
+
+    fn hashes(headers: &[BlockHeader]) -> Vec<&str> {
+        headers.iter().map(|h| h.hash.as_str()).collect()
+    }
+
+    fn raw_payload(header: &BlockHeader) -> Option<Vec<u8>> {
+        let payload = json!({
+            "coinbase": "",
+            "minerData": "",
+            "outputsHash": "o",
+            "payloadHash": header.payload_hash,
+            "transactionsHash": "t",
+            "transactions": []
+        });
+        Some(payload.to_string().into_bytes())
+    }
+
+    #[test]
+    fn canonical_headers_follow_parents_past_forks() {
+        let archived = vec![
+            header(12, "c", "b"),
+            header(11, "b", "a"),
+            header(11, "b2", "a"),
+            header(12, "c2", "b2"),
+            header(10, "a", "z"),
+        ];
+
+        let (headers, parent) = canonical_headers(archived, 10, 12, "c".to_string()).unwrap();
+        assert_eq!(hashes(&headers), ["a", "b", "c"]);
+        assert_eq!(parent, "z");
+    }
+
+    #[test]
+    fn canonical_headers_fail_on_a_missing_header() {
+        let archived = vec![header(12, "c", "b"), header(10, "a", "z")];
+        let error = canonical_headers(archived, 10, 12, "c".to_string()).unwrap_err();
+        assert_eq!(error.to_string(), "No archived block header b at height 11");
+    }
+
+    #[test]
+    fn canonical_headers_fail_on_a_parent_at_another_height() {
+        let archived = vec![header(12, "c", "a"), header(10, "a", "z")];
+        assert!(canonical_headers(archived, 10, 12, "c".to_string()).is_err());
+    }
+
+    #[test]
+    fn archived_payloads_are_decoded_by_hash() {
+        let headers = vec![header(10, "a", "z"), header(11, "b", "a")];
+        let raw = headers
+            .iter()
+            .map(|h| (h.payload_hash.clone(), raw_payload(h)))
+            .collect();
+
+        let payloads = decode_archived_payloads(&headers, &raw).unwrap();
+        assert_eq!(payloads.len(), 2);
+        assert_eq!(payloads["p-b"].payload_hash, "p-b");
+    }
+
+    #[test]
+    fn archived_payloads_fail_on_a_missing_payload() {
+        let headers = vec![header(10, "a", "z"), header(11, "b", "a")];
+        let raw = HashMap::from([
+            ("p-a".to_string(), raw_payload(&headers[0])),
+            ("p-b".to_string(), None),
+        ]);
+
+        let error = decode_archived_payloads(&headers, &raw).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "No archived payload p-b for block b at height 11"
+        );
     }
 }
@@ -0,0 +1,348 @@
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use sqlx::{PgPool, Postgres, Transaction};
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::entities::Block;
+use crate::ingest::{IndexedTotals, Ingest};
+
+///
+/// Leases of the `chain_leases` table held by an instance. A chain is
+/// indexed by the instance holding its lease, until the lease expires
+/// without being renewed and another instance claims it.
+#[derive(Debug, Clone)]
+pub struct ChainLeases {
+    pub pool: PgPool,
+    pub instance_id: String,
+    pub ttl: Duration,
+}
+
+impl ChainLeases {
+    pub fn new(pool: PgPool, instance_id: String, ttl: Duration) -> Self {
+        Self {
+            pool,
+            instance_id,
+            ttl,
+        }
+    }
+
+    ///
+    /// Record that the instance is alive, forget the instances without a
+    /// heartbeat for `ttl` and return the number of live ones.
+    pub async fn heartbeat(&self) -> Result<u64, sqlx::Error> {
+        let ttl = self.ttl.as_secs_f64();
+        sqlx::query!(
+            r#"
+            INSERT INTO indexer_instances(instance_id, heartbeat_at)
+            VALUES ($1, now())
+            ON CONFLICT (instance_id)
+            DO UPDATE SET heartbeat_at = EXCLUDED.heartbeat_at
+            "#,
+            self.instance_id
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM indexer_instances WHERE heartbeat_at < now() - make_interval(secs => $1)",
+            ttl
+        )
+        .execute(&self.pool)
+        .await?;
+        let live = sqlx::query_scalar!(r#"SELECT COUNT(*) AS "count!" FROM indexer_instances"#)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(live as u64)
+    }
+
+    /// Take the lease of a chain when it is free, expired or already held
+    pub async fn claim(&self, chain_id: i16) -> Result<bool, sqlx::Error> {
+        let claimed = sqlx::query_scalar!(
+            r#"
+            INSERT INTO chain_leases(chain_id, instance_id, acquired_at, expires_at)
+            VALUES ($1, $2, now(), now() + make_interval(secs => $3))
+            ON CONFLICT (chain_id)
+            DO UPDATE SET
+                instance_id = EXCLUDED.instance_id,
+                acquired_at = EXCLUDED.acquired_at,
+                expires_at = EXCLUDED.expires_at
+            WHERE chain_leases.expires_at < now()
+                OR chain_leases.instance_id = EXCLUDED.instance_id
+            RETURNING chain_id
+            "#,
+            chain_id,
+            self.instance_id,
+            self.ttl.as_secs_f64()
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(claimed.is_some())
+    }
+
+    ///
+    /// Extend the leases of the chains still held by the instance and
+    /// return them. A chain missing from the result was claimed by another
+    /// instance after its lease expired.
+    pub async fn renew(&self, chains: &[i16]) -> Result<Vec<i16>, sqlx::Error> {
+        sqlx::query_scalar!(
+            r#"
+            UPDATE chain_leases SET expires_at = now() + make_interval(secs => $3)
+            WHERE instance_id = $1 AND chain_id = ANY($2)
+            RETURNING chain_id
+            "#,
+            self.instance_id,
+            chains,
+            self.ttl.as_secs_f64()
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    ///
+    /// Fail unless the instance holds the unexpired lease of a chain. The
+    /// lease row stays locked until the transaction ends, so that no other
+    /// instance claims the chain before its writes are committed.
+    pub async fn fence(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+    ) -> Result<(), anyhow::Error> {
+        let held = sqlx::query_scalar!(
+            r#"
+            SELECT chain_id FROM chain_leases
+            WHERE chain_id = $1 AND instance_id = $2 AND expires_at > now()
+            FOR SHARE
+            "#,
+            chain_id,
+            self.instance_id
+        )
+        .fetch_optional(tx)
+        .await?;
+        if held.is_none() {
+            return Err(LeaseLost {
+                chain_id,
+                instance_id: self.instance_id.clone(),
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// Give up the leases of chains, for other instances to claim them
+    pub async fn release(&self, chains: &[i16]) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM chain_leases WHERE instance_id = $1 AND chain_id = ANY($2)",
+            self.instance_id,
+            chains
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Give up every lease and leave the live instances
+    pub async fn release_all(&self) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM chain_leases WHERE instance_id = $1",
+            self.instance_id
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query!(
+            "DELETE FROM indexer_instances WHERE instance_id = $1",
+            self.instance_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// Lease of a chain no longer held by the instance writing it
+#[derive(thiserror::Error, Debug)]
+#[error("Chain {chain_id} is no longer leased to {instance_id}")]
+pub struct LeaseLost {
+    pub chain_id: i16,
+    pub instance_id: String,
+}
+
+///
+/// Indexers of the chains an instance holds a lease on. Every heartbeat
+/// the instance renews its leases, stops the indexers of the chains it lost,
+/// then claims or releases chains to hold its share of them among the live
+/// instances.
+pub struct ShardedIndexers {
+    leases: ChainLeases,
+    heartbeat: Duration,
+    /// Indexers of every chain, cloned when their chain is claimed
+    indexers: HashMap<i16, Ingest>,
+    running: HashMap<i16, JoinHandle<Result<IndexedTotals, anyhow::Error>>>,
+    /// Chains held after reaching their end height
+    done: HashSet<i16>,
+    renewed_at: Instant,
+}
+
+impl ShardedIndexers {
+    pub fn new(leases: ChainLeases, heartbeat: Duration, indexers: Vec<Ingest>) -> Self {
+        Self {
+            leases,
+            heartbeat,
+            indexers: indexers.into_iter().map(|i| (i.chain_id, i)).collect(),
+            running: HashMap::new(),
+            done: HashSet::new(),
+            renewed_at: Instant::now(),
+        }
+    }
+
+    /// Balance the chains every heartbeat, never returns
+    pub async fn run(&mut self) {
+        info!(
+            instance_id = self.leases.instance_id,
+            chains = self.indexers.len(),
+            "Sharing chains with the other instances"
+        );
+        let mut interval = tokio::time::interval(self.heartbeat);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.balance().await {
+                error!(error = %e, "Failed to update the chain leases");
+                // Another instance may index the chains once the leases
+                // expire, which can happen before the next heartbeat
+                if self.renewed_at.elapsed() + self.heartbeat >= self.leases.ttl {
+                    self.stop(&self.held()).await;
+                }
+            }
+        }
+    }
+
+    /// Stop every indexer and release the leases
+    pub async fn shutdown(mut self) {
+        self.stop(&self.held()).await;
+        if let Err(e) = self.leases.release_all().await {
+            warn!(error = %e, "Failed to release the chain leases");
+        }
+    }
+
+    async fn balance(&mut self) -> Result<(), sqlx::Error> {
+        let live = self.leases.heartbeat().await?;
+
+        // A failed indexer gives its chain to the next claim, a finished one
+        // keeps it so that no instance indexes it again
+        let finished = self
+            .running
+            .iter()
+            .filter(|(_, handle)| handle.is_finished())
+            .map(|(chain_id, _)| *chain_id)
+            .collect::<Vec<_>>();
+        let mut failed = vec![];
+        for chain_id in finished {
+            if let Some(handle) = self.running.remove(&chain_id) {
+                match handle.await {
+                    Ok(Ok(_)) => {
+                        self.done.insert(chain_id);
+                        continue;
+                    }
+                    Ok(Err(e)) => error!(chain_id, error = %e, "Indexer failed"),
+                    Err(e) => error!(chain_id, error = %e, "Indexer panicked"),
+                }
+                failed.push(chain_id);
+            }
+        }
+        if !failed.is_empty() {
+            self.leases.release(&failed).await?;
+        }
+
+        let held = self.held();
+        // The leases expire `ttl` after the query started at the latest
+        let renewing_at = Instant::now();
+        let renewed = self.leases.renew(&held).await?;
+        self.renewed_at = renewing_at;
+        let lost = held
+            .into_iter()
+            .filter(|c| !renewed.contains(c))
+            .collect::<Vec<_>>();
+        if !lost.is_empty() {
+            warn!(chains = ?lost, "Chain leases taken by another instance");
+            self.stop(&lost).await;
+        }
+
+        let share = (self.indexers.len() as u64).div_ceil(live.max(1)) as usize;
+        let held = self.held();
+        if held.len() > share {
+            let extra = held[share..].to_vec();
+            info!(chains = ?extra, live, "Releasing chains to the other instances");
+            self.stop(&extra).await;
+            self.leases.release(&extra).await?;
+            return Ok(());
+        }
+
+        let mut free = self
+            .indexers
+            .keys()
+            .filter(|c| !held.contains(c))
+            .copied()
+            .collect::<Vec<_>>();
+        free.sort_unstable();
+        let mut holding = held.len();
+        for chain_id in free {
+            if holding >= share {
+                break;
+            }
+            if self.leases.claim(chain_id).await? {
+                self.start(chain_id).await?;
+                holding += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Start indexing a claimed chain after its last processed block
+    async fn start(&mut self, chain_id: i16) -> Result<(), sqlx::Error> {
+        let mut indexer = self.indexers[&chain_id].clone();
+        indexer.lease = Some(self.leases.clone());
+        if let Some(height) = Block::last_processed_height(&self.leases.pool, chain_id).await? {
+            indexer.qparams.min_height = height as u64 + 1;
+        }
+        info!(
+            chain_id,
+            min_height = indexer.qparams.min_height,
+            "Claimed chain"
+        );
+        self.running
+            .insert(chain_id, tokio::spawn(async move { indexer.start().await }));
+
+        Ok(())
+    }
+
+    /// Chains the instance holds a lease on, by id
+    fn held(&self) -> Vec<i16> {
+        let mut chains = self
+            .running
+            .keys()
+            .chain(&self.done)
+            .copied()
+            .collect::<Vec<_>>();
+        chains.sort_unstable();
+        chains
+    }
+
+    /// Abort the indexers of chains and wait for them to stop
+    async fn stop(&mut self, chains: &[i16]) {
+        for chain_id in chains {
+            self.done.remove(chain_id);
+            if let Some(handle) = self.running.remove(chain_id) {
+                handle.abort();
+                let _ = handle.await;
+                info!(chain_id, "Stopped indexing chain");
+            }
+        }
+    }
+}
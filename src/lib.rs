@@ -1,8 +1,28 @@
 // pub mod fetch_service;
 
+pub mod accounts;
+pub mod archive;
+pub mod calls;
+pub mod cli;
+pub mod client;
 pub mod configuration;
+pub mod decode;
+pub mod deployments;
 pub mod entities;
+pub mod export;
+pub mod graphql;
 pub mod ingest;
+pub mod leases;
+pub mod metrics;
+pub mod network;
+pub mod nfts;
+pub mod pact;
+pub mod pagination;
+pub mod processors;
+pub mod routes;
 pub mod startup;
+pub mod subscriptions;
+pub mod telemetry;
 pub mod types;
 pub mod utils;
+pub mod verify;
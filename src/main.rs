@@ -1,12 +1,18 @@
+use anyhow::Context;
+use chainweb_indexer::cli::Cli;
 use chainweb_indexer::configuration::get_configuration;
-use chainweb_indexer::startup::Application;
+use chainweb_indexer::telemetry::{init_telemetry, shutdown_telemetry};
+use clap::Parser;
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
-    let configuration = get_configuration().expect("Failed to read configuration");
-
-    let application = Application::build(configuration).await?;
-    application.run_indexers().await?;
-
-    Ok(())
+    let cli = Cli::parse();
+    let mut configuration = get_configuration().context("Failed to read configuration")?;
+    cli.overrides.apply(&mut configuration);
+    configuration.validate()?;
+    init_telemetry(&configuration.telemetry)?;
+    let result = cli.run(configuration).await;
+    // Flush the spans not exported yet
+    shutdown_telemetry();
+    result
 }
@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use prometheus::{
+    Encoder, GaugeVec, HistogramOpts, HistogramVec, IntCounterVec, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+
+/// Request labels of `Metrics::retries`
+pub const CUT_REQUEST: &str = "cut";
+pub const HEADERS_REQUEST: &str = "headers";
+pub const PAYLOADS_REQUEST: &str = "payloads";
+
+///
+/// Prometheus metrics of the indexers, labelled by chain. Exposed in the
+/// text format by the `/metrics` endpoint.
+pub struct Metrics {
+    registry: Registry,
+    pub indexed_height: IntGaugeVec,
+    pub node_height: IntGaugeVec,
+    pub lag_blocks: IntGaugeVec,
+    pub lag_seconds: GaugeVec,
+    pub header_fetch_seconds: HistogramVec,
+    pub payload_fetch_seconds: HistogramVec,
+    pub db_write_seconds: HistogramVec,
+    pub retries: IntCounterVec,
+    pub decode_failures: IntCounterVec,
+    pub reorgs: IntCounterVec,
+    /// Creation time in microseconds of the latest indexed block per chain,
+    /// the lag in seconds is computed when the metrics are read
+    indexed_times: Mutex<HashMap<i16, u64>>,
+}
+
+/// Metrics shared by every indexer and the api server
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        let chain = &["chain"];
+        let int_gauge = |name: &str, help: &str| {
+            let gauge = IntGaugeVec::new(Opts::new(name, help), chain).unwrap();
+            registry.register(Box::new(gauge.clone())).unwrap();
+            gauge
+        };
+        let histogram = |name: &str, help: &str| {
+            let histogram = HistogramVec::new(HistogramOpts::new(name, help), chain).unwrap();
+            registry.register(Box::new(histogram.clone())).unwrap();
+            histogram
+        };
+        let counter = |name: &str, help: &str, labels: &[&str]| {
+            let counter = IntCounterVec::new(Opts::new(name, help), labels).unwrap();
+            registry.register(Box::new(counter.clone())).unwrap();
+            counter
+        };
+
+        let lag_seconds = GaugeVec::new(
+            Opts::new(
+                "indexer_lag_seconds",
+                "Age of the latest indexed block of a chain",
+            ),
+            chain,
+        )
+        .unwrap();
+        registry.register(Box::new(lag_seconds.clone())).unwrap();
+
+        Self {
+            indexed_height: int_gauge(
+                "indexer_indexed_height",
+                "Height of the latest indexed block of a chain",
+            ),
+            node_height: int_gauge(
+                "indexer_node_height",
+                "Height of a chain in the cut of the node",
+            ),
+            lag_blocks: int_gauge(
+                "indexer_lag_blocks",
+                "Blocks of a chain known by the node and not indexed yet",
+            ),
+            lag_seconds,
+            header_fetch_seconds: histogram(
+                "indexer_header_fetch_seconds",
+                "Time to fetch a batch of block headers, retries included",
+            ),
+            payload_fetch_seconds: histogram(
+                "indexer_payload_fetch_seconds",
+                "Time to fetch the payloads of a batch, retries included",
+            ),
+            db_write_seconds: histogram(
+                "indexer_db_write_seconds",
+                "Time to store a batch of blocks and the derived tables",
+            ),
+            retries: counter(
+                "indexer_retries_total",
+                "Failed requests to the node that are retried",
+                &["chain", "request"],
+            ),
+            decode_failures: counter(
+                "indexer_decode_failures_total",
+                "Transactions and coinbases that failed to decode",
+                chain,
+            ),
+            reorgs: counter(
+                "indexer_reorgs_total",
+                "Chain reorganizations rolled back to their fork point",
+                chain,
+            ),
+            indexed_times: Mutex::new(HashMap::new()),
+            registry,
+        }
+    }
+
+    /// Record the latest indexed block of a chain
+    pub fn set_indexed(&self, chain_id: i16, height: u64, creation_time: u64) {
+        let chain = chain_id.to_string();
+        self.indexed_height
+            .with_label_values(&[&chain])
+            .set(height as i64);
+        self.indexed_times
+            .lock()
+            .unwrap()
+            .insert(chain_id, creation_time);
+        self.update_lag_blocks(&chain);
+    }
+
+    /// Record the height of a chain in the cut of the node
+    pub fn set_node_height(&self, chain_id: i16, height: u64) {
+        let chain = chain_id.to_string();
+        // Counters of the chain are exported from 0 rather than on first use
+        for request in [CUT_REQUEST, HEADERS_REQUEST, PAYLOADS_REQUEST] {
+            self.retries.with_label_values(&[&chain, request]);
+        }
+        self.decode_failures.with_label_values(&[&chain]);
+        self.reorgs.with_label_values(&[&chain]);
+        self.node_height
+            .with_label_values(&[&chain])
+            .set(height as i64);
+        self.update_lag_blocks(&chain);
+    }
+
+    fn update_lag_blocks(&self, chain: &str) {
+        let node = self.node_height.with_label_values(&[chain]).get();
+        let indexed = self.indexed_height.with_label_values(&[chain]).get();
+        self.lag_blocks
+            .with_label_values(&[chain])
+            .set((node - indexed).max(0));
+    }
+
+    /// Every metric in the Prometheus text format
+    pub fn encode(&self) -> String {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        for (chain_id, creation_time) in self.indexed_times.lock().unwrap().iter() {
+            self.lag_seconds
+                .with_label_values(&[&chain_id.to_string()])
+                .set(now.saturating_sub(*creation_time) as f64 / 1_000_000.0);
+        }
+
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("Failed to encode metrics");
+        String::from_utf8(buffer).expect("Metrics are not utf-8")
+    }
+}
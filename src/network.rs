@@ -0,0 +1,175 @@
+use serde::Deserialize;
+
+use crate::types::NodeInfo;
+
+/// Chainweb versions the indexer knows the chain graph history of
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    Mainnet01,
+    Testnet04,
+    Development,
+    /// Any other version, the graph history comes from configuration
+    Custom,
+}
+
+impl Network {
+    /// Chainweb version name used in the node API paths
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Network::Mainnet01 => "mainnet01",
+            Network::Testnet04 => "testnet04",
+            Network::Development => "development",
+            Network::Custom => "custom",
+        }
+    }
+
+    /// Chain graph history of the network. `custom` is only used by
+    /// [`Network::Custom`].
+    pub fn chain_graph(&self, custom: &[GraphTransition]) -> Result<ChainGraph, String> {
+        let transitions = match self {
+            Network::Mainnet01 => vec![
+                GraphTransition::with_chain_count(0, 10),
+                GraphTransition::with_chain_count(852054, 20),
+            ],
+            Network::Testnet04 => vec![
+                GraphTransition::with_chain_count(0, 10),
+                GraphTransition::with_chain_count(332604, 20),
+            ],
+            Network::Development => vec![
+                GraphTransition::with_chain_count(0, 10),
+                GraphTransition::with_chain_count(60, 20),
+            ],
+            Network::Custom => custom.to_vec(),
+        };
+        ChainGraph::new(transitions)
+    }
+}
+
+/// Set of chains that exist from `height` on
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct GraphTransition {
+    pub height: u64,
+    pub chains: Vec<i16>,
+}
+
+impl GraphTransition {
+    pub fn with_chain_count(height: u64, count: i16) -> Self {
+        Self {
+            height,
+            chains: (0..count).collect(),
+        }
+    }
+}
+
+///
+/// History of the chain graph of a network, ordered by height. Chains are
+/// only ever added, so a chain exists from the first transition it appears in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChainGraph {
+    transitions: Vec<GraphTransition>,
+}
+
+impl ChainGraph {
+    pub fn new(mut transitions: Vec<GraphTransition>) -> Result<Self, String> {
+        transitions.sort_by_key(|t| t.height);
+        for transition in transitions.iter_mut() {
+            transition.chains.sort_unstable();
+        }
+        match transitions.first() {
+            None => return Err("The chain graph history is empty.".to_string()),
+            Some(t) if t.height != 0 => {
+                return Err(format!(
+                    "The chain graph history must start at height 0, got {}.",
+                    t.height
+                ))
+            }
+            _ => {}
+        }
+        Ok(Self { transitions })
+    }
+
+    /// Graph history reported by the node `/info` endpoint
+    pub fn from_node_info(info: &NodeInfo) -> Result<Self, String> {
+        let transitions = info
+            .node_graph_history
+            .iter()
+            .map(|(height, adjacencies)| GraphTransition {
+                height: *height,
+                chains: adjacencies.iter().map(|(chain_id, _)| *chain_id).collect(),
+            })
+            .collect();
+        Self::new(transitions)
+    }
+
+    ///
+    /// Graph history from the genesis height of every chain, as reported by
+    /// recent nodes in `/info`
+    pub fn from_genesis_heights(genesis_heights: &[(String, u64)]) -> Result<Self, String> {
+        let mut genesis = genesis_heights
+            .iter()
+            .map(|(chain_id, height)| {
+                chain_id
+                    .parse::<i16>()
+                    .map(|chain_id| (*height, chain_id))
+                    .map_err(|_| format!("Invalid chain id in genesis heights: {}.", chain_id))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        genesis.sort_unstable();
+
+        let mut transitions: Vec<GraphTransition> = vec![];
+        for (height, chain_id) in genesis {
+            match transitions.last_mut() {
+                Some(last) if last.height == height => last.chains.push(chain_id),
+                last => {
+                    let mut chains = last.map(|t| t.chains.clone()).unwrap_or_default();
+                    chains.push(chain_id);
+                    transitions.push(GraphTransition { height, chains });
+                }
+            }
+        }
+        Self::new(transitions)
+    }
+
+    /// Every chain that exists at some point of the history
+    pub fn chain_ids(&self) -> Vec<i16> {
+        let mut chain_ids = self
+            .transitions
+            .iter()
+            .flat_map(|t| t.chains.iter().copied())
+            .collect::<Vec<i16>>();
+        chain_ids.sort_unstable();
+        chain_ids.dedup();
+        chain_ids
+    }
+
+    /// First height of a chain, `None` if the chain never exists
+    pub fn genesis_height(&self, chain_id: i16) -> Option<u64> {
+        self.transitions
+            .iter()
+            .find(|t| t.chains.contains(&chain_id))
+            .map(|t| t.height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn genesis_heights_match_the_mainnet_graph() {
+        let genesis_heights = (0..20)
+            .map(|c| (c.to_string(), if c < 10 { 0 } else { 852054 }))
+            .collect::<Vec<_>>();
+        let graph = ChainGraph::from_genesis_heights(&genesis_heights).unwrap();
+        assert_eq!(graph, Network::Mainnet01.chain_graph(&[]).unwrap());
+    }
+
+    #[test]
+    fn genesis_heights_must_start_at_zero() {
+        let genesis_heights = vec![("0".to_string(), 10)];
+        assert!(ChainGraph::from_genesis_heights(&genesis_heights).is_err());
+        let genesis_heights = vec![("zero".to_string(), 0)];
+        assert!(ChainGraph::from_genesis_heights(&genesis_heights).is_err());
+    }
+}
@@ -0,0 +1,333 @@
+use bigdecimal::BigDecimal;
+use serde_json::Value;
+
+use crate::types::Event;
+use crate::utils::parse_pact_decimal;
+
+/// Event of a marmalade ledger or of a poly-fungible-v2 module
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NftEventKind {
+    /// `TOKEN`, creation of a token
+    Token,
+    Mint,
+    Burn,
+    /// `TRANSFER` with a token id
+    Transfer,
+    /// `RECONCILE`, balances of the accounts after a change
+    Reconcile,
+    /// `SUPPLY`, total supply after a mint or a burn
+    Supply,
+    /// `SALE`, offer of a token for sale
+    Sale,
+    /// `BUY`, sale completed
+    Buy,
+    /// `WITHDRAW`, sale cancelled
+    Withdraw,
+}
+
+impl NftEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NftEventKind::Token => "token",
+            NftEventKind::Mint => "mint",
+            NftEventKind::Burn => "burn",
+            NftEventKind::Transfer => "transfer",
+            NftEventKind::Reconcile => "reconcile",
+            NftEventKind::Supply => "supply",
+            NftEventKind::Sale => "sale",
+            NftEventKind::Buy => "buy",
+            NftEventKind::Withdraw => "withdraw",
+        }
+    }
+}
+
+/// Parameters of a `TOKEN` event
+#[derive(Debug, Clone)]
+pub struct TokenCreation {
+    pub precision: Option<i32>,
+    /// Only given by marmalade v2
+    pub uri: Option<String>,
+    /// Policy modules, a single one for marmalade v1
+    pub policies: Value,
+}
+
+///
+/// Token event with its parameters decoded. Accounts are the seller and the
+/// buyer for sales, the owner for mints and burns.
+#[derive(Debug, Clone)]
+pub struct NftEvent {
+    pub kind: NftEventKind,
+    pub token_id: String,
+    pub sender: Option<String>,
+    pub receiver: Option<String>,
+    /// Amount of the token, or the total supply for `SUPPLY`
+    pub amount: Option<BigDecimal>,
+    /// Balances after a `RECONCILE`
+    pub sender_balance: Option<BigDecimal>,
+    pub receiver_balance: Option<BigDecimal>,
+    pub sale_id: Option<String>,
+    pub creation: Option<TokenCreation>,
+}
+
+impl NftEvent {
+    fn new(kind: NftEventKind, token_id: &str) -> Self {
+        Self {
+            kind,
+            token_id: token_id.to_string(),
+            sender: None,
+            receiver: None,
+            amount: None,
+            sender_balance: None,
+            receiver_balance: None,
+            sale_id: None,
+            creation: None,
+        }
+    }
+}
+
+/// Marmalade ledgers, the poly-fungible-v2 modules whose events are decoded
+pub const NFT_LEDGERS: &[&str] = &["marmalade.ledger", "marmalade-v2.ledger"];
+
+///
+/// Decode an event of one of the `NFT_LEDGERS` by its name and the shape of
+/// its parameters, the first one always being the token id. Events of other
+/// modules and fungible events, such as `TRANSFER` with 3 parameters, are
+/// not token events and return `None`.
+pub fn decode_event(event: &Event) -> Option<NftEvent> {
+    use NftEventKind::*;
+
+    if !NFT_LEDGERS.contains(&event.module.qualified_name().as_str()) {
+        return None;
+    }
+
+    let params = event.params.as_slice();
+    let string = |i: usize| params.get(i)?.as_str().map(String::from);
+    let decimal = |i: usize| params.get(i).and_then(parse_pact_decimal);
+
+    let (token_id, rest) = params.split_first()?;
+    let token_id = token_id.as_str()?;
+    let mut decoded = match (event.name.as_str(), rest.len()) {
+        // v1: (id precision supply policy)
+        // v2: (id precision policies uri creation-guard)
+        ("TOKEN", 3 | 4) => {
+            let (policies, uri) = match &params[2] {
+                Value::Array(_) => (params[2].clone(), string(3)),
+                _ => (Value::Array(params[3..].to_vec()), None),
+            };
+            let precision = parse_pact_decimal(&params[1]).and_then(|p| p.to_string().parse().ok());
+            NftEvent {
+                creation: Some(TokenCreation {
+                    precision,
+                    uri,
+                    policies,
+                }),
+                ..NftEvent::new(Token, token_id)
+            }
+        }
+        // (id account amount)
+        ("MINT", 2) => NftEvent {
+            receiver: Some(string(1)?),
+            amount: Some(decimal(2)?),
+            ..NftEvent::new(Mint, token_id)
+        },
+        ("BURN", 2) => NftEvent {
+            sender: Some(string(1)?),
+            amount: Some(decimal(2)?),
+            ..NftEvent::new(Burn, token_id)
+        },
+        // (id sender receiver amount)
+        ("TRANSFER", 3) => NftEvent {
+            sender: Some(string(1)?),
+            receiver: Some(string(2)?),
+            amount: Some(decimal(3)?),
+            ..NftEvent::new(Transfer, token_id)
+        },
+        // (id amount {account previous current} {account previous current})
+        ("RECONCILE", 3) => {
+            let change = |i: usize| {
+                let change = params[i].as_object()?;
+                Some((
+                    change.get("account")?.as_str()?.to_string(),
+                    change.get("current").and_then(parse_pact_decimal),
+                ))
+            };
+            let (sender, sender_balance) = change(2)?;
+            let (receiver, receiver_balance) = change(3)?;
+            NftEvent {
+                sender: Some(sender),
+                receiver: Some(receiver),
+                amount: Some(decimal(1)?),
+                sender_balance,
+                receiver_balance,
+                ..NftEvent::new(Reconcile, token_id)
+            }
+        }
+        // (id supply)
+        ("SUPPLY", 1) => NftEvent {
+            amount: Some(decimal(1)?),
+            ..NftEvent::new(Supply, token_id)
+        },
+        // (id seller amount timeout sale-id)
+        ("SALE", 4) => NftEvent {
+            sender: Some(string(1)?),
+            amount: Some(decimal(2)?),
+            ..NftEvent::new(Sale, token_id)
+        },
+        ("WITHDRAW", 4) => NftEvent {
+            sender: Some(string(1)?),
+            amount: Some(decimal(2)?),
+            ..NftEvent::new(Withdraw, token_id)
+        },
+        // v1: (id seller buyer amount timeout sale-id)
+        // v2: (id seller buyer amount sale-id)
+        ("BUY", 4 | 5) => NftEvent {
+            sender: Some(string(1)?),
+            receiver: Some(string(2)?),
+            amount: Some(decimal(3)?),
+            ..NftEvent::new(Buy, token_id)
+        },
+        _ => return None,
+    };
+    if matches!(decoded.kind, Sale | Withdraw | Buy) {
+        decoded.sale_id = Some(string(params.len() - 1)?);
+    }
+    Some(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn event(module: &str, name: &str, params: Value) -> Event {
+        let (namespace, module) = match module.split_once('.') {
+            Some((namespace, module)) => (Some(namespace), module),
+            None => (None, module),
+        };
+        serde_json::from_value(json!({
+            "module": { "name": module, "namespace": namespace },
+            "moduleHash": "hash",
+            "name": name,
+            "params": params
+        }))
+        .unwrap()
+    }
+
+    fn decimal(d: &str) -> Option<BigDecimal> {
+        Some(BigDecimal::from_str(d).unwrap())
+    }
+
+    #[test]
+    fn marmalade_v1_token() {
+        let policy =
+            json!({ "refName": { "name": "fixed-quote-policy", "namespace": "marmalade" } });
+        let decoded = decode_event(&event(
+            "marmalade.ledger",
+            "TOKEN",
+            json!(["t:1", { "int": 0 }, 1.0, policy]),
+        ))
+        .unwrap();
+        assert_eq!(decoded.kind, NftEventKind::Token);
+        assert_eq!(decoded.token_id, "t:1");
+        let creation = decoded.creation.unwrap();
+        assert_eq!(creation.precision, Some(0));
+        assert_eq!(creation.uri, None);
+        assert_eq!(creation.policies, json!([policy]));
+    }
+
+    #[test]
+    fn marmalade_v2_token() {
+        let policies = json!([
+            { "refName": { "name": "royalty-policy-v1", "namespace": "marmalade-v2" } },
+            { "refName": { "name": "guard-policy-v1", "namespace": "marmalade-v2" } }
+        ]);
+        let decoded = decode_event(&event(
+            "marmalade-v2.ledger",
+            "TOKEN",
+            json!([
+                "t:2",
+                { "int": 2 },
+                policies,
+                "ipfs://token",
+                { "keys": ["abc"], "pred": "keys-all" }
+            ]),
+        ))
+        .unwrap();
+        assert_eq!(decoded.kind, NftEventKind::Token);
+        let creation = decoded.creation.unwrap();
+        assert_eq!(creation.precision, Some(2));
+        assert_eq!(creation.uri.as_deref(), Some("ipfs://token"));
+        assert_eq!(creation.policies, policies);
+    }
+
+    #[test]
+    fn fungible_transfers_are_not_token_events() {
+        let transfer = event("coin", "TRANSFER", json!(["alice", "bob", 1.5]));
+        assert!(decode_event(&transfer).is_none());
+    }
+
+    #[test]
+    fn events_of_other_modules_are_ignored() {
+        let transfer = event(
+            "free.my-ledger",
+            "TRANSFER",
+            json!(["t:1", "alice", "bob", 1.0]),
+        );
+        assert!(decode_event(&transfer).is_none());
+    }
+
+    #[test]
+    fn token_transfer() {
+        let decoded = decode_event(&event(
+            "marmalade-v2.ledger",
+            "TRANSFER",
+            json!(["t:1", "alice", "bob", { "decimal": "0.5" }]),
+        ))
+        .unwrap();
+        assert_eq!(decoded.kind, NftEventKind::Transfer);
+        assert_eq!(decoded.sender.as_deref(), Some("alice"));
+        assert_eq!(decoded.receiver.as_deref(), Some("bob"));
+        assert_eq!(decoded.amount, decimal("0.5"));
+    }
+
+    #[test]
+    fn reconcile_balances() {
+        let decoded = decode_event(&event(
+            "marmalade-v2.ledger",
+            "RECONCILE",
+            json!([
+                "t:1",
+                1.0,
+                { "account": "alice", "previous": 1.0, "current": 0.0 },
+                { "account": "bob", "previous": 0.0, "current": 1.0 }
+            ]),
+        ))
+        .unwrap();
+        assert_eq!(decoded.sender.as_deref(), Some("alice"));
+        assert_eq!(decoded.sender_balance, decimal("0"));
+        assert_eq!(decoded.receiver.as_deref(), Some("bob"));
+        assert_eq!(decoded.receiver_balance, decimal("1"));
+    }
+
+    #[test]
+    fn sales_keep_their_id() {
+        let v1 = decode_event(&event(
+            "marmalade.ledger",
+            "BUY",
+            json!(["t:1", "alice", "bob", 1.0, { "int": 100 }, "sale-1"]),
+        ))
+        .unwrap();
+        let v2 = decode_event(&event(
+            "marmalade-v2.ledger",
+            "BUY",
+            json!(["t:1", "alice", "bob", 1.0, "sale-2"]),
+        ))
+        .unwrap();
+        assert_eq!(v1.sale_id.as_deref(), Some("sale-1"));
+        assert_eq!(v2.sale_id.as_deref(), Some("sale-2"));
+        assert_eq!(v2.receiver.as_deref(), Some("bob"));
+    }
+}
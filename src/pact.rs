@@ -0,0 +1,334 @@
+use std::ops::Range;
+
+use serde_json::Value;
+
+///
+/// Reader for the s-expressions of Pact code. It only recognizes the syntax,
+/// nothing is evaluated: lists, arrays, objects, strings, quoted symbols and
+/// any other atom (identifiers, numbers, booleans, `:` separators).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    /// `( ... )`
+    List(Vec<Expr>),
+    /// `[ ... ]`
+    Array(Vec<Expr>),
+    /// `{ ... }`, keys, `:` separators and values in source order
+    Object(Vec<Expr>),
+    /// `"..."` with escapes resolved
+    Str(String),
+    /// `'name`
+    Symbol(String),
+    Atom(String),
+}
+
+impl Expr {
+    /// Name and arguments of a function application such as
+    /// `(coin.transfer "alice" "bob" 1.0)`
+    pub fn as_call(&self) -> Option<(&str, &[Expr])> {
+        match self {
+            Expr::List(items) => match items.split_first() {
+                Some((Expr::Atom(name), args)) => Some((name.as_str(), args)),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// String literal or quoted symbol
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Expr::Str(s) | Expr::Symbol(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// String literal or quoted symbol, or a string of the payload data read
+    /// with `(read-msg "key")` or `(read-string "key")`
+    pub fn resolve_string(&self, data: &Value) -> Option<String> {
+        match self.as_call() {
+            Some(("read-msg" | "read-string", [key])) => {
+                Some(data[key.as_str()?].as_str()?.to_string())
+            }
+            _ => self.as_str().map(String::from),
+        }
+    }
+
+    /// Visit the expression and every nested expression, parents first
+    pub fn walk<'a>(&'a self, f: &mut impl FnMut(&'a Expr)) {
+        f(self);
+        if let Expr::List(items) | Expr::Array(items) | Expr::Object(items) = self {
+            for item in items {
+                item.walk(f);
+            }
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("Unexpected `{0}` at byte {1}")]
+    Unexpected(char, usize),
+    #[error("Unclosed `{0}` at byte {1}")]
+    Unclosed(char, usize),
+    #[error("Unterminated string at byte {0}")]
+    UnterminatedString(usize),
+    #[error("Nested deeper than {} at byte {0}", MAX_DEPTH)]
+    TooDeep(usize),
+}
+
+/// Deepest nesting of lists, arrays and objects read, so that hostile code
+/// cannot overflow the stack of the parser
+const MAX_DEPTH: usize = 128;
+
+/// Read every top-level expression of Pact code
+pub fn parse(code: &str) -> Result<Vec<Expr>, ParseError> {
+    Ok(parse_spanned(code)?.into_iter().map(|(e, _)| e).collect())
+}
+
+/// Top-level expressions with the byte range of their source in `code`
+pub fn parse_spanned(code: &str) -> Result<Vec<(Expr, Range<usize>)>, ParseError> {
+    let mut reader = Reader {
+        chars: code.char_indices().peekable(),
+        depth: 0,
+    };
+    let mut exprs = vec![];
+    loop {
+        reader.skip_blank();
+        let Some(&(start, _)) = reader.chars.peek() else {
+            break;
+        };
+        let Some(expr) = reader.next_expr(None)? else {
+            break;
+        };
+        let end = reader
+            .chars
+            .peek()
+            .map(|&(end, _)| end)
+            .unwrap_or(code.len());
+        exprs.push((expr, start..end));
+    }
+    Ok(exprs)
+}
+
+struct Reader<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    /// Collections opened and not closed yet
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    /// Next expression, `None` at the end of the input or, inside a
+    /// collection, at its `closing` delimiter
+    fn next_expr(&mut self, closing: Option<char>) -> Result<Option<Expr>, ParseError> {
+        self.skip_blank();
+        let Some(&(pos, c)) = self.chars.peek() else {
+            return Ok(None);
+        };
+        if Some(c) == closing {
+            self.chars.next();
+            return Ok(None);
+        }
+        match c {
+            '(' | '[' | '{' => {
+                if self.depth == MAX_DEPTH {
+                    return Err(ParseError::TooDeep(pos));
+                }
+                self.depth += 1;
+                self.chars.next();
+                let close = match c {
+                    '(' => ')',
+                    '[' => ']',
+                    _ => '}',
+                };
+                let mut items = vec![];
+                loop {
+                    self.skip_blank();
+                    if self.chars.peek().is_none() {
+                        return Err(ParseError::Unclosed(c, pos));
+                    }
+                    match self.next_expr(Some(close))? {
+                        Some(item) => items.push(item),
+                        None => break,
+                    }
+                }
+                self.depth -= 1;
+                Ok(Some(match c {
+                    '(' => Expr::List(items),
+                    '[' => Expr::Array(items),
+                    _ => Expr::Object(items),
+                }))
+            }
+            ')' | ']' | '}' => Err(ParseError::Unexpected(c, pos)),
+            '"' => {
+                self.chars.next();
+                self.string(pos).map(|s| Some(Expr::Str(s)))
+            }
+            '\'' => {
+                self.chars.next();
+                Ok(Some(Expr::Symbol(self.atom())))
+            }
+            ':' => {
+                self.chars.next();
+                Ok(Some(Expr::Atom(":".to_string())))
+            }
+            _ => Ok(Some(Expr::Atom(self.atom()))),
+        }
+    }
+
+    /// Whitespace, `,` separators and `;` comments
+    fn skip_blank(&mut self) {
+        while let Some(&(_, c)) = self.chars.peek() {
+            if c == ';' {
+                for (_, c) in self.chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            } else if c.is_whitespace() || c == ',' {
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn string(&mut self, start: usize) -> Result<String, ParseError> {
+        let mut s = String::new();
+        while let Some((_, c)) = self.chars.next() {
+            match c {
+                '"' => return Ok(s),
+                '\\' => match self.chars.next() {
+                    Some((_, 'n')) => s.push('\n'),
+                    Some((_, 't')) => s.push('\t'),
+                    Some((_, c)) => s.push(c),
+                    None => break,
+                },
+                c => s.push(c),
+            }
+        }
+        Err(ParseError::UnterminatedString(start))
+    }
+
+    fn atom(&mut self) -> String {
+        let mut atom = String::new();
+        while let Some(&(_, c)) = self.chars.peek() {
+            if c.is_whitespace() || "()[]{}\",;".contains(c) {
+                break;
+            }
+            atom.push(c);
+            self.chars.next();
+        }
+        atom
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn atom(a: &str) -> Expr {
+        Expr::Atom(a.to_string())
+    }
+
+    fn string(s: &str) -> Expr {
+        Expr::Str(s.to_string())
+    }
+
+    #[test]
+    fn strings_resolve_escapes() {
+        assert_eq!(
+            parse(r#""a \"quoted\" \\ word\n\tend""#),
+            Ok(vec![string("a \"quoted\" \\ word\n\tend")])
+        );
+    }
+
+    #[test]
+    fn delimiters_inside_strings_are_text() {
+        assert_eq!(
+            parse(r#"(f "(not [a] {list}; nor a comment")"#),
+            Ok(vec![Expr::List(vec![
+                atom("f"),
+                string("(not [a] {list}; nor a comment")
+            ])])
+        );
+    }
+
+    #[test]
+    fn comments_and_commas_are_skipped() {
+        let code = "; leading comment\n(f 1, 2) ; trailing comment\n[a,b]";
+        assert_eq!(
+            parse(code),
+            Ok(vec![
+                Expr::List(vec![atom("f"), atom("1"), atom("2")]),
+                Expr::Array(vec![atom("a"), atom("b")]),
+            ])
+        );
+    }
+
+    #[test]
+    fn nested_objects_keep_keys_and_separators() {
+        assert_eq!(
+            parse(r#"{"a": {"b": [1 'sym]}}"#),
+            Ok(vec![Expr::Object(vec![
+                string("a"),
+                atom(":"),
+                Expr::Object(vec![
+                    string("b"),
+                    atom(":"),
+                    Expr::Array(vec![atom("1"), Expr::Symbol("sym".to_string())]),
+                ]),
+            ])])
+        );
+    }
+
+    #[test]
+    fn spans_cover_the_source_of_each_expression() {
+        let code = "(namespace 'free)\n(module m g (defun f () 1))";
+        let spans = parse_spanned(code)
+            .unwrap()
+            .into_iter()
+            .map(|(_, span)| &code[span])
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            vec!["(namespace 'free)", "(module m g (defun f () 1))"]
+        );
+    }
+
+    #[test]
+    fn malformed_code_is_an_error() {
+        assert_eq!(parse("(f [1 2)"), Err(ParseError::Unexpected(')', 7)));
+        assert_eq!(parse("(f (g)"), Err(ParseError::Unclosed('(', 0)));
+        assert_eq!(parse(r#"(f "abc)"#), Err(ParseError::UnterminatedString(3)));
+        assert_eq!(parse("f)"), Err(ParseError::Unexpected(')', 1)));
+    }
+
+    #[test]
+    fn nesting_is_limited() {
+        let nested = |depth: usize| format!("{}{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(parse(&nested(MAX_DEPTH)).is_ok());
+        assert_eq!(
+            parse(&nested(MAX_DEPTH + 1)),
+            Err(ParseError::TooDeep(MAX_DEPTH))
+        );
+        // Deep enough to overflow the stack without the limit
+        let hostile = "[".repeat(1_000_000);
+        assert_eq!(parse(&hostile), Err(ParseError::TooDeep(MAX_DEPTH)));
+    }
+
+    #[test]
+    fn strings_are_read_from_the_payload_data() {
+        let data = json!({ "ns": "free", "count": 1 });
+        let [ns, count, missing, literal] =
+            parse(r#"(read-msg "ns") (read-string "count") (read-msg "missing") 'user"#)
+                .unwrap()
+                .try_into()
+                .unwrap();
+        assert_eq!(ns.resolve_string(&data), Some("free".to_string()));
+        assert_eq!(count.resolve_string(&data), None);
+        assert_eq!(missing.resolve_string(&data), None);
+        assert_eq!(literal.resolve_string(&data), Some("user".to_string()));
+    }
+}
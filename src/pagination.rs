@@ -0,0 +1,146 @@
+use serde::Serialize;
+use uuid::Uuid;
+
+pub const DEFAULT_PAGE_SIZE: i64 = 50;
+pub const MAX_PAGE_SIZE: i64 = 500;
+
+#[derive(thiserror::Error, Debug)]
+pub enum PaginationError {
+    #[error("Invalid cursor `{0}`")]
+    InvalidCursor(String),
+    #[error("`limit` must be between 1 and {}", MAX_PAGE_SIZE)]
+    InvalidLimit,
+}
+
+///
+/// Position after the last item of a page. Rows are ordered by descending
+/// `(height, id)` so the cursor is opaque to clients but stable across inserts.
+#[derive(Debug, Clone, Copy)]
+pub struct Cursor {
+    pub height: i64,
+    pub id: Uuid,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        base64_url::encode(&format!("{}:{}", self.height, self.id))
+    }
+
+    pub fn decode(input: &str) -> Result<Self, PaginationError> {
+        let invalid = || PaginationError::InvalidCursor(input.to_string());
+        let bytes = base64_url::decode(input).map_err(|_| invalid())?;
+        let decoded = String::from_utf8(bytes).map_err(|_| invalid())?;
+        let (height, id) = decoded.split_once(':').ok_or_else(invalid)?;
+        Ok(Self {
+            height: height.parse().map_err(|_| invalid())?,
+            id: id.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Validate the pagination parameters of a request
+pub fn page_bounds(
+    limit: Option<i64>,
+    cursor: Option<&str>,
+) -> Result<(i64, Option<Cursor>), PaginationError> {
+    let limit = limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(PaginationError::InvalidLimit);
+    }
+    let cursor = cursor.map(Cursor::decode).transpose()?;
+    Ok((limit, cursor))
+}
+
+#[derive(Serialize, Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Cursor of the next page, `None` on the last page
+    pub next: Option<String>,
+}
+
+impl<T> Page<T> {
+    /// Build a page out of at most `limit + 1` rows, the extra row only
+    /// tells whether there is a next page
+    pub fn new(mut items: Vec<T>, limit: i64, cursor: impl Fn(&T) -> Cursor) -> Self {
+        let next = if items.len() as i64 > limit {
+            items.truncate(limit as usize);
+            items.last().map(|item| cursor(item).encode())
+        } else {
+            None
+        };
+        Self { items, next }
+    }
+
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            next: self.next,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cursor_round_trips() {
+        let cursor = Cursor {
+            height: 4_200_123,
+            id: Uuid::new_v4(),
+        };
+        let decoded = Cursor::decode(&cursor.encode()).unwrap();
+        assert_eq!(decoded.height, cursor.height);
+        assert_eq!(decoded.id, cursor.id);
+    }
+
+    #[test]
+    fn invalid_cursors_are_rejected() {
+        let id = Uuid::new_v4();
+        for input in [
+            "not base64!".to_string(),
+            base64_url::encode(&[0xff, 0xfe]),
+            base64_url::encode("12"),
+            base64_url::encode(&format!("twelve:{}", id)),
+            base64_url::encode("12:not-a-uuid"),
+        ] {
+            assert!(
+                matches!(Cursor::decode(&input), Err(PaginationError::InvalidCursor(i)) if i == input),
+                "{} should be rejected",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn page_bounds_check_the_limit() {
+        assert_eq!(page_bounds(None, None).unwrap().0, DEFAULT_PAGE_SIZE);
+        assert_eq!(
+            page_bounds(Some(MAX_PAGE_SIZE), None).unwrap().0,
+            MAX_PAGE_SIZE
+        );
+        assert!(matches!(
+            page_bounds(Some(0), None),
+            Err(PaginationError::InvalidLimit)
+        ));
+        assert!(matches!(
+            page_bounds(Some(MAX_PAGE_SIZE + 1), None),
+            Err(PaginationError::InvalidLimit)
+        ));
+    }
+
+    #[test]
+    fn page_has_a_next_cursor_only_with_an_extra_row() {
+        let cursor = |height: &i64| Cursor {
+            height: *height,
+            id: Uuid::nil(),
+        };
+        let page = Page::new(vec![3, 2, 1], 2, cursor);
+        assert_eq!(page.items, vec![3, 2]);
+        let next = Cursor::decode(page.next.as_deref().unwrap()).unwrap();
+        assert_eq!(next.height, 2);
+
+        let page = Page::new(vec![3, 2], 2, cursor);
+        assert_eq!(page.next, None);
+    }
+}
@@ -0,0 +1,351 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use sqlx::{Postgres, Transaction};
+
+use crate::accounts::guard_changes;
+use crate::calls::function_calls;
+use crate::decode::{DecodedBlock, DecodedTransaction};
+use crate::deployments::deployments;
+use crate::entities::{
+    Account, AccountGuard, Balance, BlockGas, HourlyGas, ModuleDeployment, NftEventRecord,
+    NftOwnership, NftToken, TransactionCall, Transfer,
+};
+use crate::nfts::decode_event;
+use crate::types::{BlockHeader, Event, Output};
+
+///
+/// Derives tables from the decoded blocks of a chain. `Ingest` calls every
+/// processor within the database transaction that stores the blocks,
+/// transactions and events of a batch, so the rows of a processor are
+/// written atomically with the core data.
+///
+/// A batch may be stored again after a reorg or a reindex: `delete` must
+/// remove what the processor wrote for the batch height range before the
+/// blocks are processed again.
+#[async_trait]
+pub trait BlockProcessor: Debug + Send + Sync {
+    /// Remove the rows of a chain and an inclusive height range
+    async fn delete(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+        min_height: i64,
+        max_height: i64,
+    ) -> Result<(), sqlx::Error>;
+
+    ///
+    /// Called for every block of a batch, lowest height first. By default
+    /// calls `process_transaction` for every transaction, then
+    /// `process_event` for every event of the coinbase and the transactions.
+    async fn process_block(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        block: &DecodedBlock<'_>,
+    ) -> Result<(), sqlx::Error> {
+        for transaction in &block.transactions {
+            self.process_transaction(tx, block.header, transaction)
+                .await?;
+        }
+        for output in block.outputs() {
+            for (idx, event) in output.events.iter().enumerate() {
+                self.process_event(tx, block.header, output, idx, event)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn process_transaction(
+        &self,
+        _tx: &mut Transaction<'_, Postgres>,
+        _header: &BlockHeader,
+        _transaction: &DecodedTransaction,
+    ) -> Result<(), sqlx::Error> {
+        Ok(())
+    }
+
+    /// `idx` is the position of the event in the output
+    async fn process_event(
+        &self,
+        _tx: &mut Transaction<'_, Postgres>,
+        _header: &BlockHeader,
+        _output: &Output,
+        _idx: usize,
+        _event: &Event,
+    ) -> Result<(), sqlx::Error> {
+        Ok(())
+    }
+
+    ///
+    /// Called once all the blocks of a batch or of a reindexed range are
+    /// processed, with the lowest height written, to refresh aggregates
+    /// that depend on earlier rows.
+    async fn finish(
+        &self,
+        _tx: &mut Transaction<'_, Postgres>,
+        _chain_id: i16,
+        _min_height: i64,
+    ) -> Result<(), sqlx::Error> {
+        Ok(())
+    }
+}
+
+/// Processors of the tables of the crate, in the order they must run
+pub fn builtin_processors(block_gas_limit: u64) -> Vec<Arc<dyn BlockProcessor>> {
+    vec![
+        Arc::new(GasProcessor { block_gas_limit }),
+        Arc::new(CallProcessor),
+        Arc::new(TransferProcessor),
+        // Accounts are first seen in transfers too
+        Arc::new(AccountProcessor),
+        Arc::new(DeploymentProcessor),
+        Arc::new(NftProcessor),
+    ]
+}
+
+/// Gas of every block and its hourly aggregates
+#[derive(Debug)]
+pub struct GasProcessor {
+    pub block_gas_limit: u64,
+}
+
+#[async_trait]
+impl BlockProcessor for GasProcessor {
+    async fn delete(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+        min_height: i64,
+        max_height: i64,
+    ) -> Result<(), sqlx::Error> {
+        BlockGas::delete(tx, chain_id, min_height, max_height).await
+    }
+
+    async fn process_block(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        block: &DecodedBlock<'_>,
+    ) -> Result<(), sqlx::Error> {
+        BlockGas::new(block.header, &block.transactions, self.block_gas_limit)
+            .insert(tx)
+            .await
+    }
+
+    async fn finish(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+        min_height: i64,
+    ) -> Result<(), sqlx::Error> {
+        HourlyGas::rebuild(tx, chain_id, min_height).await
+    }
+}
+
+/// Module functions called by the code of transactions
+#[derive(Debug)]
+pub struct CallProcessor;
+
+#[async_trait]
+impl BlockProcessor for CallProcessor {
+    async fn delete(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+        min_height: i64,
+        max_height: i64,
+    ) -> Result<(), sqlx::Error> {
+        TransactionCall::delete(tx, chain_id, min_height, max_height).await
+    }
+
+    async fn process_transaction(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        header: &BlockHeader,
+        transaction: &DecodedTransaction,
+    ) -> Result<(), sqlx::Error> {
+        for (idx, call) in function_calls(transaction).into_iter().enumerate() {
+            TransactionCall::new(header, transaction, idx, call)
+                .insert(tx)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Fungible transfers and the balances derived from them
+#[derive(Debug)]
+pub struct TransferProcessor;
+
+#[async_trait]
+impl BlockProcessor for TransferProcessor {
+    async fn delete(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+        min_height: i64,
+        max_height: i64,
+    ) -> Result<(), sqlx::Error> {
+        Transfer::delete(tx, chain_id, min_height, max_height).await
+    }
+
+    async fn process_event(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        header: &BlockHeader,
+        output: &Output,
+        idx: usize,
+        event: &Event,
+    ) -> Result<(), sqlx::Error> {
+        match Transfer::from_event(header, output, idx, event) {
+            Some(transfer) => transfer.insert(tx).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn finish(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+        min_height: i64,
+    ) -> Result<(), sqlx::Error> {
+        Balance::rebuild(tx, chain_id, min_height).await
+    }
+}
+
+/// Account guards and the accounts registry
+#[derive(Debug)]
+pub struct AccountProcessor;
+
+#[async_trait]
+impl BlockProcessor for AccountProcessor {
+    async fn delete(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+        min_height: i64,
+        max_height: i64,
+    ) -> Result<(), sqlx::Error> {
+        AccountGuard::delete(tx, chain_id, min_height, max_height).await
+    }
+
+    async fn process_block(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        block: &DecodedBlock<'_>,
+    ) -> Result<(), sqlx::Error> {
+        let changes = block
+            .transactions
+            .iter()
+            .flat_map(|t| guard_changes(t).into_iter().map(move |c| (t, c)));
+        for (idx, (transaction, change)) in changes.enumerate() {
+            AccountGuard::new(block.header, transaction, idx, change)
+                .insert(tx)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn finish(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+        min_height: i64,
+    ) -> Result<(), sqlx::Error> {
+        Account::rebuild(tx, chain_id, min_height).await
+    }
+}
+
+/// Modules, interfaces and namespaces defined by transactions
+#[derive(Debug)]
+pub struct DeploymentProcessor;
+
+#[async_trait]
+impl BlockProcessor for DeploymentProcessor {
+    async fn delete(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+        min_height: i64,
+        max_height: i64,
+    ) -> Result<(), sqlx::Error> {
+        ModuleDeployment::delete(tx, chain_id, min_height, max_height).await
+    }
+
+    async fn process_block(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        block: &DecodedBlock<'_>,
+    ) -> Result<(), sqlx::Error> {
+        let found = block
+            .transactions
+            .iter()
+            .flat_map(|t| deployments(t).into_iter().map(move |d| (t, d)));
+        for (idx, (transaction, deployment)) in found.enumerate() {
+            ModuleDeployment::new(block.header, transaction, idx, deployment)
+                .insert(tx)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn finish(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+        _min_height: i64,
+    ) -> Result<(), sqlx::Error> {
+        ModuleDeployment::fill_hashes(tx, chain_id).await
+    }
+}
+
+/// Marmalade and poly-fungible-v2 events, tokens and holders
+#[derive(Debug)]
+pub struct NftProcessor;
+
+#[async_trait]
+impl BlockProcessor for NftProcessor {
+    async fn delete(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+        min_height: i64,
+        max_height: i64,
+    ) -> Result<(), sqlx::Error> {
+        NftEventRecord::delete(tx, chain_id, min_height, max_height).await?;
+        NftToken::delete(tx, chain_id, min_height, max_height).await
+    }
+
+    async fn process_block(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        block: &DecodedBlock<'_>,
+    ) -> Result<(), sqlx::Error> {
+        let mut events = vec![];
+        for output in block.outputs() {
+            for event in &output.events {
+                if let Some(decoded) = decode_event(event) {
+                    events.push((output, event, decoded));
+                }
+            }
+        }
+        for (idx, (output, event, decoded)) in events.into_iter().enumerate() {
+            let record = NftEventRecord::new(block.header, output, idx, event, decoded.clone());
+            if let Some(token) = NftToken::from_event(&record, &decoded) {
+                token.insert(tx).await?;
+            }
+            record.insert(tx).await?;
+        }
+        Ok(())
+    }
+
+    async fn finish(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        chain_id: i16,
+        min_height: i64,
+    ) -> Result<(), sqlx::Error> {
+        NftOwnership::rebuild(tx, chain_id, min_height).await
+    }
+}
@@ -0,0 +1,50 @@
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::entities::{Account, AccountGuard};
+use crate::routes::ApiError;
+
+#[derive(Deserialize)]
+pub struct AccountQuery {
+    pub chain_id: Option<i16>,
+    /// Qualified token module name, e.g. `coin`
+    pub module: Option<String>,
+}
+
+/// An account on every chain and token it was seen on
+pub async fn account(
+    account: web::Path<String>,
+    query: web::Query<AccountQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let accounts = Account::list(
+        pool.get_ref(),
+        &account,
+        query.chain_id,
+        query.module.as_deref(),
+    )
+    .await?;
+    if accounts.is_empty() {
+        return Err(ApiError::NotFound(format!("Account {} not found", account)));
+    }
+
+    Ok(HttpResponse::Ok().json(accounts))
+}
+
+/// Guards set on an account, latest first
+pub async fn account_guards(
+    account: web::Path<String>,
+    query: web::Query<AccountQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let guards = AccountGuard::list(
+        pool.get_ref(),
+        &account,
+        query.chain_id,
+        query.module.as_deref(),
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(guards))
+}
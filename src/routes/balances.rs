@@ -0,0 +1,33 @@
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::entities::Balance;
+use crate::routes::ApiError;
+
+#[derive(Deserialize)]
+pub struct BalancesQuery {
+    pub chain_id: Option<i16>,
+    /// Qualified token module name, e.g. `coin`
+    pub module: Option<String>,
+    /// Balances as of this block height instead of the latest ones
+    pub height: Option<i64>,
+}
+
+/// Balances of an account per chain and token
+pub async fn account_balances(
+    account: web::Path<String>,
+    query: web::Query<BalancesQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let balances = Balance::list(
+        pool.get_ref(),
+        &account,
+        query.chain_id,
+        query.module.as_deref(),
+        query.height,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(balances))
+}
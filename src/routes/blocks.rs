@@ -0,0 +1,54 @@
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::entities::BlockRecord;
+use crate::pagination::page_bounds;
+use crate::routes::ApiError;
+
+#[derive(Deserialize)]
+pub struct BlocksQuery {
+    pub chain_id: Option<i16>,
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+}
+
+/// Latest blocks first, optionally of a single chain
+pub async fn list_blocks(
+    query: web::Query<BlocksQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let (limit, cursor) = page_bounds(query.limit, query.cursor.as_deref())?;
+    let blocks = BlockRecord::list(pool.get_ref(), query.chain_id, limit, cursor).await?;
+
+    Ok(HttpResponse::Ok().json(blocks))
+}
+
+pub async fn block_by_hash(
+    hash: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let block = BlockRecord::find_by_hash(pool.get_ref(), &hash)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Block {} not found", hash)))?;
+
+    Ok(HttpResponse::Ok().json(block))
+}
+
+/// Block of a chain at a height, the canonical one when a fork left several
+pub async fn block_by_height(
+    path: web::Path<(i16, i64)>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let (chain_id, height) = path.into_inner();
+    let block = BlockRecord::find_by_height(pool.get_ref(), chain_id, height)
+        .await?
+        .ok_or_else(|| {
+            ApiError::NotFound(format!(
+                "Block at height {} of chain {} not found",
+                height, chain_id
+            ))
+        })?;
+
+    Ok(HttpResponse::Ok().json(block))
+}
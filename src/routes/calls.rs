@@ -0,0 +1,36 @@
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::entities::TransactionCall;
+use crate::pagination::page_bounds;
+use crate::routes::ApiError;
+
+#[derive(Deserialize)]
+pub struct CallsQuery {
+    /// Qualified module name, e.g. `free.my-dex`
+    pub module: Option<String>,
+    pub function: Option<String>,
+    pub chain_id: Option<i16>,
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+}
+
+/// Module functions called by transactions, latest first
+pub async fn list_calls(
+    query: web::Query<CallsQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let (limit, cursor) = page_bounds(query.limit, query.cursor.as_deref())?;
+    let calls = TransactionCall::list(
+        pool.get_ref(),
+        query.module.as_deref(),
+        query.function.as_deref(),
+        query.chain_id,
+        limit,
+        cursor,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(calls))
+}
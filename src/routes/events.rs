@@ -0,0 +1,36 @@
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::entities::EventRecord;
+use crate::pagination::page_bounds;
+use crate::routes::ApiError;
+
+#[derive(Deserialize)]
+pub struct EventsQuery {
+    /// Qualified module name, e.g. `coin` or `free.my-module`
+    pub module: Option<String>,
+    pub name: Option<String>,
+    pub chain_id: Option<i16>,
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+}
+
+/// Latest events first, optionally filtered by module, name and chain
+pub async fn list_events(
+    query: web::Query<EventsQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let (limit, cursor) = page_bounds(query.limit, query.cursor.as_deref())?;
+    let events = EventRecord::list(
+        pool.get_ref(),
+        query.module.as_deref(),
+        query.name.as_deref(),
+        query.chain_id,
+        limit,
+        cursor,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(events))
+}
@@ -0,0 +1,66 @@
+use actix_web::{web, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::entities::{BlockGas, ChainGas, HourlyGas};
+use crate::pagination::page_bounds;
+use crate::routes::ApiError;
+
+#[derive(Deserialize)]
+pub struct BlockGasQuery {
+    pub chain_id: Option<i16>,
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+}
+
+/// Gas of the latest blocks first, optionally of a single chain
+pub async fn list_block_gas(
+    query: web::Query<BlockGasQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let (limit, cursor) = page_bounds(query.limit, query.cursor.as_deref())?;
+    let blocks = BlockGas::list(pool.get_ref(), query.chain_id, limit, cursor).await?;
+
+    Ok(HttpResponse::Ok().json(blocks))
+}
+
+#[derive(Deserialize)]
+pub struct HourlyGasQuery {
+    pub chain_id: Option<i16>,
+    /// RFC 3339 start of the range, inclusive
+    pub from: Option<DateTime<Utc>>,
+    /// RFC 3339 end of the range, exclusive
+    pub to: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+}
+
+/// Gas per chain and hour, latest hours first
+pub async fn list_hourly_gas(
+    query: web::Query<HourlyGasQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let (limit, _) = page_bounds(query.limit, None)?;
+    let hours =
+        HourlyGas::list(pool.get_ref(), query.chain_id, query.from, query.to, limit).await?;
+
+    Ok(HttpResponse::Ok().json(hours))
+}
+
+#[derive(Deserialize)]
+pub struct ChainGasQuery {
+    /// RFC 3339 start of the range, inclusive
+    pub from: Option<DateTime<Utc>>,
+    /// RFC 3339 end of the range, exclusive
+    pub to: Option<DateTime<Utc>>,
+}
+
+/// Gas per chain over a time range, all indexed hours by default
+pub async fn list_chain_gas(
+    query: web::Query<ChainGasQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let chains = ChainGas::list(pool.get_ref(), query.from, query.to).await?;
+
+    Ok(HttpResponse::Ok().json(chains))
+}
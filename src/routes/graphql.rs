@@ -0,0 +1,18 @@
+use actix_web::{web, HttpResponse};
+use async_graphql::http::GraphiQLSource;
+
+use crate::graphql::IndexerSchema;
+
+pub async fn graphql(
+    schema: web::Data<IndexerSchema>,
+    request: web::Json<async_graphql::Request>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(schema.execute(request.into_inner()).await)
+}
+
+/// In-browser IDE to explore the schema
+pub async fn graphiql() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(GraphiQLSource::build().endpoint("/graphql").finish())
+}
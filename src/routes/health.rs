@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use serde::Serialize;
+use serde_json::json;
+use sqlx::PgPool;
+
+use crate::client::NodeClient;
+use crate::startup::{chain_progress, get_processed_blocks_logs, ChainStatus};
+use crate::types::CurrentCut;
+
+/// Node cut compared with the indexed heights by `/ready`
+#[derive(Clone)]
+pub struct ReadinessCheck {
+    pub cut_url: String,
+    /// End height of every indexed chain, the other chains of the cut are
+    /// not checked
+    pub chains: HashMap<i16, Option<u64>>,
+    /// Blocks a chain may lag behind the cut and still be ready
+    pub max_lag: u64,
+    /// Client shared with the indexers, so the cut requests count against
+    /// the same limits
+    pub client: NodeClient,
+    /// Bounds the wait for a request slot as well as the request
+    pub timeout: Duration,
+}
+
+impl ReadinessCheck {
+    async fn current_cut(&self) -> Result<CurrentCut, anyhow::Error> {
+        let request = self.client.send(self.client.get(&self.cut_url));
+        let cut = tokio::time::timeout(self.timeout, request)
+            .await
+            .context("Timed out waiting for the node")??;
+        Ok(cut.json()?)
+    }
+}
+
+#[derive(Serialize)]
+struct ChainReadiness {
+    #[serde(flatten)]
+    progress: ChainStatus,
+    ready: bool,
+}
+
+/// The process is up and the database answers
+pub async fn health(pool: web::Data<PgPool>) -> HttpResponse {
+    match sqlx::query("SELECT 1").execute(pool.get_ref()).await {
+        Ok(_) => HttpResponse::Ok().json(json!({ "status": "ok", "database": "ok" })),
+        Err(e) => HttpResponse::ServiceUnavailable()
+            .json(json!({ "status": "unavailable", "database": e.to_string() })),
+    }
+}
+
+/// Every indexed chain of the node cut is within `max_lag` blocks
+pub async fn ready(pool: web::Data<PgPool>, check: web::Data<ReadinessCheck>) -> HttpResponse {
+    let unavailable = |e: anyhow::Error| {
+        HttpResponse::ServiceUnavailable().json(json!({
+            "ready": false,
+            "error": format!("{:#}", e),
+        }))
+    };
+    let processed = match get_processed_blocks_logs(pool.get_ref()).await {
+        Ok(processed) => processed,
+        Err(e) => return unavailable(anyhow::Error::new(e).context("Failed to query database")),
+    };
+    let cut = match check.current_cut().await {
+        Ok(cut) => cut,
+        Err(e) => return unavailable(e.context("Failed to fetch cut from chainweb node")),
+    };
+
+    let chains = chain_progress(&processed, &cut, &check.chains)
+        .into_iter()
+        .map(|progress| ChainReadiness {
+            ready: progress.lag <= check.max_lag,
+            progress,
+        })
+        .collect::<Vec<_>>();
+
+    let ready = chains.iter().all(|c| c.ready);
+    let body = json!({ "ready": ready, "max_lag": check.max_lag, "chains": chains });
+    if ready {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::ServiceUnavailable().json(body)
+    }
+}
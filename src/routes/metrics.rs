@@ -0,0 +1,10 @@
+use actix_web::HttpResponse;
+
+use crate::metrics::metrics;
+
+/// Indexer metrics in the Prometheus text format
+pub async fn prometheus_metrics() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics().encode())
+}
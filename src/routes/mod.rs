@@ -0,0 +1,65 @@
+mod accounts;
+mod balances;
+mod blocks;
+mod calls;
+mod events;
+mod gas;
+mod graphql;
+mod health;
+mod metrics;
+mod modules;
+mod nfts;
+mod subscribe;
+mod transactions;
+mod transfers;
+
+pub use accounts::*;
+pub use balances::*;
+pub use blocks::*;
+pub use calls::*;
+pub use events::*;
+pub use gas::*;
+pub use graphql::*;
+pub use health::*;
+pub use metrics::*;
+pub use modules::*;
+pub use nfts::*;
+pub use subscribe::*;
+pub use transactions::*;
+pub use transfers::*;
+
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+
+use crate::pagination::PaginationError;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ApiError {
+    #[error("{0}")]
+    NotFound(String),
+    #[error(transparent)]
+    BadRequest(#[from] PaginationError),
+    #[error(transparent)]
+    Unexpected(#[from] sqlx::Error),
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let message = match self {
+            ApiError::Unexpected(e) => {
+                tracing::error!(error = %e, "Failed to query database");
+                "Internal server error".to_string()
+            }
+            e => e.to_string(),
+        };
+        HttpResponse::build(self.status_code()).json(serde_json::json!({ "error": message }))
+    }
+}
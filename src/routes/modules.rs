@@ -0,0 +1,59 @@
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::entities::ModuleDeployment;
+use crate::pagination::page_bounds;
+use crate::routes::ApiError;
+
+#[derive(Deserialize)]
+pub struct ModulesQuery {
+    pub chain_id: Option<i16>,
+    pub namespace: Option<String>,
+    /// `module`, `interface` or `namespace`
+    pub kind: Option<String>,
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+}
+
+/// Latest version of every deployed module, most recently deployed first
+pub async fn list_modules(
+    query: web::Query<ModulesQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let (limit, cursor) = page_bounds(query.limit, query.cursor.as_deref())?;
+    let modules = ModuleDeployment::list(
+        pool.get_ref(),
+        query.chain_id,
+        query.namespace.as_deref(),
+        query.kind.as_deref(),
+        limit,
+        cursor,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(modules))
+}
+
+#[derive(Deserialize)]
+pub struct ModuleHistoryQuery {
+    pub chain_id: Option<i16>,
+}
+
+/// Every version of a module by qualified name, e.g. `free.my-module`
+pub async fn module_history(
+    qualified_name: web::Path<String>,
+    query: web::Query<ModuleHistoryQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let versions =
+        ModuleDeployment::history(pool.get_ref(), &qualified_name, query.chain_id).await?;
+    if versions.is_empty() {
+        return Err(ApiError::NotFound(format!(
+            "Module {} not found",
+            qualified_name
+        )));
+    }
+
+    Ok(HttpResponse::Ok().json(versions))
+}
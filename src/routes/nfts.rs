@@ -0,0 +1,126 @@
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::entities::{NftEventRecord, NftOwnership, NftToken};
+use crate::pagination::page_bounds;
+use crate::routes::ApiError;
+
+#[derive(Deserialize)]
+pub struct NftsQuery {
+    pub chain_id: Option<i16>,
+    /// Qualified ledger module name, e.g. `marmalade-v2.ledger`
+    pub module: Option<String>,
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+}
+
+/// Tokens, latest created first
+pub async fn list_nfts(
+    query: web::Query<NftsQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let (limit, cursor) = page_bounds(query.limit, query.cursor.as_deref())?;
+    let tokens = NftToken::list(
+        pool.get_ref(),
+        query.chain_id,
+        query.module.as_deref(),
+        limit,
+        cursor,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+#[derive(Deserialize)]
+pub struct NftQuery {
+    pub chain_id: Option<i16>,
+    pub module: Option<String>,
+}
+
+/// A token on every chain and ledger it was created on
+pub async fn nft(
+    token_id: web::Path<String>,
+    query: web::Query<NftQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let tokens = NftToken::find(
+        pool.get_ref(),
+        &token_id,
+        query.chain_id,
+        query.module.as_deref(),
+    )
+    .await?;
+    if tokens.is_empty() {
+        return Err(ApiError::NotFound(format!("Token {} not found", token_id)));
+    }
+
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+/// Current holders of a token
+pub async fn nft_owners(
+    token_id: web::Path<String>,
+    query: web::Query<NftQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let owners = NftOwnership::list_by_token(
+        pool.get_ref(),
+        &token_id,
+        query.chain_id,
+        query.module.as_deref(),
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(owners))
+}
+
+#[derive(Deserialize)]
+pub struct NftEventsQuery {
+    pub chain_id: Option<i16>,
+    pub module: Option<String>,
+    /// `token`, `mint`, `burn`, `transfer`, `reconcile`, `supply`, `sale`,
+    /// `buy` or `withdraw`
+    pub kind: Option<String>,
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+}
+
+/// Events of a token, latest first
+pub async fn nft_events(
+    token_id: web::Path<String>,
+    query: web::Query<NftEventsQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let (limit, cursor) = page_bounds(query.limit, query.cursor.as_deref())?;
+    let events = NftEventRecord::list(
+        pool.get_ref(),
+        &token_id,
+        query.chain_id,
+        query.module.as_deref(),
+        query.kind.as_deref(),
+        limit,
+        cursor,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(events))
+}
+
+/// Tokens held by an account
+pub async fn account_nfts(
+    account: web::Path<String>,
+    query: web::Query<NftQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let tokens = NftOwnership::list_by_account(
+        pool.get_ref(),
+        &account,
+        query.chain_id,
+        query.module.as_deref(),
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(tokens))
+}
@@ -0,0 +1,45 @@
+use std::time::Duration;
+
+use actix_web::web::Bytes;
+use actix_web::{web, HttpResponse};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::{BroadcastStream, IntervalStream};
+use tokio_stream::StreamExt;
+
+use crate::subscriptions::{Notification, SubscriptionFilter};
+
+/// Comment sent on idle streams so proxies keep the connection open
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+///
+/// Server-sent events stream of new blocks, transactions and events matching
+/// the query filter. Each message is `event: <type>` with the row as `data`.
+pub async fn subscribe(
+    filter: web::Query<SubscriptionFilter>,
+    notifier: web::Data<broadcast::Sender<Notification>>,
+) -> HttpResponse {
+    let filter = filter.into_inner();
+    let notifications = BroadcastStream::new(notifier.subscribe()).filter_map(move |n| match n {
+        Ok(notification) if filter.matches(&notification) => {
+            let data = serde_json::to_string(&notification).ok()?;
+            Some(format!(
+                "event: {}\ndata: {}\n\n",
+                notification.kind(),
+                data
+            ))
+        }
+        Ok(_) => None,
+        // The subscriber is too slow, let it know it missed notifications
+        Err(e) => Some(format!("event: lagged\ndata: \"{}\"\n\n", e)),
+    });
+    let keep_alive = IntervalStream::new(tokio::time::interval(KEEP_ALIVE_INTERVAL))
+        .map(|_| ": keep-alive\n\n".to_string());
+    let stream = notifications
+        .merge(keep_alive)
+        .map(|message| Ok::<_, actix_web::Error>(Bytes::from(message)));
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(stream)
+}
@@ -0,0 +1,44 @@
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::entities::TransactionRecord;
+use crate::pagination::page_bounds;
+use crate::routes::ApiError;
+
+#[derive(Deserialize)]
+pub struct TransactionsQuery {
+    pub sender: Option<String>,
+    pub chain_id: Option<i16>,
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+}
+
+/// Latest transactions first, optionally filtered by sender and chain
+pub async fn list_transactions(
+    query: web::Query<TransactionsQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let (limit, cursor) = page_bounds(query.limit, query.cursor.as_deref())?;
+    let transactions = TransactionRecord::list(
+        pool.get_ref(),
+        query.sender.as_deref(),
+        query.chain_id,
+        limit,
+        cursor,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(transactions))
+}
+
+pub async fn transaction_by_request_key(
+    request_key: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let transaction = TransactionRecord::find_by_request_key(pool.get_ref(), &request_key)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Transaction {} not found", request_key)))?;
+
+    Ok(HttpResponse::Ok().json(transaction))
+}
@@ -0,0 +1,37 @@
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::entities::Transfer;
+use crate::pagination::page_bounds;
+use crate::routes::ApiError;
+
+#[derive(Deserialize)]
+pub struct TransfersQuery {
+    /// Matches both the sender and the receiver
+    pub account: Option<String>,
+    /// Qualified token module name, e.g. `coin`
+    pub module: Option<String>,
+    pub chain_id: Option<i16>,
+    pub limit: Option<i64>,
+    pub cursor: Option<String>,
+}
+
+/// Latest transfers first, optionally filtered by account, token and chain
+pub async fn list_transfers(
+    query: web::Query<TransfersQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let (limit, cursor) = page_bounds(query.limit, query.cursor.as_deref())?;
+    let transfers = Transfer::list(
+        pool.get_ref(),
+        query.account.as_deref(),
+        query.module.as_deref(),
+        query.chain_id,
+        limit,
+        cursor,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(transfers))
+}
@@ -1,14 +1,46 @@
 use std::collections::HashMap;
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use actix_web::dev::Server;
+use actix_web::{web, App, HttpServer};
+use anyhow::Context;
+use backoff::future::retry_notify;
+use futures_util::future::try_join_all;
+use serde::Serialize;
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
+use tokio::sync::broadcast;
+use tracing::{info, warn};
 
+use crate::archive::RawArchive;
+use crate::client::{NodeClient, NodeError};
 use crate::configuration::{ApplicationSettings, DatabaseSettings, Settings};
-use crate::entities::Block;
-use crate::ingest::{Ingest, QueryParams};
+use crate::entities::{Balance, Block};
+use crate::graphql::build_schema;
+use crate::ingest::{IndexedTotals, Ingest, QueryParams};
+use crate::leases::{ChainLeases, ShardedIndexers};
+use crate::network::{ChainGraph, Network};
+use crate::processors::BlockProcessor;
+use crate::routes::{
+    account, account_balances, account_guards, account_nfts, block_by_hash, block_by_height,
+    graphiql, graphql, health, list_block_gas, list_blocks, list_calls, list_chain_gas,
+    list_events, list_hourly_gas, list_modules, list_nfts, list_transactions, list_transfers,
+    module_history, nft, nft_events, nft_owners, prometheus_metrics, ready, subscribe,
+    transaction_by_request_key, ReadinessCheck,
+};
+use crate::subscriptions::{notification_channel, Notification};
+use crate::types::{CurrentCut, NodeInfo};
+use crate::verify::coin_details;
 
 pub struct Application {
     indexers: Vec<Ingest>,
+    api_server: Option<Server>,
+    finite: bool,
+    /// Leases of the chains shared with other instances, and their renewal
+    /// interval
+    leases: Option<(ChainLeases, Duration)>,
 }
 
 impl Application {
@@ -16,41 +48,645 @@ impl Application {
         let db_pool = get_connection_pool(&configuration.database);
         let processed_blocks = get_processed_blocks_logs(&db_pool).await?;
 
+        let client = node_client(&configuration.application)?;
+        let node_info = get_node_info(&client, &configuration.application.host).await?;
+        let chain_graph = validate_node_info(&configuration.application, &node_info)?;
+        let node_url = configuration
+            .application
+            .node_url()
+            .map_err(anyhow::Error::msg)?;
+
+        let notifier = notification_channel();
         let mut indexers = vec![];
         let chains_blocks_map =
-            get_min_height_for_chains(&processed_blocks, &configuration.application);
+            get_min_height_for_chains(&processed_blocks, &chain_graph, &configuration.application);
 
-        for chain_id in 0..configuration.application.number_of_chains {
+        let chain_ids = chain_graph
+            .chain_ids()
+            .into_iter()
+            .filter(|chain_id| configuration.application.indexes_chain(*chain_id))
+            .collect::<Vec<_>>();
+        if let Some(chains) = &configuration.application.chains {
+            if let Some(unknown) = chains.iter().find(|c| !chain_ids.contains(c)) {
+                anyhow::bail!(
+                    "Chain {} of `application.chains` is not on the network",
+                    unknown
+                );
+            }
+        }
+
+        for &chain_id in &chain_ids {
             let c = configuration.clone();
+            let chain_settings = c.application.chain_settings(chain_id);
             let mm = chains_blocks_map.get(&chain_id).unwrap();
-            let query_params = QueryParams::new(c.application.limit, *mm);
+            let query_params = QueryParams::new(chain_settings.limit, *mm);
             let pool = db_pool.clone();
 
-            let url = c.application.host.clone();
-            indexers.push(Ingest::new(chain_id, url, query_params.clone(), pool));
+            let url = node_url.clone();
+            let archive = RawArchive::from_settings(&c.archive, pool.clone());
+            indexers.push(
+                Ingest::new(
+                    chain_id,
+                    url,
+                    query_params.clone(),
+                    pool,
+                    archive,
+                    client.clone(),
+                )
+                .with_notifier(notifier.clone())
+                .with_block_gas_limit(c.application.block_gas_limit)
+                .with_max_height(chain_settings.max_height)
+                .with_concurrency(chain_settings.concurrency)
+                .with_finite(c.application.finite),
+            );
         }
 
-        Ok(Self { indexers })
+        let api_server = if configuration.api.enabled {
+            let address = format!("{}:{}", configuration.api.host, configuration.api.port);
+            let listener = TcpListener::bind(&address)
+                .with_context(|| format!("Failed to bind the api server to {}", address))?;
+            info!(%address, "Api listening");
+            let readiness = ReadinessCheck {
+                cut_url: format!("{}/cut", node_url),
+                chains: chain_ids
+                    .iter()
+                    .map(|&chain_id| {
+                        let max_height = configuration
+                            .application
+                            .chain_settings(chain_id)
+                            .max_height;
+                        (chain_id, max_height)
+                    })
+                    .collect(),
+                max_lag: configuration.api.ready_max_lag,
+                client: client.clone(),
+                timeout: READY_CUT_TIMEOUT,
+            };
+            Some(run(listener, db_pool.clone(), notifier, readiness)?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            indexers,
+            api_server,
+            finite: configuration.application.finite,
+            leases: configuration.leases.enabled.then(|| {
+                let settings = &configuration.leases;
+                (
+                    ChainLeases::new(
+                        db_pool.clone(),
+                        settings.instance_id(),
+                        Duration::from_secs(settings.ttl_seconds),
+                    ),
+                    Duration::from_secs(settings.heartbeat_seconds),
+                )
+            }),
+        })
     }
 
+    /// Run a processor on every chain after the built-in ones
+    pub fn with_processor(mut self, processor: Arc<dyn BlockProcessor>) -> Self {
+        self.indexers = self
+            .indexers
+            .into_iter()
+            .map(|indexer| indexer.with_processor(processor.clone()))
+            .collect();
+        self
+    }
+
+    ///
+    /// Run the indexers and the api server until a shutdown signal. In
+    /// finite mode, return once every indexer reached its end height,
+    /// logging the stored rows. With leases, only the chains held by the
+    /// instance are indexed.
     pub async fn run_indexers(self) -> Result<(), anyhow::Error> {
-        let mut workers = vec![];
-        for mut indexer in self.indexers {
-            workers.push(tokio::spawn(async move {
-                indexer.start().await.unwrap();
-            }));
+        let Self {
+            indexers,
+            api_server,
+            finite,
+            leases,
+        } = self;
+        let serves_api = api_server.is_some();
+        let api_server = async move {
+            match api_server {
+                Some(server) => server.await.context("Api server failed"),
+                None => std::future::pending().await,
+            }
+        };
+        tokio::pin!(api_server);
+
+        if let Some((leases, heartbeat)) = leases {
+            let mut sharded = ShardedIndexers::new(leases, heartbeat, indexers);
+            let result = tokio::select! {
+                _ = sharded.run() => Ok(()),
+                result = &mut api_server => result,
+                _ = shutdown_signal() => {
+                    info!("Shutting down");
+                    Ok(())
+                }
+            };
+            sharded.shutdown().await;
+            return result;
         }
-        for worker in workers {
-            worker.await?;
+
+        let started = Instant::now();
+        let indexers = try_join_all(indexers.into_iter().map(|mut indexer| async move {
+            let chain_id = indexer.chain_id;
+            let totals = tokio::spawn(async move { indexer.start().await })
+                .await?
+                .with_context(|| format!("Indexer of chain {} failed", chain_id))?;
+            Ok::<_, anyhow::Error>((chain_id, totals))
+        }));
+
+        // Indexers following the chain heads never finish on their own
+        tokio::select! {
+            result = indexers => {
+                log_totals(&result?, started.elapsed());
+                if finite || !serves_api {
+                    return Ok(());
+                }
+            }
+            result = &mut api_server => return result,
+            _ = shutdown_signal() => {
+                info!("Shutting down");
+                return Ok(());
+            }
+        }
+
+        // Keep serving the indexed blocks
+        tokio::select! {
+            result = api_server => result,
+            _ = shutdown_signal() => {
+                info!("Shutting down");
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Log the rows stored by each indexer and by all of them
+fn log_totals(chains: &[(i16, IndexedTotals)], elapsed: Duration) {
+    let mut all = IndexedTotals::default();
+    for (chain_id, totals) in chains {
+        info!(
+            chain_id,
+            blocks = totals.blocks,
+            transactions = totals.transactions,
+            events = totals.events,
+            "Chain indexed"
+        );
+        all += *totals;
+    }
+    info!(
+        chains = chains.len(),
+        blocks = all.blocks,
+        transactions = all.transactions,
+        events = all.events,
+        seconds = elapsed.as_secs_f64(),
+        "Indexing finished"
+    );
+}
+
+/// Resolves on Ctrl-C or SIGTERM
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(_) => std::future::pending().await,
         }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
 
-        Ok(())
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate => {}
     }
 }
 
+///
+/// Rebuild the derived tables of a chain for an inclusive height range from
+/// the raw archive, including the tables of the given processors. Does not
+/// talk to the node.
+pub async fn reindex(
+    configuration: Settings,
+    chain_id: i16,
+    min_height: u64,
+    max_height: u64,
+    processors: &[Arc<dyn BlockProcessor>],
+) -> Result<(), anyhow::Error> {
+    let indexer = chain_indexer(&configuration, chain_id, min_height, processors)?;
+    let replayed = indexer.reindex(min_height, max_height).await?;
+    info!(chain_id, blocks = replayed, "Reindexed");
+
+    Ok(())
+}
+
+///
+/// Index an inclusive height range of a chain from the node and exit,
+/// including the tables of the given processors. Blocks already indexed in
+/// the range are stored again.
+pub async fn backfill(
+    configuration: Settings,
+    chain_id: i16,
+    min_height: u64,
+    max_height: u64,
+    processors: &[Arc<dyn BlockProcessor>],
+) -> Result<(), anyhow::Error> {
+    let mut indexer = chain_indexer(&configuration, chain_id, min_height, processors)?;
+    let stored = indexer.backfill(min_height, max_height).await?;
+    info!(chain_id, blocks = stored, "Backfilled");
+
+    Ok(())
+}
+
+///
+/// Remove the blocks of a chain above `height` and every row derived from
+/// them, including the tables of the given processors. The indexer of the
+/// chain must not be running; it fetches the blocks again when restarted.
+pub async fn rollback(
+    configuration: Settings,
+    chain_id: i16,
+    height: u64,
+    processors: &[Arc<dyn BlockProcessor>],
+) -> Result<(), anyhow::Error> {
+    let indexer = chain_indexer(&configuration, chain_id, height, processors)?;
+    let removed = indexer.rollback(height).await?;
+    info!(chain_id, height, blocks = removed, "Rolled back");
+
+    Ok(())
+}
+
+/// Indexer of a single chain used by the one-off commands
+fn chain_indexer(
+    configuration: &Settings,
+    chain_id: i16,
+    min_height: u64,
+    processors: &[Arc<dyn BlockProcessor>],
+) -> Result<Ingest, anyhow::Error> {
+    let db_pool = get_connection_pool(&configuration.database);
+    let archive = RawArchive::from_settings(&configuration.archive, db_pool.clone());
+    let limit = configuration.application.chain_settings(chain_id).limit;
+    let query_params = QueryParams::new(limit, min_height);
+    let node_url = configuration
+        .application
+        .node_url()
+        .map_err(anyhow::Error::msg)?;
+    let client = node_client(&configuration.application)?;
+    Ok(processors.iter().fold(
+        Ingest::new(chain_id, node_url, query_params, db_pool, archive, client)
+            .with_block_gas_limit(configuration.application.block_gas_limit),
+        |indexer, processor| indexer.with_processor(processor.clone()),
+    ))
+}
+
+/// Apply the migrations of `migrations/` missing from the database
+pub async fn migrate(configuration: Settings) -> Result<(), anyhow::Error> {
+    let db_pool = get_connection_pool(&configuration.database);
+    sqlx::migrate!("./migrations")
+        .run(&db_pool)
+        .await
+        .context("Failed to migrate the database")?;
+    info!("Database migrated");
+
+    Ok(())
+}
+
+/// Indexing progress of a chain, reported by the `status` command
+#[derive(Serialize, Debug)]
+pub struct ChainStatus {
+    pub chain_id: i16,
+    /// `None` until the first batch of the chain is indexed
+    pub indexed_height: Option<i64>,
+    pub node_height: u64,
+    /// Blocks left to index up to the end height of the chain or the cut
+    pub lag: u64,
+}
+
+/// Indexed height of every indexed chain of the node cut, compared with the cut
+pub async fn status(configuration: Settings) -> Result<Vec<ChainStatus>, anyhow::Error> {
+    let db_pool = get_connection_pool(&configuration.database);
+    let processed = get_processed_blocks_logs(&db_pool)
+        .await
+        .context("Failed to query database")?;
+    let node_url = configuration
+        .application
+        .node_url()
+        .map_err(anyhow::Error::msg)?;
+    let client = node_client(&configuration.application)?;
+    let cut: CurrentCut = client
+        .send(client.get(format!("{}/cut", node_url)))
+        .await
+        .and_then(|resp| resp.json())
+        .context("Failed to fetch cut from chainweb node")?;
+
+    let application = &configuration.application;
+    let chains = cut
+        .hashes
+        .keys()
+        .filter(|chain_id| application.indexes_chain(**chain_id))
+        .map(|&chain_id| (chain_id, application.chain_settings(chain_id).max_height))
+        .collect();
+
+    Ok(chain_progress(&processed, &cut, &chains))
+}
+
+///
+/// Progress of the chains of the cut found in `chains`, which gives their
+/// end height, ordered by chain id. A chain with an end height is done once
+/// it is indexed up to it.
+pub fn chain_progress(
+    processed: &[Block],
+    cut: &CurrentCut,
+    chains: &HashMap<i16, Option<u64>>,
+) -> Vec<ChainStatus> {
+    let mut progress = cut
+        .hashes
+        .iter()
+        .filter_map(|(chain_id, head)| Some((*chain_id, head, chains.get(chain_id)?)))
+        .map(|(chain_id, head, max_height)| {
+            let target = max_height.map_or(head.height, |max| max.min(head.height));
+            let indexed_height = processed
+                .iter()
+                .find(|b| b.chain_id == chain_id)
+                .map(|b| b.height);
+            let lag = match indexed_height {
+                Some(height) => target.saturating_sub(height as u64),
+                None => target + 1,
+            };
+            ChainStatus {
+                chain_id,
+                indexed_height,
+                node_height: head.height,
+                lag,
+            }
+        })
+        .collect::<Vec<_>>();
+    progress.sort_by_key(|c| c.chain_id);
+    progress
+}
+
+/// Readiness probes should not hang on a slow node
+const READY_CUT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Accounts compared by `verify_balances` when none are given
+const VERIFY_SAMPLE_SIZE: i64 = 100;
+
+///
+/// Compare indexed KDA balances of a chain with `coin.details` evaluated by
+/// the node, as of the height the node evaluated it at. Without accounts,
+/// the largest indexed balances are checked. Accounts are skipped when the
+/// node is ahead of the last processed block of the chain. Returns the
+/// number of mismatching accounts.
+pub async fn verify_balances(
+    configuration: Settings,
+    chain_id: i16,
+    accounts: Vec<String>,
+) -> Result<usize, anyhow::Error> {
+    let db_pool = get_connection_pool(&configuration.database);
+    let version = configuration
+        .application
+        .chainweb_version()
+        .map_err(anyhow::Error::msg)?;
+    let node_url = configuration
+        .application
+        .node_url()
+        .map_err(anyhow::Error::msg)?;
+
+    let accounts = if accounts.is_empty() {
+        Balance::list_by_module(&db_pool, chain_id, "coin", VERIFY_SAMPLE_SIZE)
+            .await?
+            .into_iter()
+            .map(|b| b.account)
+            .collect()
+    } else {
+        accounts
+    };
+    let indexed_height = Block::last_processed_height(&db_pool, chain_id)
+        .await?
+        .with_context(|| format!("Chain {} is not indexed", chain_id))?;
+
+    let client = node_client(&configuration.application)?;
+    let mut mismatches = 0;
+    let mut skipped = 0;
+    for account in &accounts {
+        let node = coin_details(&client, &node_url, version, chain_id, account)
+            .await
+            .with_context(|| format!("Failed to fetch coin.details of {}", account))?;
+        let height = match node.height {
+            Some(height) if height <= indexed_height => height,
+            _ => {
+                skipped += 1;
+                warn!(
+                    %account,
+                    chain_id,
+                    indexed_height,
+                    node_height = ?node.height,
+                    "Node is ahead of the indexed chain, balance not compared"
+                );
+                continue;
+            }
+        };
+        let indexed = Balance::list(
+            &db_pool,
+            account,
+            Some(chain_id),
+            Some("coin"),
+            Some(height),
+        )
+        .await?
+        .pop()
+        .map(|b| b.balance)
+        .unwrap_or_default();
+        let node_balance = node.balance.unwrap_or_default();
+        if node_balance != indexed {
+            mismatches += 1;
+            warn!(
+                %account,
+                chain_id,
+                height,
+                indexed = %indexed,
+                node = %node_balance,
+                "Balance differs from the node"
+            );
+        }
+    }
+    info!(
+        chain_id,
+        balances = accounts.len(),
+        mismatches,
+        skipped,
+        "Verified balances"
+    );
+
+    Ok(mismatches)
+}
+
+pub fn run(
+    listener: TcpListener,
+    db_pool: PgPool,
+    notifier: broadcast::Sender<Notification>,
+    readiness: ReadinessCheck,
+) -> Result<Server, std::io::Error> {
+    let schema = web::Data::new(build_schema(db_pool.clone()));
+    let notifier = web::Data::new(notifier);
+    let readiness = web::Data::new(readiness);
+    let db_pool = web::Data::new(db_pool);
+    let server = HttpServer::new(move || {
+        App::new()
+            .route("/blocks", web::get().to(list_blocks))
+            .route("/blocks/{hash}", web::get().to(block_by_hash))
+            .route(
+                "/chains/{chain_id}/blocks/{height}",
+                web::get().to(block_by_height),
+            )
+            .route("/transactions", web::get().to(list_transactions))
+            .route(
+                "/transactions/{request_key}",
+                web::get().to(transaction_by_request_key),
+            )
+            .route("/events", web::get().to(list_events))
+            .route("/calls", web::get().to(list_calls))
+            .route("/transfers", web::get().to(list_transfers))
+            .route("/modules", web::get().to(list_modules))
+            .route("/modules/{qualified_name}", web::get().to(module_history))
+            .route("/nfts", web::get().to(list_nfts))
+            .route("/nfts/{token_id}", web::get().to(nft))
+            .route("/nfts/{token_id}/owners", web::get().to(nft_owners))
+            .route("/nfts/{token_id}/events", web::get().to(nft_events))
+            .route("/gas/blocks", web::get().to(list_block_gas))
+            .route("/gas/hourly", web::get().to(list_hourly_gas))
+            .route("/gas/chains", web::get().to(list_chain_gas))
+            .route("/accounts/{account}", web::get().to(account))
+            .route("/accounts/{account}/guards", web::get().to(account_guards))
+            .route(
+                "/accounts/{account}/balances",
+                web::get().to(account_balances),
+            )
+            .route("/accounts/{account}/nfts", web::get().to(account_nfts))
+            .route("/graphql", web::post().to(graphql))
+            .route("/graphql", web::get().to(graphiql))
+            .route("/subscribe", web::get().to(subscribe))
+            .route("/metrics", web::get().to(prometheus_metrics))
+            .route("/health", web::get().to(health))
+            .route("/ready", web::get().to(ready))
+            .app_data(db_pool.clone())
+            .app_data(schema.clone())
+            .app_data(notifier.clone())
+            .app_data(readiness.clone())
+    })
+    .listen(listener)?
+    .run();
+
+    Ok(server)
+}
+
+/// Client of the node with the limits of the configuration
+pub fn node_client(settings: &ApplicationSettings) -> Result<NodeClient, anyhow::Error> {
+    NodeClient::new(&settings.http, &settings.backoff).context("Failed to build the http client")
+}
+
+pub async fn get_node_info(client: &NodeClient, host: &str) -> Result<NodeInfo, anyhow::Error> {
+    let url = format!("{}/info", host.trim_end_matches('/'));
+    let budget = client.retry_budget();
+    let info = retry_notify(
+        client.backoff(),
+        || async {
+            let resp = client
+                .send(client.get(&url))
+                .await
+                .map_err(|e| e.into_backoff(&budget))?;
+            resp.json::<NodeInfo>().map_err(|e| e.into_backoff(&budget))
+        },
+        |e: NodeError, delay| warn!(error = %e, ?delay, "Retrying node info request"),
+    )
+    .await
+    .context("Failed to fetch info from chainweb node.")?;
+
+    Ok(info)
+}
+
+///
+/// Check the configuration against what the node reports and return the
+/// chain graph of the node, which includes upcoming graph transitions.
+fn validate_node_info(
+    settings: &ApplicationSettings,
+    info: &NodeInfo,
+) -> Result<ChainGraph, anyhow::Error> {
+    let version = settings.chainweb_version().map_err(anyhow::Error::msg)?;
+    if info.node_version != version {
+        anyhow::bail!(
+            "The node at {} runs {} but the configured network is {}",
+            settings.host,
+            info.node_version,
+            version
+        );
+    }
+
+    let configured_graph =
+        if settings.network != Network::Custom || !settings.graph_history.is_empty() {
+            Some(settings.chain_graph().map_err(anyhow::Error::msg)?)
+        } else {
+            None
+        };
+    let history_graph = ChainGraph::from_node_info(info).map_err(anyhow::Error::msg)?;
+    let node_graph = if info.node_genesis_heights.is_empty() {
+        if configured_graph
+            .as_ref()
+            .is_some_and(|g| *g != history_graph)
+        {
+            warn!(
+                %version,
+                "The chain graph reported by the node differs from the configured one, using the node's"
+            );
+        }
+        history_graph
+    } else {
+        // Recent nodes report when each chain starts, which must agree with
+        // the network
+        let genesis_graph = ChainGraph::from_genesis_heights(&info.node_genesis_heights)
+            .map_err(anyhow::Error::msg)?;
+        if let Some(configured_graph) = &configured_graph {
+            let mut chain_ids = configured_graph.chain_ids();
+            chain_ids.extend(genesis_graph.chain_ids());
+            chain_ids.sort_unstable();
+            chain_ids.dedup();
+            let height = |h: Option<u64>| h.map_or("none".to_string(), |h| h.to_string());
+            for chain_id in chain_ids {
+                let node = genesis_graph.genesis_height(chain_id);
+                let configured = configured_graph.genesis_height(chain_id);
+                if node != configured {
+                    anyhow::bail!(
+                        "The node at {} reports genesis height {} for chain {} but the configured network {} has {}",
+                        settings.host,
+                        height(node),
+                        chain_id,
+                        version,
+                        height(configured)
+                    );
+                }
+            }
+        }
+        genesis_graph
+    };
+    info!(
+        host = %settings.host,
+        version = %info.node_version,
+        chains = node_graph.chain_ids().len(),
+        "Connected to node"
+    );
+
+    Ok(node_graph)
+}
+
 pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
     PgPoolOptions::new()
-        .acquire_timeout(std::time::Duration::from_secs(2))
+        .acquire_timeout(Duration::from_secs(2))
         .connect_lazy_with(configuration.with_db())
 }
 
@@ -69,14 +705,13 @@ pub async fn get_processed_blocks_logs(pool: &PgPool) -> Result<Vec<Block>, sqlx
 
 fn get_min_height_for_chains(
     processed_blocks: &[Block],
+    chain_graph: &ChainGraph,
     settings: &ApplicationSettings,
 ) -> HashMap<i16, u64> {
     let mut chains_blocks_map: HashMap<i16, u64> = HashMap::new();
-    let min_height = settings.min_height;
-    // Min height for chains gt>9
-    let min_height2 = settings.chain_fork_height;
 
-    for chain_id in 0..settings.number_of_chains {
+    for chain_id in chain_graph.chain_ids() {
+        let min_height = settings.chain_settings(chain_id).min_height;
         match processed_blocks.iter().find(|b| b.chain_id == chain_id) {
             Some(v) => {
                 let next_height = v.height + 1;
@@ -84,12 +719,9 @@ fn get_min_height_for_chains(
                 // next_height as u64
             }
             None => {
-                let m = if chain_id > 9 && min_height < min_height2 {
-                    min_height2
-                } else {
-                    min_height
-                };
-                chains_blocks_map.insert(chain_id, m);
+                // Chains added by a graph transition start at the transition height
+                let genesis_height = chain_graph.genesis_height(chain_id).unwrap_or_default();
+                chains_blocks_map.insert(chain_id, min_height.max(genesis_height));
             }
         }
     }
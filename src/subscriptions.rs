@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::entities::{BlockRecord, EventRecord, TransactionRecord};
+
+/// Notifications buffered per subscriber before it starts lagging behind
+pub const NOTIFICATION_CAPACITY: usize = 4096;
+
+/// Rows broadcast to subscribers once their batch is committed
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "type", content = "data", rename_all = "lowercase")]
+pub enum Notification {
+    Block(BlockRecord),
+    Transaction(TransactionRecord),
+    Event(EventRecord),
+}
+
+impl Notification {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Notification::Block(_) => "block",
+            Notification::Transaction(_) => "transaction",
+            Notification::Event(_) => "event",
+        }
+    }
+
+    fn chain_id(&self) -> i16 {
+        match self {
+            Notification::Block(b) => b.chain_id,
+            Notification::Transaction(t) => t.chain_id,
+            Notification::Event(e) => e.chain_id,
+        }
+    }
+}
+
+pub fn notification_channel() -> broadcast::Sender<Notification> {
+    broadcast::channel(NOTIFICATION_CAPACITY).0
+}
+
+///
+/// Server side filter of a subscription. Every given field must match,
+/// `module`, `name` and `account` only match transactions and events.
+#[derive(Deserialize, Debug, Default)]
+pub struct SubscriptionFilter {
+    /// Comma separated notification types, e.g. `block,event`. All by default.
+    pub types: Option<String>,
+    pub chain_id: Option<i16>,
+    /// Qualified module name of events, e.g. `coin`
+    pub module: Option<String>,
+    /// Event name, e.g. `TRANSFER`
+    pub name: Option<String>,
+    /// Sender of transactions or any parameter of events
+    pub account: Option<String>,
+}
+
+impl SubscriptionFilter {
+    pub fn matches(&self, notification: &Notification) -> bool {
+        if let Some(types) = &self.types {
+            if !types.split(',').any(|t| t.trim() == notification.kind()) {
+                return false;
+            }
+        }
+        if matches!(self.chain_id, Some(c) if c != notification.chain_id()) {
+            return false;
+        }
+        match notification {
+            Notification::Block(_) => {
+                self.module.is_none() && self.name.is_none() && self.account.is_none()
+            }
+            Notification::Transaction(t) => {
+                self.module.is_none()
+                    && self.name.is_none()
+                    && field_matches(&self.account, &t.sender)
+            }
+            Notification::Event(e) => {
+                let account_matches = match &self.account {
+                    Some(account) => e
+                        .params
+                        .as_array()
+                        .into_iter()
+                        .flatten()
+                        .any(|p| p.as_str() == Some(account)),
+                    None => true,
+                };
+                field_matches(&self.module, &e.module)
+                    && field_matches(&self.name, &e.name)
+                    && account_matches
+            }
+        }
+    }
+}
+
+fn field_matches(filter: &Option<String>, value: &str) -> bool {
+    match filter {
+        Some(expected) => expected == value,
+        None => true,
+    }
+}
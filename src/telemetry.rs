@@ -0,0 +1,68 @@
+use anyhow::Context;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace, Resource};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter};
+
+use crate::configuration::{LogFormat, TelemetrySettings};
+
+/// Filter used when `RUST_LOG` is not set, without a log of every query
+const DEFAULT_LOG_FILTER: &str = "info,sqlx::query=warn";
+
+///
+/// Install the global subscriber: logs on stderr in the configured format,
+/// filtered by `RUST_LOG`, and spans exported to an OpenTelemetry collector
+/// when `telemetry.otlp_endpoint` is set.
+pub fn init_telemetry(settings: &TelemetrySettings) -> Result<(), anyhow::Error> {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER));
+    let json = matches!(settings.format, LogFormat::Json);
+
+    let otel = match &settings.otlp_endpoint {
+        Some(endpoint) => {
+            let tracer = opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint),
+                )
+                .with_trace_config(trace::config().with_resource(Resource::new(vec![
+                    KeyValue::new("service.name", settings.service_name.clone()),
+                ])))
+                .install_batch(runtime::Tokio)
+                .context("Failed to install the OpenTelemetry exporter")?;
+            Some(tracing_opentelemetry::layer().with_tracer(tracer))
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        // Spans are logged when closed, with their timings and recorded counts
+        .with(json.then(|| {
+            fmt::layer()
+                .with_writer(std::io::stderr)
+                .json()
+                .with_current_span(true)
+                .with_span_events(FmtSpan::CLOSE)
+        }))
+        .with((!json).then(|| {
+            fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_span_events(FmtSpan::CLOSE)
+        }))
+        .with(otel)
+        .try_init()
+        .context("Failed to install the tracing subscriber")?;
+
+    Ok(())
+}
+
+/// Export the spans still buffered before the process exits
+pub fn shutdown_telemetry() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
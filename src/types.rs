@@ -1,12 +1,19 @@
 use std::collections::HashMap;
 
 use serde::Deserialize;
+use serde_json::value::RawValue;
 
 #[derive(Deserialize, Debug)]
 pub struct BlockHeaderItems {
     pub items: Vec<BlockHeader>,
 }
 
+/// Page of items kept as the exact json returned by the node
+#[derive(Deserialize, Debug)]
+pub struct RawItems {
+    pub items: Vec<Box<RawValue>>,
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct NewHead {
@@ -40,6 +47,25 @@ pub struct HashHeight {
     pub height: u64,
 }
 
+/// `[[chain, [adjacent chains]]]` of a chain graph
+pub type ChainAdjacencies = Vec<(i16, Vec<i16>)>;
+
+/// Response of the node `/info` endpoint
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeInfo {
+    pub node_version: String,
+    pub node_api_version: String,
+    pub node_package_version: Option<String>,
+    pub node_number_of_chains: u16,
+    pub node_chains: Vec<String>,
+    /// Latest transition first
+    pub node_graph_history: Vec<(u64, ChainAdjacencies)>,
+    /// Only returned by recent node versions
+    #[serde(default)]
+    pub node_genesis_heights: Vec<(String, u64)>,
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct CurrentCut {
     pub hashes: HashMap<i16, HashHeight>,
@@ -58,6 +84,7 @@ pub struct BlockPayload {
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct TransactionWithCmdSigs {
+    pub hash: String,
     pub cmd: String,
     pub sigs: Vec<Sig>,
 }
@@ -149,6 +176,16 @@ pub struct Module {
     pub namespace: serde_json::Value,
 }
 
+impl Module {
+    /// Module name prefixed with its namespace, e.g. `free.my-module`
+    pub fn qualified_name(&self) -> String {
+        match self.namespace.as_str() {
+            Some(namespace) => format!("{}.{}", namespace, self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
 #[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct OutputResult {
@@ -1,4 +1,8 @@
+use std::str::FromStr;
+
+use bigdecimal::BigDecimal;
 use reqwest::header::HeaderMap;
+use serde_json::Value;
 
 use crate::ingest::QueryParams;
 
@@ -25,8 +29,8 @@ pub fn req_header_content_type_with_accept() -> HeaderMap {
     map
 }
 
-pub fn decode_from_base64_url(input: &str) -> Vec<u8> {
-    base64_url::decode(input).unwrap()
+pub fn decode_from_base64_url(input: &str) -> Result<Vec<u8>, base64_url::base64::DecodeError> {
+    base64_url::decode(input)
 }
 
 // Create url endpoint with query parameters
@@ -39,3 +43,55 @@ pub fn format_endpoint_with_query_params(params: &QueryParams) -> String {
 
     endpoint
 }
+
+/// Parse a Pact decimal which is encoded either as a json number or as
+/// `{"decimal": "1.0"}` / `{"int": 1}`
+pub fn parse_pact_decimal(value: &Value) -> Option<BigDecimal> {
+    match value {
+        Value::Number(n) => BigDecimal::from_str(&n.to_string()).ok(),
+        Value::String(s) => BigDecimal::from_str(s).ok(),
+        Value::Object(o) => o
+            .get("decimal")
+            .or_else(|| o.get("int"))
+            .and_then(parse_pact_decimal),
+        _ => None,
+    }
+}
+
+/// Exact decimal of the shortest representation of a float, e.g. a gas price
+pub fn decimal_from_f64(value: f64) -> BigDecimal {
+    BigDecimal::from_str(&value.to_string()).unwrap_or_default()
+}
+
+/// Nearest-rank percentile of sorted values, `p` between 0 and 1
+pub fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentile_uses_the_nearest_rank() {
+        let values = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0];
+        assert_eq!(percentile(&values, 0.5), Some(5.0));
+        assert_eq!(percentile(&values, 0.9), Some(9.0));
+        assert_eq!(percentile(&values, 0.91), Some(10.0));
+        assert_eq!(percentile(&values, 1.0), Some(10.0));
+        assert_eq!(percentile(&values, 0.0), Some(1.0));
+    }
+
+    #[test]
+    fn percentile_of_few_values() {
+        assert_eq!(percentile(&[], 0.5), None);
+        assert_eq!(percentile(&[3.0], 0.5), Some(3.0));
+        assert_eq!(percentile(&[3.0], 0.9), Some(3.0));
+        assert_eq!(percentile(&[1.0, 2.0], 0.5), Some(1.0));
+        assert_eq!(percentile(&[1.0, 2.0], 0.9), Some(2.0));
+    }
+}
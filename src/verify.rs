@@ -0,0 +1,81 @@
+use anyhow::Context;
+use bigdecimal::BigDecimal;
+use blake2::digest::consts::U32;
+use blake2::{Blake2b, Digest};
+use serde_json::{json, Value};
+
+use crate::client::NodeClient;
+use crate::utils::{parse_pact_decimal, req_header_content_type};
+
+/// Balance of an account according to the node
+#[derive(Debug)]
+pub struct NodeBalance {
+    /// `None` when the account does not exist on the chain
+    pub balance: Option<BigDecimal>,
+    /// Height the node evaluated `coin.details` at, when it reports it
+    pub height: Option<i64>,
+}
+
+///
+/// Unsigned command evaluated with the node `/local` endpoint. Local calls
+/// are not submitted to the mempool, so no signature or gas payer is needed.
+fn local_command(version: &str, chain_id: i16, code: &str) -> Value {
+    let cmd = json!({
+        "networkId": version,
+        "payload": { "exec": { "code": code, "data": {} } },
+        "signers": [],
+        "meta": {
+            "creationTime": chrono::Utc::now().timestamp() - 60,
+            "ttl": 600,
+            "gasLimit": 10000,
+            "chainId": chain_id.to_string(),
+            "gasPrice": 1e-8,
+            "sender": ""
+        },
+        "nonce": chrono::Utc::now().to_rfc3339()
+    })
+    .to_string();
+    let hash = base64_url::encode(&Blake2b::<U32>::digest(cmd.as_bytes()));
+
+    json!({ "hash": hash, "sigs": [], "cmd": cmd })
+}
+
+///
+/// Evaluate `(coin.details account)` on the node. `node_url` is the chainweb
+/// version root, e.g. `https://api.chainweb.com/chainweb/0.0/mainnet01`.
+pub async fn coin_details(
+    client: &NodeClient,
+    node_url: &str,
+    version: &str,
+    chain_id: i16,
+    account: &str,
+) -> Result<NodeBalance, anyhow::Error> {
+    let url = format!("{}/chain/{}/pact/api/v1/local", node_url, chain_id);
+    let code = format!("(coin.details {})", Value::from(account));
+    let request = client
+        .post(&url)
+        .headers(req_header_content_type())
+        .json(&local_command(version, chain_id, &code));
+    let body: Value = client.send(request).await?.json()?;
+
+    let height = body["metaData"]["blockHeight"].as_i64();
+    let result = &body["result"];
+    match result["status"].as_str() {
+        Some("success") => {
+            let balance = parse_pact_decimal(&result["data"]["balance"])
+                .with_context(|| format!("Invalid coin.details result: {}", result["data"]))?;
+            Ok(NodeBalance {
+                balance: Some(balance),
+                height,
+            })
+        }
+        // coin.details fails reading the row of accounts that do not exist
+        Some("failure") if result["error"].to_string().contains("row not found") => {
+            Ok(NodeBalance {
+                balance: None,
+                height,
+            })
+        }
+        _ => anyhow::bail!("Unexpected local result: {}", result),
+    }
+}